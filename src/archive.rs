@@ -0,0 +1,355 @@
+//! Reading the text files inside zip and tar archives used as corpus files.
+//!
+//! Archives are recognized by their magic bytes. Regular file entries starting without NUL bytes
+//! are text, in any encoding, and anything else (images, binaries, ...) is skipped. Zip entries
+//! may be stored or deflated, which covers what `zip` and most other tools write; deflated ones
+//! are decompressed by `gzip`, like compressed corpus files.
+
+use std::io::{self, Read};
+
+use crate::compression::{self, Compression};
+
+/// Bytes at the start of an entry checked for NULs to spot binary files.
+const BINARY_CHECK_LENGTH: usize = 8192;
+
+/// How many bytes from the start of a file `ArchiveKind::detect` needs.
+pub const MAGIC_LENGTH: usize = 262;
+
+/// The most an archive's zip entries can decompress to in total, since they're held in memory.
+const MAX_ZIP_OUTPUT: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+}
+
+impl ArchiveKind {
+    /// The kind of archive `bytes` are, if they're one.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if bytes.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// The text entries of the `kind` archive `bytes`, in archive order.
+pub fn text_entries(kind: ArchiveKind, bytes: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let entries = match kind {
+        ArchiveKind::Zip => zip_entries(bytes)?,
+        ArchiveKind::Tar => tar_entries(bytes)?,
+    };
+
+    Ok(entries.into_iter().filter(|entry| is_text(entry)).collect())
+}
+
+fn is_text(entry: &[u8]) -> bool {
+    !entry[..entry.len().min(BINARY_CHECK_LENGTH)].contains(&0)
+}
+
+fn corrupt(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Corrupt archive: {}.", reason),
+    )
+}
+
+/// The contents of the regular files in a tar archive.
+fn tar_entries(bytes: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    const BLOCK: usize = 512;
+    let mut entries = vec![];
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + BLOCK) {
+        // The archive ends with zero blocks.
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = tar_number(&header[124..136]).ok_or_else(|| corrupt("invalid tar size"))?;
+        let start = offset + BLOCK;
+        let end = usize::try_from(size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| corrupt("tar entry past the end"))?;
+        // Regular files; the rest are directories, links and extended headers.
+        if matches!(header[156], b'0' | 0) {
+            entries.push(bytes[start..end].to_vec());
+        }
+        offset = end.div_ceil(BLOCK) * BLOCK;
+    }
+
+    Ok(entries)
+}
+
+/// A tar header number: octal text, or big-endian binary when the top bit is set.
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        let value = field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |value, &b| {
+                value.checked_mul(256).map(|value| value | u64::from(b))
+            });
+        return value;
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    match text {
+        "" => Some(0),
+        text => u64::from_str_radix(text, 8).ok(),
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    let field = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([field[0], field[1]]) as usize)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    let field = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize)
+}
+
+/// The contents of the files in a zip archive, from its central directory. Encrypted entries
+/// and ones compressed with anything but deflate are skipped.
+fn zip_entries(bytes: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    // The end of central directory record is last, followed by a comment of up to 64 KiB.
+    let search_start = bytes.len().saturating_sub(22 + 0xffff);
+    let end_record = (search_start..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| bytes[offset..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| corrupt("no zip central directory"))?;
+    let count = u16_at(bytes, end_record + 10).unwrap();
+    let mut offset = u32_at(bytes, end_record + 16).unwrap();
+    let mut entries = vec![];
+    let mut total_size = 0usize;
+    for _ in 0..count {
+        if bytes.get(offset..offset + 4) != Some(b"PK\x01\x02") {
+            return Err(corrupt("invalid zip directory entry"));
+        }
+        let field = |at: usize, width: usize| match width {
+            2 => u16_at(bytes, offset + at),
+            _ => u32_at(bytes, offset + at),
+        };
+        let invalid = || corrupt("invalid zip directory entry");
+        let flags = field(8, 2).ok_or_else(invalid)?;
+        let method = field(10, 2).ok_or_else(invalid)?;
+        let crc = field(16, 4).ok_or_else(invalid)?;
+        let compressed_size = field(20, 4).ok_or_else(invalid)?;
+        let size = field(24, 4).ok_or_else(invalid)?;
+        let name_length = field(28, 2).ok_or_else(invalid)?;
+        let extra_length = field(30, 2).ok_or_else(invalid)?;
+        let comment_length = field(32, 2).ok_or_else(invalid)?;
+        let local_header = field(42, 4).ok_or_else(invalid)?;
+        let name = bytes
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or_else(invalid)?;
+        offset += 46 + name_length + extra_length + comment_length;
+        if [compressed_size, size, local_header].contains(&0xffff_ffff) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Zip64 archives aren't supported.",
+            ));
+        }
+        let encrypted = flags & 1 != 0;
+        if name.ends_with(b"/") || encrypted || !matches!(method, 0 | 8) {
+            continue;
+        }
+        if bytes.get(local_header..local_header + 4) != Some(b"PK\x03\x04") {
+            return Err(corrupt("invalid zip file header"));
+        }
+        let data_start = local_header
+            + 30
+            + u16_at(bytes, local_header + 26).ok_or_else(invalid)?
+            + u16_at(bytes, local_header + 28).ok_or_else(invalid)?;
+        let data = bytes
+            .get(data_start..data_start + compressed_size)
+            .ok_or_else(|| corrupt("zip entry past the end"))?;
+        total_size = total_size.saturating_add(size);
+        if total_size > MAX_ZIP_OUTPUT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Zip archives can't hold more than {} MiB of files.",
+                    MAX_ZIP_OUTPUT >> 20
+                ),
+            ));
+        }
+        entries.push(match method {
+            0 => data.to_vec(),
+            _ => inflate(data, crc as u32, size)?,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Decompress the raw deflate `data` of a zip entry expected to hold `size` bytes with checksum
+/// `crc`.
+///
+/// Zip entries record everything a gzip trailer holds, so the data is piped through `gzip` as a
+/// gzip member, which also checks it. Reading stops after `size` bytes whatever the data holds.
+fn inflate(data: &[u8], crc: u32, size: usize) -> io::Result<Vec<u8>> {
+    // Deflate, no flags, no timestamp, unknown OS.
+    const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    let trailer = [crc.to_le_bytes(), (size as u32).to_le_bytes()].concat();
+    let mut member = GZIP_HEADER.chain(data).chain(&trailer[..]);
+    let entry = compression::decompress_reader(Compression::Gzip, &mut member, |output| {
+        // Deflate expands by at most about 1000 times, but the size could still be a lie.
+        let mut entry = Vec::with_capacity(size.min(data.len().saturating_mul(4)));
+        output.take(size as u64 + 1).read_to_end(&mut entry)?;
+        Ok(entry)
+    })?;
+    if entry.len() != size {
+        return Err(corrupt("zip entry size mismatch"));
+    }
+
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello corpus\n" deflated, and its CRC-32.
+    const DEFLATED: [u8; 15] = [
+        0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x48, 0xce, 0x2f, 0x2a, 0x28, 0x2d, 0xe6, 0x02, 0x00,
+    ];
+    const DEFLATED_CRC: u32 = 0x1de0_09df;
+
+    fn joined(bytes: &[u8]) -> Option<io::Result<Vec<u8>>> {
+        let kind = ArchiveKind::detect(bytes)?;
+        Some(text_entries(kind, bytes).map(|entries| entries.join(&b'\n')))
+    }
+
+    /// A zip archive of stored `entries`.
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|&(name, data)| (name, 0, 0, data, data.len()))
+            .collect();
+        zip_with(&entries)
+    }
+
+    /// A zip archive of `(name, method, crc, data, size)` entries.
+    fn zip_with(entries: &[(&str, u16, u32, &[u8], usize)]) -> Vec<u8> {
+        let mut archive = vec![];
+        let mut directory = vec![];
+        for &(name, method, crc, data, size) in entries {
+            let offset = archive.len() as u32;
+            let fields = [
+                &method.to_le_bytes()[..],
+                &[0; 4],
+                &crc.to_le_bytes(),
+                &(data.len() as u32).to_le_bytes(),
+                &(size as u32).to_le_bytes(),
+                &(name.len() as u16).to_le_bytes(),
+            ]
+            .concat();
+            archive.extend(b"PK\x03\x04\x0a\x00\x00\x00");
+            archive.extend(&fields);
+            archive.extend([0, 0]);
+            archive.extend(name.as_bytes());
+            archive.extend(data);
+            directory.extend(b"PK\x01\x02\x1e\x03\x0a\x00\x00\x00");
+            directory.extend(&fields);
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = archive.len() as u32;
+        let count = (entries.len() as u16).to_le_bytes();
+        archive.extend(&directory);
+        archive.extend(b"PK\x05\x06\x00\x00\x00\x00");
+        archive.extend([count, count].concat());
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend([0, 0]);
+
+        archive
+    }
+
+    /// A tar archive of regular file `entries`.
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = vec![];
+        for (name, data) in entries {
+            let mut header = [0; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            archive.extend(header);
+            archive.extend(*data);
+            archive.resize(archive.len().div_ceil(512) * 512, 0);
+        }
+        archive.extend([0; 1024]);
+
+        archive
+    }
+
+    #[test]
+    fn test_zip_deflated() {
+        // Inflating needs gzip installed.
+        if std::process::Command::new("gzip")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let entry = |crc, size| ("a.txt", 8, crc, &DEFLATED[..], size);
+        let archive = zip_with(&[entry(DEFLATED_CRC, 13)]);
+        assert_eq!(joined(&archive).unwrap().unwrap(), b"hello corpus\n");
+        // Wrong checksums and sizes are caught, and big sizes aren't trusted.
+        assert!(joined(&zip_with(&[entry(0, 13)])).unwrap().is_err());
+        assert!(joined(&zip_with(&[entry(DEFLATED_CRC, 12)]))
+            .unwrap()
+            .is_err());
+        assert!(joined(&zip_with(&[entry(DEFLATED_CRC, 1 << 20)]))
+            .unwrap()
+            .is_err());
+        let bomb = zip_with(&[entry(DEFLATED_CRC, 1100 << 20)]);
+        let error = joined(&bomb).unwrap().unwrap_err();
+        assert!(error.to_string().contains("more than 1024 MiB"));
+    }
+
+    #[test]
+    fn test_zip() {
+        let archive = zip(&[
+            ("a.txt", b"first"),
+            ("dir/", b""),
+            ("image.png", b"\x89PNG\x00\x01"),
+            ("b.txt", "sécond".as_bytes()),
+        ]);
+        assert_eq!(ArchiveKind::detect(&archive), Some(ArchiveKind::Zip));
+        assert_eq!(
+            joined(&archive).unwrap().unwrap(),
+            "first\nsécond".as_bytes()
+        );
+        assert!(joined(&zip(&[("a.txt", b"first")])[..30]).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_tar() {
+        let archive = tar(&[
+            ("a.txt", b"first"),
+            ("binary", b"\x00\x01"),
+            ("b.txt", b"second"),
+        ]);
+        assert_eq!(ArchiveKind::detect(&archive), Some(ArchiveKind::Tar));
+        assert_eq!(joined(&archive).unwrap().unwrap(), b"first\nsecond");
+        assert!(joined(&archive[..514]).unwrap().is_err());
+        assert!(joined(b"plain text").is_none());
+    }
+
+    #[test]
+    fn test_tar_number() {
+        assert_eq!(tar_number(b"00000000017\0"), Some(15));
+        assert_eq!(tar_number(b"\0\0\0\0\0\0\0\0\0\0\0\0"), Some(0));
+        assert_eq!(tar_number(b"\x80\0\0\0\0\0\0\0\0\0\x01\x00"), Some(256));
+        assert_eq!(tar_number(b"0000000008\0\0"), None);
+    }
+}
@@ -0,0 +1,117 @@
+//! Substrings common in an attacker's corpus, like a list of leaked passwords.
+//!
+//! Guessing attacks built from such lists try their most frequent fragments first, so avoiding
+//! those fragments keeps passphrases away from the first guesses of dictionary-hybrid attacks.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+/// The most frequent substrings of one length in an attacker corpus, lowercased.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommonSubstrings {
+    length: usize,
+    substrings: HashSet<String>,
+}
+
+impl CommonSubstrings {
+    /// The `top` most frequent substrings of `length` characters in `lines`, counting each
+    /// substring once per line. Whitespace is ignored, and ties are broken alphabetically.
+    ///
+    /// Panics if `length` is 0.
+    pub fn from_lines<'a>(lines: impl Iterator<Item = &'a str>, length: usize, top: usize) -> Self {
+        assert!(length > 0, "substring length must be positive");
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for line in lines {
+            let line = normalize(line);
+            let mut seen = HashSet::new();
+            for substring in windows(&line, length) {
+                if seen.insert(substring) {
+                    *counts.entry(substring.to_string()).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(top);
+
+        Self {
+            length,
+            substrings: counts.into_iter().map(|(substring, _)| substring).collect(),
+        }
+    }
+
+    /// Read an attacker corpus with one entry (e.g. password) per line. Lines which aren't valid
+    /// UTF-8 are decoded lossily, since leaked lists often mix encodings.
+    pub fn read(mut reader: impl io::Read, length: usize, top: usize) -> io::Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let text = String::from_utf8_lossy(&bytes);
+
+        Ok(Self::from_lines(text.lines(), length, top))
+    }
+
+    /// Add the substrings of `other`, which must have the same length.
+    pub fn merge(&mut self, other: CommonSubstrings) {
+        assert_eq!(self.length, other.length, "substring lengths must match");
+        self.substrings.extend(other.substrings);
+    }
+
+    pub fn len(&self) -> usize {
+        self.substrings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.substrings.is_empty()
+    }
+
+    /// The first common substring in `text`, ignoring case and whitespace.
+    pub fn find(&self, text: &str) -> Option<&str> {
+        let text = normalize(text);
+        let found =
+            windows(&text, self.length).find_map(|substring| self.substrings.get(substring));
+
+        found.map(String::as_str)
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Every substring of `text` `length` characters long.
+fn windows(text: &str, length: usize) -> impl Iterator<Item = &str> {
+    let starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let count = starts.len();
+    (0..(count + 1).saturating_sub(length)).map(move |i| {
+        let end = starts.get(i + length).copied().unwrap_or(text.len());
+        &text[starts[i]..end]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_substrings() {
+        let passwords = "password1\nPassword!\nletmein\nmonkey\npassport\nmonkey123\n";
+        let common = CommonSubstrings::read(passwords.as_bytes(), 4, 3).unwrap();
+        // "pass" is in 3 lines, and then "assw" and "monk" come first of those in 2.
+        assert_eq!(common.len(), 3);
+        assert_eq!(common.find("Compass Party"), Some("pass"));
+        assert_eq!(common.find("mon key"), Some("monk"));
+        assert_eq!(common.find("massword"), Some("assw"));
+        assert_eq!(common.find("word"), None);
+        assert_eq!(common.find("pas"), None);
+    }
+
+    #[test]
+    fn test_windows() {
+        assert_eq!(windows("abcd", 2).collect::<Vec<_>>(), ["ab", "bc", "cd"]);
+        assert_eq!(windows("éàü", 2).collect::<Vec<_>>(), ["éà", "àü"]);
+        assert_eq!(windows("ab", 3).count(), 0);
+    }
+}
@@ -0,0 +1,77 @@
+//! Commitments to the randomness behind passphrases, for audit trails.
+//!
+//! In audit mode each passphrase is generated from its own ChaCha20 stream with a fresh 256-bit
+//! seed. Its record commits to the seed and the chain's fingerprint with a SHA-256 hash, which
+//! can be logged without revealing anything about the passphrase. Whoever holds the seed can
+//! later show the record is genuine, and regenerate the passphrase to attest how it was made.
+
+use crate::sha256;
+
+/// A record of how one passphrase was generated, safe to log once the seed is removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// The fingerprint of the chain the passphrase was generated from.
+    pub model: String,
+    /// The seed of the passphrase's random stream. It determines the passphrase, so keep it as
+    /// secret as the passphrase itself.
+    pub seed: [u8; 32],
+    /// The hex SHA-256 commitment to `model` and `seed`.
+    pub commitment: String,
+}
+
+impl AuditRecord {
+    pub fn new(model: &str, seed: [u8; 32]) -> Self {
+        Self {
+            model: model.to_string(),
+            seed,
+            commitment: commitment(model, &seed),
+        }
+    }
+}
+
+/// The commitment to generating from the chain with fingerprint `model` using `seed`.
+pub fn commitment(model: &str, seed: &[u8; 32]) -> String {
+    let mut hasher = sha256::Sha256::new();
+    hasher.update(b"markovpass audit v1\0");
+    hasher.update(model.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(seed);
+    sha256::to_hex(&hasher.finalize())
+}
+
+/// Parse a seed written as 64 hex digits.
+pub fn parse_seed(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut seed = [0; 32];
+    for (byte, digits) in seed.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+
+    Some(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commitment() {
+        let record = AuditRecord::new("abc", [7; 32]);
+        assert_eq!(record.commitment, commitment("abc", &[7; 32]));
+        assert_ne!(record.commitment, commitment("abd", &[7; 32]));
+        assert_ne!(record.commitment, commitment("abc", &[8; 32]));
+        assert_eq!(record.commitment.len(), 64);
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        let hex = sha256::to_hex(&[0xa5; 32]);
+        assert_eq!(parse_seed(&hex), Some([0xa5; 32]));
+        assert_eq!(parse_seed(&hex.to_uppercase()), Some([0xa5; 32]));
+        assert_eq!(parse_seed(&hex[2..]), None);
+        assert_eq!(parse_seed(&format!("{}zz", &hex[2..])), None);
+        assert_eq!(parse_seed(&format!("{}+1", &hex[3..])), None);
+    }
+}
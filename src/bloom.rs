@@ -0,0 +1,104 @@
+//! A bloom filter for fast, compact word membership checks.
+//!
+//! A filter never misses a word that was inserted, but may report a word it doesn't contain,
+//! roughly as often as the false positive rate it was sized for.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u32,
+}
+
+impl BloomFilter {
+    /// An empty filter sized to hold `capacity` words with about `false_positive_rate` chance of
+    /// reporting any other word.
+    ///
+    /// Panics unless `false_positive_rate` is strictly between 0 and 1.
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+        let ln2 = std::f64::consts::LN_2;
+        let capacity = capacity.max(1) as f64;
+        let bit_count = (-capacity * false_positive_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let bit_count = bit_count.max(64);
+        let hash_count = ((bit_count as f64 / capacity) * ln2).round().max(1.0) as u32;
+
+        Self {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count,
+        }
+    }
+
+    /// A filter holding `words`, sized for them.
+    pub fn from_words<'a>(words: impl ExactSizeIterator<Item = &'a str>, rate: f64) -> Self {
+        let mut filter = Self::new(words.len(), rate);
+        for word in words {
+            filter.insert(word);
+        }
+
+        filter
+    }
+
+    pub fn insert(&mut self, word: &str) {
+        for bit in self.bit_indices(word) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Whether `word` may have been inserted. False means it certainly wasn't.
+    pub fn contains(&self, word: &str) -> bool {
+        self.bit_indices(word)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The size of the filter's bit array in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// The bits for `word`, by double hashing with two independent hashes.
+    fn bit_indices(&self, word: &str) -> impl Iterator<Item = u64> {
+        let hash = |seed: u64| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            word.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (first, second) = (hash(0), hash(1) | 1);
+        let bit_count = self.bit_count;
+        (0..self.hash_count as u64)
+            .map(move |i| first.wrapping_add(i.wrapping_mul(second)) % bit_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let words: Vec<String> = (0..1000).map(|i| format!("word{}", i)).collect();
+        let filter = BloomFilter::from_words(words.iter().map(String::as_str), 0.01);
+        assert!(words.iter().all(|word| filter.contains(word)));
+        let false_positives = (0..10000)
+            .filter(|i| filter.contains(&format!("other{}", i)))
+            .count();
+        assert!(false_positives < 300, "{}", false_positives);
+        // About 9.6 bits per word for a 1% false positive rate.
+        assert!(filter.byte_size() < 1300, "{}", filter.byte_size());
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = BloomFilter::new(0, 0.01);
+        assert!(!filter.contains(""));
+        assert!(!filter.contains("word"));
+    }
+}
@@ -0,0 +1,569 @@
+//! The 'corpus' subcommands, and choosing, verifying and deduplicating corpus files.
+
+use crate::cli::data_dir::{
+    data_dir_files, data_dirs, get_corpus_files, system_dictionary, user_data_dir, DataDirOptions,
+    IGNORE_FILE_NAME,
+};
+use crate::cli::{format_size, progress, tr, tr_format, warn, CliResult};
+use clap::Subcommand;
+
+pub fn corpus_fetch(name: &str) -> CliResult {
+    // clap only accepts known set names.
+    let set = markovpass::download::corpus_set(name).unwrap();
+    let data_dir = user_data_dir()?;
+    std::fs::create_dir_all(&data_dir)?;
+    progress(
+        "Fetching {} into {}",
+        &[&set.description, &data_dir.display()],
+    );
+    for text in set.texts {
+        let path = data_dir.join(text.file_name);
+        if path.is_file() {
+            match markovpass::sha256::file_hex_digest(&path)? == text.sha256 {
+                true => progress("Already have {}", &[&text.file_name]),
+                false => warn(
+                    "Keeping {}, which differs from the curated copy.",
+                    &[&path.display()],
+                ),
+            }
+            continue;
+        }
+        progress("Downloading {}...", &[&text.file_name]);
+        markovpass::download::download_text(text, &path)?;
+    }
+
+    Ok(())
+}
+
+pub fn corpus_list(options: &DataDirOptions) -> CliResult {
+    let mut in_use = false;
+    for dir in data_dirs() {
+        let files = data_dir_files(&dir, options)?;
+        let status = match (files.is_empty(), in_use) {
+            (true, _) => "empty",
+            (false, false) => "in use",
+            (false, true) => "unused",
+        };
+        in_use |= !files.is_empty();
+        println!("{} ({})", dir.display(), status);
+        for path in files {
+            let size = std::fs::metadata(&path)?.len();
+            let name = path.strip_prefix(&dir).unwrap_or(&path);
+            println!("    {:>9}  {}", format_size(size), name.display());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn corpus_add(files: &[std::path::PathBuf], force: bool) -> CliResult {
+    let data_dir = user_data_dir()?;
+    std::fs::create_dir_all(&data_dir)?;
+    for file in files {
+        let name = file
+            .file_name()
+            .ok_or_else(|| tr_format("{} isn't a file.", &[&file.display()]))?;
+        let destination = data_dir.join(name);
+        if destination.exists() && !force {
+            return Err(tr_format(
+                "{} is already in {}. Use --force to replace it.",
+                &[&name.to_string_lossy(), &data_dir.display()],
+            )
+            .into());
+        }
+        std::fs::copy(file, &destination)?;
+        progress("Added {}", &[&destination.display()]);
+    }
+
+    Ok(())
+}
+
+pub fn corpus_remove(names: &[String]) -> CliResult {
+    let data_dir = user_data_dir()?;
+    // Check every name before removing anything.
+    let paths = names
+        .iter()
+        .map(|name| user_corpus_file(&data_dir, name))
+        .collect::<Result<Vec<_>, _>>()?;
+    for path in paths {
+        std::fs::remove_file(&path)?;
+        progress("Removed {}", &[&path.display()]);
+    }
+
+    Ok(())
+}
+
+/// The path of the corpus file `name` (as 'corpus list' shows it) in the user data directory.
+fn user_corpus_file(
+    data_dir: &std::path::Path,
+    name: &str,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let relative = std::path::Path::new(name);
+    // Names are relative to the data directory and can't climb out of it.
+    let is_relative = relative
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)));
+    if !is_relative {
+        return Err(tr_format("'{}' isn't a file name in the data directory.", &[&name]).into());
+    }
+    let path = data_dir.join(relative);
+    if path.is_file() {
+        return Ok(path);
+    }
+    let elsewhere = data_dirs()
+        .into_iter()
+        .skip(1)
+        .find(|dir| dir.join(relative).is_file());
+
+    Err(match elsewhere {
+        Some(dir) => tr_format(
+            "{} is in {}, which isn't the user data directory. Remove it there, or add a '{}' \
+             pattern to skip it.",
+            &[&name, &dir.display(), &IGNORE_FILE_NAME],
+        ),
+        None => tr_format("No corpus file '{}' in {}.", &[&name, &data_dir.display()]),
+    }
+    .into())
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CorpusCommand {
+    /// Download a curated set of public domain texts into the data directory, so passphrases
+    /// can be generated without giving corpus files. Each text is checked against a pinned
+    /// SHA-256 digest before it's saved
+    Fetch {
+        /// The set of texts to download
+        #[clap(value_parser = clap::builder::PossibleValuesParser::new(corpus_set_names()))]
+        set: String,
+    },
+    /// List the files in each data directory with their sizes. Only the first directory with
+    /// any files is used
+    List {
+        /// Only list files with these extensions (e.g. 'txt,md')
+        #[clap(long = "extension", value_parser, use_value_delimiter = true)]
+        extensions: Vec<String>,
+
+        /// Descend into subdirectories
+        #[clap(long, value_parser, default_value_t = false)]
+        recursive: bool,
+    },
+    /// Copy corpus files into the user data directory
+    Add {
+        #[clap(value_parser, required = true)]
+        files: Vec<std::path::PathBuf>,
+
+        /// Replace files of the same name
+        #[clap(long, value_parser, default_value_t = false)]
+        force: bool,
+    },
+    /// Remove corpus files from the user data directory, by their names as listed
+    Remove {
+        #[clap(value_parser, required = true)]
+        names: Vec<String>,
+    },
+}
+
+/// The names of the corpus sets 'corpus fetch' knows.
+fn corpus_set_names() -> Vec<&'static str> {
+    markovpass::download::CORPUS_SETS
+        .iter()
+        .map(|set| set.name)
+        .collect()
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CorpusArgs {
+    /// Files to use as markov chain input corpus, expanding '*', '?' and '**' wildcards. Use '-'
+    /// to read from stdin. 'http://' and 'https://' URLs are downloaded (with curl) and cached
+    #[clap(value_parser)]
+    pub files: Vec<String>,
+
+    /// Train on the system word list (e.g. /usr/share/dict/words) instead of corpus files
+    #[clap(long, value_parser, default_value_t = false, conflicts_with = "files")]
+    pub use_system_dictionary: bool,
+
+    /// Ngram length, from 2 to 7. Short ngrams give passphrases which look less like words, and
+    /// long ones need much more memory and give less entropy per character
+    #[clap(short = 'l', value_parser, default_value_t = 3)]
+    pub ngram_length: usize,
+
+    /// Allow ngram lengths over 7
+    #[clap(long, value_parser, default_value_t = false)]
+    pub force_ngram_length: bool,
+
+    /// Train on ngrams starting N characters apart instead of on every ngram, from 1 to the
+    /// ngram length. Models remember the step they were trained with
+    #[clap(long, value_parser, default_value_t = 1, value_name = "N")]
+    pub ngram_step: usize,
+
+    /// Start an ngram at every word boundary as well as every '--ngram-step' characters, so
+    /// each word's start is still learned
+    #[clap(long, value_parser, default_value_t = false)]
+    pub reset_at_words: bool,
+
+    /// Minimum length of corpus words to train on. Short words still help with transitions,
+    /// and '--min-output-word-length' can keep them out of passphrases
+    #[clap(
+        short = 'w',
+        long = "min-corpus-word-length",
+        value_parser,
+        default_value_t = 5,
+        value_name = "N"
+    )]
+    pub min_word_length: usize,
+
+    /// Keep apostrophes in corpus words, strip them out, or drop words containing them
+    #[clap(long, value_parser, default_value_t = markovpass::ApostrophePolicy::Keep, possible_values = ["keep", "strip", "drop-word"])]
+    pub apostrophes: markovpass::ApostrophePolicy,
+
+    /// Spell out standalone numbers in the corpus ("42" becomes "forty two") instead of dropping
+    /// them
+    #[clap(long, value_parser, default_value_t = false)]
+    pub spell_numbers: bool,
+
+    /// Only train on corpus words written entirely in this script, avoiding passphrases which mix
+    /// scripts
+    #[clap(long, value_parser = ["latin", "greek", "cyrillic", "any"], default_value = "any")]
+    pub script: String,
+
+    /// Where digits may appear in corpus words: trimmed off and dropped, at the end ("ipv6"),
+    /// anywhere but the start ("x86", "h2o"), or anywhere ("3des")
+    #[clap(long, value_parser, default_value_t = markovpass::DigitPolicy::Drop, possible_values = ["drop", "trailing", "not-leading", "anywhere"])]
+    pub digits: markovpass::DigitPolicy,
+
+    /// Keep capitalization from the corpus (e.g. German nouns) instead of lowercasing it. The
+    /// choice of case then counts towards entropy, but each case variant is trained separately
+    #[clap(long, value_parser, default_value_t = false)]
+    pub preserve_case: bool,
+
+    /// Drop corpus lines which repeat an earlier line in any file (ignoring case and spacing),
+    /// like licenses or headers repeated in every file
+    #[clap(long, value_parser, default_value_t = false)]
+    pub dedupe_lines: bool,
+
+    /// Drop corpus sentences which repeat an earlier sentence in any file (ignoring case and
+    /// spacing)
+    #[clap(long, value_parser, default_value_t = false)]
+    pub dedupe_sentences: bool,
+
+    /// Only use data directory files with these extensions (e.g. 'txt,md')
+    #[clap(long = "extension", value_parser, use_value_delimiter = true)]
+    pub extensions: Vec<String>,
+
+    /// Descend into subdirectories when scanning data directories
+    #[clap(long, value_parser, default_value_t = false)]
+    pub recursive: bool,
+
+    /// Fail instead of waiting for a corpus to be typed when reading stdin from a terminal
+    #[clap(long, value_parser, default_value_t = false)]
+    pub require_stdin_pipe: bool,
+
+    /// The encoding of the corpus files. 'auto' reads files as UTF-8 if they're valid UTF-8, and
+    /// otherwise as Windows-1252 (which covers Latin-1 text)
+    #[clap(
+        long,
+        value_parser = ["auto", "utf-8", "latin-1", "windows-1252"],
+        default_value = "auto"
+    )]
+    pub encoding: String,
+
+    /// Skip corpus files which can't be read or decoded instead of failing
+    #[clap(long, value_parser, default_value_t = false)]
+    pub skip_bad_files: bool,
+
+    /// Read and count up to N corpus files at once on separate threads [default: one per core]
+    #[clap(
+        short = 'j',
+        long,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        value_name = "N"
+    )]
+    pub jobs: Option<usize>,
+
+    #[clap(flatten)]
+    pub verify: VerifyArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct VerifyArgs {
+    /// File of SHA-256 checksums (as output by 'sha256sum') to verify corpus and model files
+    /// against. Files with an accompanying '.sum' file are always verified
+    #[clap(long, value_parser)]
+    pub checksums: Option<std::path::PathBuf>,
+
+    /// Refuse to use corpus or model files without a verified checksum
+    #[clap(long, value_parser, default_value_t = false)]
+    pub strict: bool,
+}
+
+impl VerifyArgs {
+    /// The expected digests of `files`, given as (path, URL it was downloaded from), for
+    /// `GenPassphraseOptions::expected_sha256`. Files are checked as they're read for use rather
+    /// than here, so they can't change in between.
+    pub fn expected_digests(
+        &self,
+        files: &[(std::path::PathBuf, Option<&str>)],
+    ) -> Result<std::collections::HashMap<std::path::PathBuf, String>, Box<dyn std::error::Error>>
+    {
+        let checksums = match &self.checksums {
+            Some(path) => parse_checksums(&std::fs::read_to_string(path)?),
+            None => vec![],
+        };
+        let mut digests = std::collections::HashMap::new();
+        for (path, url) in files {
+            match find_checksum(path, *url, &checksums)? {
+                Some(expected) => drop(digests.insert(path.clone(), expected)),
+                None if self.strict => {
+                    let name = url.map_or_else(|| path.display().to_string(), str::to_string);
+                    return Err(tr_format("No checksum found for {}.", &[&name]).into());
+                }
+                None => (),
+            }
+        }
+
+        Ok(digests)
+    }
+}
+
+/// Parse `sha256sum` style "<hex digest>  <file name>" lines. A bare digest has an empty name.
+fn parse_checksums(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, char::is_whitespace);
+            let digest = parts.next().filter(|digest| !digest.is_empty())?;
+            let name = parts.next().unwrap_or("").trim_start();
+            let name = name.strip_prefix('*').unwrap_or(name);
+            Some((digest.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// Find the expected checksum for `path` in its '.sum' file or in `checksums`. Files downloaded
+/// from a `url` are matched by the URL or the file name in it instead of their cached path.
+fn find_checksum(
+    path: &std::path::Path,
+    url: Option<&str>,
+    checksums: &[(String, String)],
+) -> std::io::Result<Option<String>> {
+    let file_name = match url {
+        Some(url) => markovpass::download::file_name(url).to_string(),
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let matches = |name: &str| {
+        name.is_empty()
+            || name == file_name
+            || match url {
+                Some(url) => name == url,
+                None => path == std::path::Path::new(name),
+            }
+    };
+
+    let mut sum_file = path.as_os_str().to_owned();
+    sum_file.push(".sum");
+    let sum_file = std::path::PathBuf::from(sum_file);
+    if sum_file.is_file() {
+        let sums = parse_checksums(&std::fs::read_to_string(sum_file)?);
+        if let Some((digest, _)) = sums.into_iter().find(|(_, name)| matches(name)) {
+            return Ok(Some(digest));
+        }
+    }
+
+    Ok(checksums
+        .iter()
+        .find(|(_, name)| !name.is_empty() && matches(name))
+        .map(|(digest, _)| digest.clone()))
+}
+
+impl CorpusArgs {
+    /// Options for reading the corpus, with generation options left at their defaults.
+    ///
+    /// Corpus files are only resolved if `resolve_files` is set, since looking for data files
+    /// fails when there are none.
+    pub fn gen_passphrase_options(
+        &self,
+        resolve_files: bool,
+    ) -> Result<markovpass::GenPassphraseOptions, Box<dyn std::error::Error>> {
+        if self.ngram_length > markovpass::MAX_NGRAM_LENGTH && !self.force_ngram_length {
+            return Err(tr_format(
+                "Ngram length {} is over the maximum of {}: chains get huge and mostly reproduce \
+                 the corpus. Use --force-ngram-length to try anyway.",
+                &[&self.ngram_length, &markovpass::MAX_NGRAM_LENGTH],
+            )
+            .into());
+        }
+        let data_dir_options = DataDirOptions {
+            extensions: self.extensions.clone(),
+            recursive: self.recursive,
+        };
+        let mut embedded_corpus = false;
+        // Data directory files can go missing between listing and reading them, which shouldn't
+        // stop unattended runs while there's still some corpus.
+        let from_data_dir = self.files.is_empty() && !self.use_system_dictionary;
+        let files = if resolve_files {
+            let files = match self.use_system_dictionary {
+                true => vec![system_dictionary()?],
+                false => match get_corpus_files(&self.files, &data_dir_options) {
+                    // Without corpus files or a data directory, fall back on the built in corpus.
+                    Err(error)
+                        if self.files.is_empty()
+                            && error.kind() == std::io::ErrorKind::NotFound
+                            && !markovpass::EMBEDDED_CORPUS.is_empty() =>
+                    {
+                        embedded_corpus = true;
+                        vec![]
+                    }
+                    result => result?,
+                },
+            };
+            if files.is_empty() && !embedded_corpus {
+                if self.verify.strict {
+                    return Err(tr("Can't verify a corpus read from stdin in strict mode.").into());
+                }
+                if std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+                    if self.require_stdin_pipe {
+                        return Err(
+                            tr("Refusing to read the corpus from a terminal on stdin.").into()
+                        );
+                    }
+                    progress("Reading corpus from stdin, Ctrl-D to finish.", &[]);
+                }
+            }
+            files
+        } else {
+            vec![]
+        };
+        let urls = self.urls()?;
+        let origins: Vec<_> = files
+            .iter()
+            .map(|path: &std::path::PathBuf| (path.clone(), urls.get(path).map(String::as_str)))
+            .collect();
+        let expected_sha256 = self.verify.expected_digests(&origins)?;
+
+        Ok(markovpass::GenPassphraseOptions {
+            files,
+            ngram_length: self.ngram_length,
+            ngram_step: self.ngram_step,
+            reset_at_words: self.reset_at_words,
+            min_word_length: self.min_word_length,
+            apostrophes: self.apostrophes,
+            spell_numbers: self.spell_numbers,
+            digits: self.digits,
+            script: match self.script.as_str() {
+                "any" => None,
+                script => Some(script.parse()?),
+            },
+            preserve_case: self.preserve_case,
+            dedupe_lines: self.dedupe_lines,
+            dedupe_sentences: self.dedupe_sentences,
+            encoding: match self.encoding.as_str() {
+                "auto" => None,
+                encoding => Some(encoding.parse()?),
+            },
+            skip_bad_files: self.skip_bad_files,
+            skip_missing_files: from_data_dir && !embedded_corpus,
+            training_threads: self.jobs,
+            expected_sha256,
+            embedded_corpus,
+            ..Default::default()
+        })
+    }
+
+    /// The URLs of the corpus files which are downloads, by their cached paths.
+    fn urls(&self) -> std::io::Result<std::collections::HashMap<std::path::PathBuf, String>> {
+        let mut urls = std::collections::HashMap::new();
+        for file in &self.files {
+            if markovpass::download::is_url(file) {
+                let path = markovpass::download::cache_path(file, &download_cache_dir(file)?);
+                urls.insert(path, file.clone());
+            }
+        }
+
+        Ok(urls)
+    }
+}
+
+/// The cached copy of the corpus at `url`, downloading it if it isn't cached yet.
+pub fn download_corpus(url: &str) -> std::io::Result<std::path::PathBuf> {
+    let cache_dir = download_cache_dir(url)?;
+    if !markovpass::download::cache_path(url, &cache_dir).is_file() {
+        progress("Downloading {}...", &[&url]);
+    }
+
+    markovpass::download::fetch(url, &cache_dir)
+}
+
+/// The directory downloads (like `url`) are cached in.
+fn download_cache_dir(url: &str) -> std::io::Result<std::path::PathBuf> {
+    directories::ProjectDirs::from_path("markovpass".into())
+        .map(|pds| pds.cache_dir().join("downloads"))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                tr_format("No cache directory to download '{}' into.", &[&url]),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checksums() {
+        let contents = "abc123  austen.txt\ndef456 *corpus/lovecraft.txt\n\n0fed\n";
+        assert_eq!(
+            parse_checksums(contents),
+            vec![
+                ("abc123".to_string(), "austen.txt".to_string()),
+                ("def456".to_string(), "corpus/lovecraft.txt".to_string()),
+                ("0fed".to_string(), "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_checksum() {
+        let checksums = parse_checksums("abc123  austen.txt\ndef456  corpus/lovecraft.txt");
+        let find =
+            |path: &str| find_checksum(std::path::Path::new(path), None, &checksums).unwrap();
+        assert_eq!(find("texts/austen.txt"), Some("abc123".to_string()));
+        assert_eq!(find("corpus/lovecraft.txt"), Some("def456".to_string()));
+        assert_eq!(find("wodehouse.txt"), None);
+
+        // Downloads are matched by their URL or its file name, not their cached path.
+        let checksums = parse_checksums(
+            "abc123  pg1342.txt\ndef456  https://example.com/lovecraft.txt?download=1",
+        );
+        let find = |url: &str| {
+            let cached = markovpass::download::cache_path(url, std::path::Path::new("cache"));
+            find_checksum(&cached, Some(url), &checksums).unwrap()
+        };
+        assert_eq!(
+            find("https://www.gutenberg.org/cache/epub/1342/pg1342.txt"),
+            Some("abc123".to_string())
+        );
+        assert_eq!(
+            find("https://example.com/lovecraft.txt?download=1"),
+            Some("def456".to_string())
+        );
+        assert_eq!(find("https://example.com/wodehouse.txt"), None);
+    }
+
+    #[test]
+    fn test_user_corpus_file() {
+        let dir = std::env::temp_dir().join(format!("markovpass-remove-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "text").unwrap();
+        let found = user_corpus_file(&dir, "a.txt");
+        let missing = user_corpus_file(&dir, "b.txt");
+        let outside = user_corpus_file(&dir, "../a.txt");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found.unwrap(), dir.join("a.txt"));
+        assert!(missing.is_err());
+        assert!(outside.is_err());
+    }
+}
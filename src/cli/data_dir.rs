@@ -0,0 +1,414 @@
+//! Finding corpus files in the data directories and by glob patterns.
+
+use crate::cli::corpus::download_corpus;
+use crate::cli::tr_format;
+
+/// Rules for discovering corpus files in the data directories.
+#[derive(Debug, Clone, Default)]
+pub struct DataDirOptions {
+    pub extensions: Vec<String>,
+    pub recursive: bool,
+}
+
+/// Where word lists are installed on common systems, in the order they're looked for.
+const SYSTEM_DICTIONARIES: [&str; 3] = [
+    "/usr/share/dict/words",
+    "/usr/dict/words",
+    "/usr/share/dict/web2",
+];
+
+pub fn system_dictionary() -> std::io::Result<std::path::PathBuf> {
+    find_file(&SYSTEM_DICTIONARIES).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            tr_format(
+                "No system dictionary found in any of {}. Install one (e.g. the 'wamerican' \
+                 package) or give corpus files.",
+                &[&SYSTEM_DICTIONARIES.join(", ")],
+            ),
+        )
+    })
+}
+
+/// The first of `candidates` which is a file.
+fn find_file(candidates: &[&str]) -> Option<std::path::PathBuf> {
+    candidates
+        .iter()
+        .map(std::path::PathBuf::from)
+        .find(|path| path.is_file())
+}
+
+/// Name of the file listing patterns for data directory files to skip.
+pub const IGNORE_FILE_NAME: &str = ".markovpassignore";
+
+pub fn get_corpus_files(
+    files: &[String],
+    options: &DataDirOptions,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    match files {
+        [] => get_data_files(options),
+        [x] if x == "-" => Ok(vec![]),
+        _ => {
+            let mut paths = vec![];
+            for file in files {
+                if markovpass::download::is_url(file) {
+                    paths.push(download_corpus(file)?);
+                } else if file.contains(['*', '?']) && !std::path::Path::new(file).exists() {
+                    paths.extend(expand_glob(file)?);
+                } else {
+                    paths.push(file.into());
+                }
+            }
+            Ok(paths)
+        }
+    }
+}
+
+/// The files matching `pattern`, sorted, for shells (like Windows') which don't expand
+/// wildcards themselves. '*' and '?' match within a path component, '**' matches any number of
+/// directories, and hidden files only match patterns starting with '.'.
+fn expand_glob(pattern: &str) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let normalized = pattern.replace('\\', "/");
+    let components: Vec<&str> = normalized.split('/').collect();
+    let literal = components
+        .iter()
+        .position(|component| component.contains(['*', '?']))
+        .unwrap_or(components.len());
+    let base = std::path::PathBuf::from(components[..literal].join("/"));
+    let mut paths = vec![];
+    glob_walk(&base, &components[literal..], &mut paths)?;
+    if paths.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            tr_format("No corpus files match '{}'.", &[&pattern]),
+        ));
+    }
+    paths.sort();
+    paths.dedup();
+
+    Ok(paths)
+}
+
+/// Add the files under `dir` matching the pattern `components` to `paths`.
+fn glob_walk(
+    dir: &std::path::Path,
+    components: &[&str],
+    paths: &mut Vec<std::path::PathBuf>,
+) -> std::io::Result<()> {
+    let Some((&component, rest)) = components.split_first() else {
+        if dir.is_file() {
+            paths.push(dir.to_path_buf());
+        }
+        return Ok(());
+    };
+    if component == "**" {
+        glob_walk(dir, rest, paths)?;
+    }
+    let listed = match dir.as_os_str().is_empty() {
+        true => std::path::Path::new("."),
+        false => dir,
+    };
+    // Anything that isn't a readable directory just has no matches.
+    let Ok(entries) = std::fs::read_dir(listed) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') && !component.starts_with('.') {
+            continue;
+        }
+        let path = dir.join(&name);
+        if component == "**" {
+            // Symlinked directories aren't followed, so links can't loop.
+            if entry.file_type()?.is_dir() {
+                glob_walk(&path, components, paths)?;
+            }
+        } else if wildcard_match(component, &name) {
+            glob_walk(&path, rest, paths)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The user's data directory, where 'corpus fetch' and 'corpus add' put files.
+pub fn user_data_dir() -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    Ok(directories::ProjectDirs::from_path("markovpass".into())
+        .ok_or("No data directory found for markovpass.")?
+        .data_dir()
+        .to_path_buf())
+}
+
+/// The directories searched for corpus data, in order. Only the first with any files is used.
+pub fn data_dirs() -> Vec<std::path::PathBuf> {
+    let mut data_dirs: Vec<_> = user_data_dir().into_iter().collect();
+    if cfg!(target_os = "linux") {
+        data_dirs.extend(
+            std::env::var("XDG_DATA_DIRS")
+                .unwrap_or_else(|_| "/usr/local/share/:/usr/share/".to_string())
+                .split(':')
+                .map(|s| std::path::PathBuf::from(s).join("markovpass")),
+        );
+    }
+
+    data_dirs
+}
+
+/// The corpus files in the data directory `dir`, sorted.
+pub fn data_dir_files(
+    dir: &std::path::Path,
+    options: &DataDirOptions,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let ignore_patterns = read_ignore_patterns(dir)?;
+    let mut paths = vec![];
+    scan_data_dir(dir, dir, options, &ignore_patterns, &mut paths)?;
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn get_data_files(options: &DataDirOptions) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let data_dirs = data_dirs();
+    for dir in &data_dirs {
+        let paths = data_dir_files(dir, options)?;
+        if !paths.is_empty() {
+            return Ok(paths);
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        tr_format(
+            "No corpus data found in any of {}.",
+            &[&data_dirs
+                .iter()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")],
+        ),
+    ))
+}
+
+/// Add the corpus files in `dir` to `paths`. Symlinked files are included, but symlinked
+/// directories aren't descended into, which could loop forever.
+fn scan_data_dir(
+    root: &std::path::Path,
+    dir: &std::path::Path,
+    options: &DataDirOptions,
+    ignore_patterns: &[String],
+    paths: &mut Vec<std::path::PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative_path = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        // Checksum files aren't corpus data.
+        if relative_path == IGNORE_FILE_NAME
+            || relative_path.ends_with(".sum")
+            || ignore_patterns
+                .iter()
+                .any(|pattern| is_ignored(pattern, &relative_path))
+        {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            if options.recursive {
+                match scan_data_dir(root, &path, options, ignore_patterns, paths) {
+                    // The directory was removed since it was listed.
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                    result => result?,
+                }
+            }
+        } else if path.is_file() && has_allowed_extension(&path, &options.extensions) {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the ignore patterns from a data directory, skipping blank lines and '#' comments.
+fn read_ignore_patterns(dir: &std::path::Path) -> std::io::Result<Vec<String>> {
+    let ignore_file = dir.join(IGNORE_FILE_NAME);
+    if !ignore_file.is_file() {
+        return Ok(vec![]);
+    }
+    let contents = std::fs::read_to_string(ignore_file)?;
+
+    Ok(contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.trim_end_matches('/').to_string())
+        .collect())
+}
+
+fn has_allowed_extension(path: &std::path::Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+    let extension = path.extension().map(|ext| ext.to_string_lossy());
+    extensions.iter().any(|allowed| {
+        let allowed = allowed.trim_start_matches('.');
+        extension
+            .as_deref()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(allowed))
+    })
+}
+
+/// Patterns containing a '/' match against the whole relative path, others against any component.
+fn is_ignored(pattern: &str, relative_path: &str) -> bool {
+    if pattern.contains('/') {
+        wildcard_match(pattern.trim_start_matches('/'), relative_path)
+    } else {
+        relative_path
+            .split('/')
+            .any(|component| wildcard_match(pattern, component))
+    }
+}
+
+/// Match `text` against a pattern where '*' matches any run of characters and '?' any one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = std::env::temp_dir().join(format!("markovpass-glob-{}", std::process::id()));
+        for name in [
+            "a.txt",
+            "b.md",
+            ".hidden.txt",
+            "sub/c.txt",
+            "sub/deeper/d.txt",
+        ] {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "text").unwrap();
+        }
+        let root = dir.to_string_lossy();
+        let expand = |pattern: &str| expand_glob(&format!("{}/{}", root, pattern));
+        let names = |paths: Vec<std::path::PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| {
+                    let relative = path.strip_prefix(&dir).unwrap();
+                    relative.to_string_lossy().replace('\\', "/")
+                })
+                .collect()
+        };
+        let top = expand("*.txt").map(names);
+        let recursive = expand("**/*.txt").map(names);
+        let nested = expand("s?b/*/*.txt").map(names);
+        let missing = expand("*.csv");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(top.unwrap(), ["a.txt"]);
+        assert_eq!(
+            recursive.unwrap(),
+            ["a.txt", "sub/c.txt", "sub/deeper/d.txt"]
+        );
+        assert_eq!(nested.unwrap(), ["sub/deeper/d.txt"]);
+        assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_data_dir_files() {
+        let dir = std::env::temp_dir().join(format!("markovpass-data-{}", std::process::id()));
+        for name in ["a.txt", "sub/b.txt"] {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "text").unwrap();
+        }
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a.txt"), dir.join("link.txt")).unwrap();
+        let options = DataDirOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let files = data_dir_files(&dir, &options);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let files: Vec<_> = files
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            files,
+            ["a.txt", "link.txt", "sub/b.txt"].map(std::path::PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_find_file() {
+        let found = find_file(&["/nonexistent/words", "Cargo.toml", "src"]);
+        assert_eq!(found, Some("Cargo.toml".into()));
+        assert_eq!(find_file(&["src", "/nonexistent/words"]), None);
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.txt", "austen.txt"));
+        assert!(wildcard_match("a?sten*", "austen.txt"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("*.txt", "austen.md"));
+        assert!(!wildcard_match("?", ""));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        assert!(is_ignored("drafts", "drafts/notes.txt"));
+        assert!(is_ignored("*.bak", "old/austen.bak"));
+        assert!(is_ignored("old/*.txt", "old/austen.txt"));
+        assert!(!is_ignored("old/*.txt", "new/old/austen.txt"));
+        assert!(!is_ignored("drafts", "austen.txt"));
+    }
+
+    #[test]
+    fn test_has_allowed_extension() {
+        let path = std::path::Path::new("corpus/austen.TXT");
+        assert!(has_allowed_extension(path, &[]));
+        assert!(has_allowed_extension(path, &["md".into(), ".txt".into()]));
+        assert!(!has_allowed_extension(path, &["md".into()]));
+        assert!(!has_allowed_extension(
+            std::path::Path::new("README"),
+            &["md".into()]
+        ));
+    }
+}
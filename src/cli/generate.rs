@@ -0,0 +1,1086 @@
+//! The 'generate' subcommand, which runs when no subcommand is given.
+
+use crate::cli::corpus::CorpusArgs;
+use crate::cli::records::{OutputFormat, RecordWriter, Value};
+use crate::cli::secrets::{check_stdout_not_file, confirm_passphrase, open_append, write_paranoid};
+use crate::cli::train::{build_chain, ChainArgs};
+use crate::cli::{
+    catch_interrupts, format_date, format_timestamp, interrupted, progress, quote, tr, tr_format,
+    unix_time, warn, CliResult, INTERRUPTED_EXIT_CODE,
+};
+use crate::is_broken_pipe;
+use markovpass::wipe;
+
+/// What a run of 'generate' did, for '--audit-log'. Nothing about the passphrases themselves is
+/// kept.
+#[derive(Debug, Default)]
+struct Session {
+    /// The fingerprint of the chain, once it's built.
+    model: Option<String>,
+    /// How many passphrases were output.
+    count: usize,
+}
+
+pub fn generate(args: &GenerateArgs) -> CliResult {
+    let mut session = Session::default();
+    let result = generate_passphrases(args, &mut session);
+    if let Some(path) = &args.audit_log {
+        let exit_status = match &result {
+            Err(error) if !is_broken_pipe(&**error) => 1,
+            _ if interrupted() => INTERRUPTED_EXIT_CODE,
+            _ => 0,
+        };
+        let logged = log_session(path, args, &session, exit_status);
+        // A failed run's own error matters more than the log's.
+        if result.is_ok() {
+            logged?;
+        }
+    }
+    if result.is_ok() && interrupted() {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+
+    result
+}
+
+/// Append a JSON line describing `session` to the audit log at `path`.
+fn log_session(
+    path: &std::path::Path,
+    args: &GenerateArgs,
+    session: &Session,
+    exit_status: i32,
+) -> CliResult {
+    use std::io::Write;
+
+    let min_entropy: Vec<_> = args
+        .min_entropy
+        .iter()
+        .map(|entropy| format!("{:?}", entropy))
+        .collect();
+    let record = format!(
+        "{{\"time\": {}, \"command\": \"generate\", \"count\": {}, \"min_entropy\": [{}], \
+         \"model\": {}, \"exit_status\": {}}}",
+        quote(&format_timestamp(unix_time())),
+        session.count,
+        min_entropy.join(", "),
+        session.model.as_deref().map_or("null".to_string(), quote),
+        exit_status
+    );
+    writeln!(open_append(path, false)?, "{}", record)?;
+
+    Ok(())
+}
+
+fn generate_passphrases(args: &GenerateArgs, session: &mut Session) -> CliResult {
+    use std::io::Write;
+
+    let start = std::time::Instant::now();
+    args.check_no_disk()?;
+    if args.format == OutputFormat::Text && (args.label.is_some() || args.valid_days.is_some()) {
+        return Err("--label and --valid-days need a structured --format.".into());
+    }
+    let gen_passphrase_options = args.gen_passphrase_options()?;
+    if args.suggest_params {
+        return suggest_params(&gen_passphrase_options);
+    }
+    let chain = build_chain(&gen_passphrase_options, args.chain.save_model.as_deref())?;
+    if args.audit_log.is_some() {
+        session.model = Some(chain.fingerprint());
+    }
+    args.check_feasible(&chain)?;
+    if args.dry_run {
+        return dry_run(args, &gen_passphrase_options, &chain);
+    }
+    #[cfg(all(target_os = "linux", feature = "systemd-creds"))]
+    if let Some(name) = &args.systemd_cred {
+        let passphrase = single_passphrase(args, &chain, &gen_passphrase_options)?;
+        let output = args
+            .systemd_cred_output
+            .clone()
+            .unwrap_or_else(|| format!("{}.cred", name).into());
+        crate::cli::secrets::systemd_creds_encrypt(&passphrase, name, &output)?;
+        session.count = 1;
+        return Ok(());
+    }
+    if args.confirm {
+        let passphrase = single_passphrase(args, &chain, &gen_passphrase_options)?;
+        let timeout = std::time::Duration::from_secs(args.confirm_timeout);
+        confirm_passphrase(passphrase, timeout)?;
+        session.count = 1;
+        return Ok(());
+    }
+    let hint_words = if args.hint {
+        if gen_passphrase_options.files.is_empty() && !gen_passphrase_options.embedded_corpus {
+            return Err("Hints need corpus files to draw words from.".into());
+        }
+        // Hints match words case insensitively, even with a case preserving corpus.
+        let mut words: Vec<String> = markovpass::corpus_words(&gen_passphrase_options)?
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect();
+        words.sort();
+        words.dedup();
+        words
+    } else {
+        vec![]
+    };
+
+    // Passphrases are written as they're generated, flushing every `args.buffer` passphrases (and
+    // before each hint or read-out so the two streams stay in order).
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut unflushed = 0;
+    let mut records = RecordWriter::new(args.format);
+    let mut stats = markovpass::GenerationStats::default();
+    let mut summary = BatchSummary::default();
+    // Structured formats always give some entropy, the Shannon entropy unless told otherwise.
+    let shown_measures = args.entropy_measures();
+    let measures = shown_measures
+        .clone()
+        .unwrap_or_else(|| vec![markovpass::EntropyMeasure::Shannon]);
+    // Passphrases under the minimum entropy once normalized, and the lowest normalized entropy.
+    let mut weak = (0, f64::INFINITY);
+    let expires = args
+        .valid_days
+        .map(|days| format_date(unix_time().saturating_add(days.saturating_mul(86400))));
+    let mut audit_log = args
+        .audit
+        .as_deref()
+        .map(|path| open_append(path, false))
+        .transpose()?;
+    let mut audit_seeds = args
+        .audit_seeds
+        .as_deref()
+        .map(|path| open_append(path, true))
+        .transpose()?;
+    // From here on Ctrl-C stops after the passphrase being generated, so output only ever has
+    // complete lines (and records) and the summary still gets printed.
+    catch_interrupts();
+    'batches: for (i, &min_entropy) in args.min_entropy.iter().enumerate() {
+        let options = markovpass::GenPassphraseOptions {
+            min_entropy,
+            ..gen_passphrase_options.clone()
+        };
+        if args.min_entropy.len() > 1 && args.format == OutputFormat::Text {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "# Minimum entropy {}", min_entropy)?;
+        }
+        let mut passphrases = markovpass::iter_passphrases_from_chain(&chain, &options)?;
+        for result in passphrases.by_ref() {
+            if interrupted() {
+                stats.merge(passphrases.stats());
+                break 'batches;
+            }
+            let result = result?;
+            let entropies: Vec<_> = measures
+                .iter()
+                .map(|&measure| (measure, result.entropy_by(measure)))
+                .collect();
+            let shown_entropy = shown_measures
+                .as_ref()
+                .map(|_| format_entropies(&entropies));
+            let markovpass::Passphrase {
+                text: passphrase,
+                words,
+                entropy,
+                normalized_entropy,
+                audit,
+                ..
+            } = result;
+            summary.add(&passphrase, entropy);
+            session.count += 1;
+            if normalized_entropy < min_entropy {
+                weak.0 += 1;
+                weak.1 = weak.1.min(normalized_entropy);
+            }
+            if let (Some(log), Some(record)) = (&mut audit_log, &audit) {
+                writeln!(
+                    log,
+                    "{}\tmodel={}\tcommitment={}\tmin_entropy={}",
+                    format_timestamp(unix_time()),
+                    record.model,
+                    record.commitment,
+                    min_entropy
+                )?;
+            }
+            if let (Some(seeds), Some(record)) = (&mut audit_seeds, &audit) {
+                let seed = markovpass::sha256::to_hex(&record.seed);
+                writeln!(seeds, "{}  {}", seed, record.commitment)?;
+            }
+            if args.paranoid {
+                out.flush()?;
+                write_paranoid(&passphrase, shown_entropy.as_deref())?;
+                wipe(passphrase);
+                words.into_iter().for_each(wipe);
+                continue;
+            }
+            match args.format {
+                OutputFormat::Text => match &shown_entropy {
+                    Some(shown) => writeln!(out, "{} <{}>", passphrase, shown)?,
+                    None => writeln!(out, "{}", passphrase)?,
+                },
+                _ => {
+                    let label = args
+                        .label
+                        .as_ref()
+                        .map(|template| template.replace("{n}", &summary.count.to_string()));
+                    let mut fields = vec![];
+                    if let Some(label) = &label {
+                        fields.push(("label", Value::Str(label)));
+                    }
+                    fields.extend([
+                        ("passphrase", Value::Str(&passphrase)),
+                        ("words", Value::List(&words)),
+                    ]);
+                    for &(measure, value) in &entropies {
+                        match measure {
+                            markovpass::EntropyMeasure::Shannon => fields.extend([
+                                ("entropy", Value::Float(value)),
+                                ("normalized_entropy", Value::Float(normalized_entropy)),
+                            ]),
+                            markovpass::EntropyMeasure::Min => {
+                                fields.push(("worst_case_entropy", Value::Float(value)))
+                            }
+                        }
+                    }
+                    fields.push(("min_entropy", Value::Float(min_entropy)));
+                    if let Some(expires) = &expires {
+                        fields.push(("expires", Value::Str(expires)));
+                    }
+                    records.write(&mut out, &fields)?
+                }
+            }
+            unflushed += 1;
+            if unflushed >= args.buffer || args.hint || args.nato {
+                out.flush()?;
+                unflushed = 0;
+            }
+            if args.hint {
+                // Styles may capitalize words, but the hint words are all lowercase.
+                let words: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+                let words: Vec<&str> = words.iter().map(String::as_str).collect();
+                match markovpass::hint::mnemonic_hint(&words, &hint_words) {
+                    Some(hint) => {
+                        eprintln!("{}", tr_format("Hint (not a passphrase): {}", &[&hint]))
+                    }
+                    None => warn("No hint found for this passphrase.", &[]),
+                }
+            }
+            if args.nato {
+                let readout = markovpass::phonetic::nato_readout(&passphrase);
+                eprintln!("{}", tr_format("Read-out: {}", &[&readout]));
+            }
+        }
+        stats.merge(passphrases.stats());
+    }
+    records.finish(&mut out)?;
+    out.flush()?;
+    if weak.0 > 0 {
+        warn(
+            "{} passphrase(s) have less than the minimum entropy (as little as {} bits) to an \
+             attacker who ignores case and word boundaries. Use --enforce-normalized-entropy to \
+             make them longer.",
+            &[&weak.0, &format!("{:.1}", weak.1)],
+        );
+    }
+    let rejections = &stats.rejections;
+    if args.count_retries {
+        match rejections.total() {
+            0 => eprintln!("{}", tr("No candidates rejected")),
+            total => eprintln!("{} candidates rejected: {}", total, rejections),
+        }
+    }
+    if args.summary || interrupted() {
+        if interrupted() {
+            eprint!("{}", tr("Interrupted. "));
+        }
+        eprintln!(
+            "{}; {} of {} candidates rejected{}; took {:.2?}",
+            summary,
+            rejections.total(),
+            stats.attempts,
+            match rejections.total() {
+                0 => String::new(),
+                _ => format!(" ({})", rejections),
+            },
+            start.elapsed()
+        );
+    }
+
+    Ok(())
+}
+
+/// Aggregate statistics about the passphrases generated in one run.
+#[derive(Debug, Default)]
+struct BatchSummary {
+    count: usize,
+    total_entropy: f64,
+    min_entropy: f64,
+    max_entropy: f64,
+    total_length: usize,
+}
+
+impl BatchSummary {
+    fn add(&mut self, passphrase: &str, entropy: f64) {
+        if self.count == 0 {
+            self.min_entropy = entropy;
+            self.max_entropy = entropy;
+        }
+        self.count += 1;
+        self.total_entropy += entropy;
+        self.min_entropy = self.min_entropy.min(entropy);
+        self.max_entropy = self.max_entropy.max(entropy);
+        self.total_length += passphrase.chars().count();
+    }
+}
+
+impl std::fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Generated {} passphrase(s)", self.count)?;
+        if self.count > 0 {
+            let count = self.count as f64;
+            write!(
+                f,
+                ": entropy mean {:.1}, min {:.1}, max {:.1} bits; mean length {:.1} characters",
+                self.total_entropy / count,
+                self.min_entropy,
+                self.max_entropy,
+                self.total_length as f64 / count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Summarize the chain `generate` would use, for checking a corpus or options still work.
+fn dry_run(
+    args: &GenerateArgs,
+    options: &markovpass::GenPassphraseOptions,
+    chain: &markovpass::PassphraseMarkovChain,
+) -> CliResult {
+    match &options.model {
+        Some(path) => println!("Model:             {}", path.display()),
+        None => println!("Corpus files:      {}", options.files.len()),
+    }
+    println!("Nodes:             {}", chain.node_count());
+    println!("Starting entropy:  {:.2} bits", chain.starting_entropy());
+    println!(
+        "Entropy rate:      {:.2} bits per character",
+        chain.entropy_rate()
+    );
+    for &min_entropy in &args.min_entropy {
+        println!(
+            "Expected length:   {:.0} characters for {} bits",
+            chain.expected_length(min_entropy),
+            min_entropy
+        );
+    }
+    println!("Fingerprint:       {}", chain.fingerprint());
+
+    Ok(())
+}
+
+/// Compare ngram lengths on a sample of the corpus and suggest the one giving the most entropy
+/// per character in natural looking words.
+fn suggest_params(options: &markovpass::GenPassphraseOptions) -> CliResult {
+    progress(
+        "Training on up to {} corpus words at each ngram length...",
+        &[&markovpass::SUGGESTION_SAMPLE_WORDS],
+    );
+    let (best, trials) = markovpass::suggest_ngram_length(options)?;
+    println!(
+        "{:>6}  {:>8}  {:>9}  {:>10}  {:>7}  {:>5}",
+        "Length", "Nodes", "Bits/char", "Characters", "Natural", "Score"
+    );
+    for trial in &trials {
+        println!(
+            "{:>6}  {:>8}  {:>9.2}  {:>10.0}  {:>6.0}%  {:>5.2}{}",
+            trial.ngram_length,
+            trial.nodes,
+            trial.entropy_rate,
+            trial.expected_length,
+            trial.naturalness * 100.0,
+            trial.score(),
+            if trial.ngram_length == best {
+                "  *"
+            } else {
+                ""
+            }
+        );
+    }
+    println!();
+    println!(
+        "Characters is the typical passphrase length for {} bits, and Natural the share of",
+        options.min_entropy
+    );
+    println!("generated words which read like corpus words.");
+    println!("Suggested: -l {}", best);
+
+    Ok(())
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct GenerateArgs {
+    /// Number of passphrases to generate
+    #[clap(short = 'n', value_parser, default_value_t = 1)]
+    pub number: usize,
+
+    /// Flush output after every N passphrases. By default each passphrase is written as soon
+    /// as it's generated
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 1, value_name = "N")]
+    pub buffer: u64,
+
+    /// Minimum entropy. Repeat to generate a labeled group of passphrases for each entropy tier
+    #[clap(short = 'e', value_parser, default_value = "60")]
+    pub min_entropy: Vec<f64>,
+
+    /// Maximum entropy, for systems which truncate long passphrases
+    #[clap(long, value_parser)]
+    pub max_entropy: Option<f64>,
+
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    /// Print the entropy of each passphrase: its Shannon entropy, its min-entropy (which only
+    /// counts the most likely option of each choice), or both (e.g. --show-entropy=both).
+    /// Structured formats give the measures chosen, or the Shannon entropy by default
+    #[clap(
+        long,
+        value_name = "MEASURE",
+        value_parser = ["shannon", "min", "both"],
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "shannon"
+    )]
+    pub show_entropy: Option<String>,
+
+    /// Output format. The structured formats give each passphrase's words, entropy and minimum
+    /// entropy
+    #[clap(long, value_parser, default_value_t = OutputFormat::Text, possible_values = ["text", "json", "toml", "yaml", "csv"], conflicts_with = "paranoid")]
+    pub format: OutputFormat,
+
+    /// Give each passphrase in structured output a label from TEMPLATE, with "n" in braces
+    /// replaced by the passphrase number, counting from 1
+    #[clap(long, value_parser, value_name = "TEMPLATE")]
+    pub label: Option<String>,
+
+    /// Give each passphrase in structured output an expiry date N days from today
+    #[clap(long, value_parser, value_name = "N")]
+    pub valid_days: Option<u64>,
+
+    #[clap(flatten)]
+    pub chain: ChainArgs,
+
+    /// String to put between the words of each passphrase
+    #[clap(long, value_parser, default_value = " ")]
+    pub separator: String,
+
+    /// How to put words together. 'compactCamel' joins them with no separator, capitalizing all
+    /// but the first
+    #[clap(long, value_parser, default_value_t = markovpass::Style::Plain, possible_values = ["plain", "compactCamel"])]
+    pub style: markovpass::Style,
+
+    /// Reject passphrases with words containing more consecutive consonants than this, to make
+    /// them easier to say aloud
+    #[clap(long, value_parser, value_name = "N")]
+    pub max_consonants: Option<usize>,
+
+    /// Reject passphrases with words shorter than this
+    #[clap(long, value_parser, value_name = "N")]
+    pub min_output_word_length: Option<usize>,
+
+    /// Reject passphrases containing words which appear in the corpus, so every word is invented
+    #[clap(long, value_parser, default_value_t = false, conflicts_with = "model")]
+    pub no_corpus_words: bool,
+
+    /// Reject passphrases containing substrings common in FILE, such as a leaked password list
+    /// with one password per line. Can be repeated
+    #[clap(long, value_parser, value_name = "FILE")]
+    pub attacker_corpus: Vec<std::path::PathBuf>,
+
+    /// Length of the attacker corpus substrings to avoid
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 5, value_name = "N")]
+    pub attacker_substring_length: u64,
+
+    /// How many of the most frequent substrings of each attacker corpus to avoid
+    #[clap(long, value_parser, default_value_t = 1000, value_name = "N")]
+    pub attacker_top: usize,
+
+    /// Experimental: train character and syllable models on the corpus, and reject passphrases
+    /// with words either finds less natural than most corpus words
+    #[clap(long, value_parser, default_value_t = false, conflicts_with = "model")]
+    pub ensemble: bool,
+
+    /// The percentile of corpus words' surprisal up to which --ensemble accepts words. Lower is
+    /// stricter
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 95.0,
+        value_name = "P",
+        requires = "ensemble"
+    )]
+    pub ensemble_percentile: f64,
+
+    /// Reject passphrases which mix scripts (e.g. Latin and Cyrillic lookalikes) or contain
+    /// easily confused characters
+    #[clap(long, value_parser, default_value_t = false)]
+    pub no_homoglyphs: bool,
+
+    /// Characters which must not appear in passphrases (e.g. "qz'")
+    #[clap(long, value_parser, default_value = "")]
+    pub exclude_chars: String,
+
+    /// Only output passphrases which comply with the policy in FILE (see 'markovpass check')
+    #[clap(long, value_parser, value_name = "FILE")]
+    pub policy: Option<std::path::PathBuf>,
+
+    /// Pipe each passphrase through the shell command CMD and output what it prints instead
+    /// (without its trailing newline), e.g. "sed 's/$/-acme/'". Policies are checked against
+    /// the transformed passphrase
+    #[clap(long, value_parser, value_name = "CMD")]
+    pub transform_cmd: Option<String>,
+
+    /// Bits of entropy the transform command loses, as a negative number, e.g. '-8' for a command
+    /// truncating passphrases. A command can't prove it adds randomness, so positive values are
+    /// refused. Entropies are reported with this adjustment
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 0.0,
+        value_name = "BITS",
+        requires = "transform-cmd",
+        allow_hyphen_values = true
+    )]
+    pub transform_entropy: f64,
+
+    /// Generate each passphrase from its own seeded random stream, and append a line to FILE for
+    /// each with its commitment (a hash of the seed and chain) but nothing about the passphrase
+    #[clap(long, value_parser, value_name = "FILE")]
+    pub audit: Option<std::path::PathBuf>,
+
+    /// Append each passphrase's audit seed and commitment to FILE, for 'markovpass attest'. The
+    /// seeds determine the passphrases, so keep FILE just as secret
+    #[clap(long, value_parser, value_name = "FILE", requires = "audit")]
+    pub audit_seeds: Option<std::path::PathBuf>,
+
+    /// Append a JSON line to FILE for each run, with the time, number of passphrases, entropy
+    /// targets, model fingerprint and exit status, but nothing about the passphrases
+    #[clap(long, value_parser, value_name = "FILE")]
+    pub audit_log: Option<std::path::PathBuf>,
+
+    /// Generate N passphrases for each one output and keep the best scoring. This costs log2(N)
+    /// bits, so passphrases get longer to keep the minimum entropy
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 1,
+        value_name = "N",
+        requires = "score"
+    )]
+    pub best_of: usize,
+
+    /// Score passphrases with NAME for --best-of, weighted by WEIGHT (default 1) if given more
+    /// than once. Names: pronounceable, typing, short
+    #[clap(long, value_parser, value_name = "NAME[=WEIGHT]", requires = "best-of")]
+    pub score: Vec<WeightedScorer>,
+
+    /// Skip the checks for parameters which can't give useful output, like '-n 0' or a minimum
+    /// entropy needing passphrases thousands of characters long
+    #[clap(long, value_parser, default_value_t = false)]
+    pub force: bool,
+
+    /// Load the corpus or model, build the chain and check the options without generating
+    /// anything, then print a summary of the chain
+    #[clap(long, value_parser, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Train briefly at each ngram length from 2 to 7 on a sample of the corpus, print how the
+    /// entropy per character and naturalness of the words compare, and suggest a length for '-l'
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        conflicts_with_all = &["model", "dry-run"]
+    )]
+    pub suggest_params: bool,
+
+    /// Refuse minimum entropies needing passphrases longer than about N characters from this
+    /// chain
+    #[clap(long, value_parser, default_value_t = 256, value_name = "N")]
+    pub max_expected_length: usize,
+
+    /// Make passphrases meet the minimum entropy even for an attacker who ignores case, and word
+    /// boundaries when there's no separator. Without this, passphrases which don't are warned
+    /// about
+    #[clap(long, value_parser, default_value_t = false)]
+    pub enforce_normalized_entropy: bool,
+
+    /// Give up after rejecting this many candidates in a row for any one passphrase
+    #[clap(long, value_parser, value_name = "N", default_value_t = 1000)]
+    pub max_retries: usize,
+
+    /// Print a summary of how many candidates were rejected, and why, to stderr
+    #[clap(long, value_parser, default_value_t = false)]
+    pub count_retries: bool,
+
+    /// Print a line of statistics about the whole run to stderr: passphrases generated, their
+    /// entropy and length, rejected candidates, and time taken
+    #[clap(long, value_parser, default_value_t = false)]
+    pub summary: bool,
+
+    /// Print a memorization hint sentence of corpus words to stderr after each passphrase. The
+    /// hint gives away much of the passphrase, so keep it just as secret
+    #[clap(long, value_parser, default_value_t = false, conflicts_with = "model")]
+    pub hint: bool,
+
+    /// Print a NATO phonetic alphabet read-out of each passphrase to stderr, for dictating it
+    #[clap(long, value_parser, default_value_t = false)]
+    pub nato: bool,
+
+    /// Refuse to write passphrases anywhere but a terminal or pipe, refuse everything '--no-disk'
+    /// does, disable hints and read-outs, and wipe passphrases from memory once they're written
+    #[clap(long, value_parser, default_value_t = false, conflicts_with_all = &["hint", "nato"])]
+    pub paranoid: bool,
+
+    /// Refuse options which write files (audit logs, saved models, downloads, output commands
+    /// and credentials) and output redirected to a file, so nothing generated touches the disk
+    #[clap(long, value_parser, default_value_t = false)]
+    pub no_disk: bool,
+
+    /// Generate one passphrase and show it on the terminal only once a key is pressed, clearing
+    /// it again after '--confirm-timeout' or another key press, for shared screens
+    #[clap(long, value_parser, default_value_t = false, conflicts_with_all = &["paranoid", "hint", "nato", "format"])]
+    pub confirm: bool,
+
+    /// How many seconds '--confirm' shows the passphrase for
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 30,
+        value_name = "SECONDS",
+        requires = "confirm"
+    )]
+    pub confirm_timeout: u64,
+
+    /// Encrypt the passphrase with 'systemd-creds' as the credential NAME instead of printing it
+    #[cfg(all(target_os = "linux", feature = "systemd-creds"))]
+    #[clap(long, value_parser, value_name = "NAME")]
+    pub systemd_cred: Option<String>,
+
+    /// Where to write the encrypted credential [default: NAME.cred]
+    #[cfg(all(target_os = "linux", feature = "systemd-creds"))]
+    #[clap(long, value_parser, requires = "systemd-cred")]
+    pub systemd_cred_output: Option<std::path::PathBuf>,
+}
+
+/// Generate the one passphrase for commands which store it somewhere rather than printing it.
+pub fn single_passphrase(
+    args: &GenerateArgs,
+    chain: &markovpass::PassphraseMarkovChain,
+    options: &markovpass::GenPassphraseOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if args.number != 1 || args.min_entropy.len() != 1 {
+        return Err("Only one passphrase can be stored or confirmed at a time.".into());
+    }
+    let (passphrase, _) = markovpass::gen_passphrases_from_chain(chain, options)?
+        .pop()
+        .unwrap();
+
+    Ok(passphrase)
+}
+
+/// Entropies as shown after text passphrases: Shannon entropy bare, and min-entropy after "min".
+fn format_entropies(entropies: &[(markovpass::EntropyMeasure, f64)]) -> String {
+    let formatted: Vec<String> = entropies
+        .iter()
+        .map(|&(measure, value)| match measure {
+            markovpass::EntropyMeasure::Shannon => value.to_string(),
+            markovpass::EntropyMeasure::Min => format!("min {}", value),
+        })
+        .collect();
+
+    formatted.join(", ")
+}
+
+/// The longest passphrase, in characters, worth generating without '--force'.
+const MAX_FEASIBLE_LENGTH: usize = 1000;
+
+impl GenerateArgs {
+    /// The entropy measures to show, if entropy should be shown at all.
+    fn entropy_measures(&self) -> Option<Vec<markovpass::EntropyMeasure>> {
+        use markovpass::EntropyMeasure::{Min, Shannon};
+
+        self.show_entropy.as_deref().map(|measure| match measure {
+            "min" => vec![Min],
+            "both" => vec![Shannon, Min],
+            _ => vec![Shannon],
+        })
+    }
+
+    /// Reject parameters which would give nothing, or take effectively forever, unless forced.
+    pub fn check_feasible(&self, chain: &markovpass::PassphraseMarkovChain) -> CliResult {
+        for &min_entropy in &self.min_entropy {
+            if !min_entropy.is_finite() {
+                return Err(tr_format("Invalid minimum entropy {}.", &[&min_entropy]).into());
+            }
+        }
+        if self.force {
+            return Ok(());
+        }
+        if self.number == 0 {
+            return Err("Nothing to generate with '-n 0'. Use --force to allow it.".into());
+        }
+        for &min_entropy in &self.min_entropy {
+            if min_entropy <= 0.0 {
+                return Err(tr_format(
+                    "A minimum entropy of {} bits gives passphrases anyone could guess. Use \
+                     --force to allow it.",
+                    &[&min_entropy],
+                )
+                .into());
+            }
+            let max_entropy = chain.max_entropy(MAX_FEASIBLE_LENGTH);
+            if min_entropy > max_entropy {
+                return Err(tr_format(
+                    "A minimum entropy of {} bits needs passphrases over {} characters long: this \
+                     chain gives at most {} bits at that length. Use --force to try anyway.",
+                    &[
+                        &min_entropy,
+                        &MAX_FEASIBLE_LENGTH,
+                        &format!("{:.0}", max_entropy),
+                    ],
+                )
+                .into());
+            }
+            let length = chain.expected_length(min_entropy);
+            if length > self.max_expected_length as f64 {
+                return Err(tr_format(
+                    "A minimum entropy of {} bits needs passphrases of about {} characters \
+                     from this chain. Lower -e, raise --max-expected-length (currently {}), or \
+                     use --force.",
+                    &[
+                        &min_entropy,
+                        &format!("{:.0}", length),
+                        &self.max_expected_length,
+                    ],
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refuse anything which could leave passphrases (or what they're generated from) on disk
+    /// with '--no-disk' or '--paranoid'.
+    pub fn check_no_disk(&self) -> CliResult {
+        let flag = match (self.no_disk, self.paranoid) {
+            (true, _) => "--no-disk",
+            (false, true) => "--paranoid",
+            (false, false) => return Ok(()),
+        };
+        let mut writers = vec![];
+        if self.audit.is_some() {
+            writers.push("--audit");
+        }
+        if self.audit_seeds.is_some() {
+            writers.push("--audit-seeds");
+        }
+        if self.audit_log.is_some() {
+            writers.push("--audit-log");
+        }
+        if self.chain.save_model.is_some() {
+            writers.push("--save-model");
+        }
+        // Commands can do anything with the passphrases they're given.
+        if self.transform_cmd.is_some() {
+            writers.push("--transform-cmd");
+        }
+        #[cfg(all(target_os = "linux", feature = "systemd-creds"))]
+        if self.systemd_cred.is_some() {
+            writers.push("--systemd-cred");
+        }
+        // Downloads are cached on disk.
+        if self
+            .corpus
+            .files
+            .iter()
+            .any(|file| markovpass::download::is_url(file))
+        {
+            writers.push("corpus URLs");
+        }
+        if !writers.is_empty() {
+            return Err(tr_format("{} doesn't allow {}.", &[&flag, &writers.join(", ")]).into());
+        }
+        // Confirmed passphrases only go to the terminal.
+        match self.confirm {
+            true => Ok(()),
+            false => check_stdout_not_file(&format!("with {}", flag)),
+        }
+    }
+
+    pub fn gen_passphrase_options(
+        &self,
+    ) -> Result<markovpass::GenPassphraseOptions, Box<dyn std::error::Error>> {
+        // Generation would aim for fewer bits from the chain, trusting the command's randomness.
+        if self.transform_entropy > 0.0 {
+            return Err(
+                "--transform-entropy can only lower entropy, so it can't be positive.".into(),
+            );
+        }
+
+        let options = self.chain.gen_passphrase_options(&self.corpus)?;
+        let reject_words = if self.no_corpus_words {
+            if options.files.is_empty() && !options.embedded_corpus {
+                return Err("--no-corpus-words needs corpus files to check words against.".into());
+            }
+            let filter = markovpass::corpus_word_filter(&options, 0.001)?;
+            Some(std::sync::Arc::new(filter))
+        } else {
+            None
+        };
+
+        let naturalness = if self.ensemble {
+            if options.files.is_empty() && !options.embedded_corpus {
+                return Err("--ensemble needs corpus files to train on.".into());
+            }
+            if !(0.0..=100.0).contains(&self.ensemble_percentile) {
+                return Err("--ensemble-percentile must be between 0 and 100.".into());
+            }
+            let ensemble = markovpass::corpus_ensemble(&options, self.ensemble_percentile)?;
+            Some(std::sync::Arc::new(ensemble))
+        } else {
+            None
+        };
+
+        let mut reject_substrings: Option<markovpass::attacker::CommonSubstrings> = None;
+        for path in &self.attacker_corpus {
+            let substrings = markovpass::attacker::CommonSubstrings::read(
+                std::fs::File::open(path)
+                    .map_err(|error| format!("{}: {}", path.display(), error))?,
+                self.attacker_substring_length as usize,
+                self.attacker_top,
+            )?;
+            match &mut reject_substrings {
+                Some(all) => all.merge(substrings),
+                None => reject_substrings = Some(substrings),
+            }
+        }
+
+        Ok(markovpass::GenPassphraseOptions {
+            number: self.number,
+            min_entropy: self.min_entropy[0],
+            max_entropy: self.max_entropy,
+            separator: self.separator.clone(),
+            style: self.style,
+            max_consonants: self.max_consonants,
+            min_output_word_length: self.min_output_word_length,
+            reject_words,
+            reject_substrings: reject_substrings.map(std::sync::Arc::new),
+            naturalness,
+            max_retries: self.max_retries,
+            enforce_normalized_entropy: self.enforce_normalized_entropy,
+            no_homoglyphs: self.no_homoglyphs,
+            exclude_chars: self.exclude_chars.clone(),
+            policy: self
+                .policy
+                .as_deref()
+                .map(markovpass::policy::Policy::load)
+                .transpose()?,
+            audit: self.audit.is_some(),
+            transform: self.transform_cmd.as_ref().map(|command| {
+                std::sync::Arc::new(CommandTransform {
+                    command: command.clone(),
+                    entropy_adjustment: self.transform_entropy,
+                }) as std::sync::Arc<dyn markovpass::transform::OutputTransform>
+            }),
+            scorer: match self.score.is_empty() {
+                true => None,
+                false => Some(std::sync::Arc::new(markovpass::scoring::Weighted(
+                    self.score
+                        .iter()
+                        .map(|score| (score.weight, score.kind.scorer()))
+                        .collect(),
+                ))),
+            },
+            best_of: self.best_of,
+            ..options
+        })
+    }
+}
+
+/// A built-in scorer with its weight, as given to --score.
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedScorer {
+    kind: markovpass::scoring::ScorerKind,
+    weight: f64,
+}
+
+impl std::str::FromStr for WeightedScorer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, weight) = match s.split_once('=') {
+            Some((kind, weight)) => {
+                let weight: f64 = weight
+                    .parse()
+                    .map_err(|_| tr_format("Invalid weight '{}'.", &[&weight]))?;
+                if !weight.is_finite() || weight < 0.0 {
+                    return Err(tr_format("Invalid weight '{}'.", &[&weight]));
+                }
+                (kind, weight)
+            }
+            None => (s, 1.0),
+        };
+
+        Ok(Self {
+            kind: kind.parse()?,
+            weight,
+        })
+    }
+}
+
+/// An output transform running a shell command with the passphrase on its stdin.
+#[derive(Debug)]
+struct CommandTransform {
+    command: String,
+    entropy_adjustment: f64,
+}
+
+impl markovpass::transform::OutputTransform for CommandTransform {
+    fn transform(&self, passphrase: &str) -> Result<String, Box<dyn std::error::Error>> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        let mut child = command
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or("Couldn't open the command's stdin")?;
+        writeln!(stdin, "{}", passphrase)?;
+        drop(stdin);
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(tr_format("'{}' exited with {}", &[&self.command, &output.status]).into());
+        }
+        let mut text = String::from_utf8(output.stdout)?;
+        if text.ends_with('\n') {
+            text.pop();
+            if text.ends_with('\r') {
+                text.pop();
+            }
+        }
+
+        Ok(text)
+    }
+
+    fn entropy_adjustment(&self) -> f64 {
+        self.entropy_adjustment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cli;
+    use clap::Parser;
+
+    #[test]
+    fn test_batch_summary() {
+        let mut summary = BatchSummary::default();
+        assert_eq!(summary.to_string(), "Generated 0 passphrase(s)");
+        summary.add("lydia bennet", 61.0);
+        summary.add("darcy", 65.0);
+        assert_eq!(
+            summary.to_string(),
+            "Generated 2 passphrase(s): entropy mean 63.0, min 61.0, max 65.0 bits; mean length 8.5 \
+             characters"
+        );
+    }
+
+    #[test]
+    fn test_format_entropies() {
+        use markovpass::EntropyMeasure::{Min, Shannon};
+
+        assert_eq!(format_entropies(&[(Shannon, 60.5)]), "60.5");
+        assert_eq!(format_entropies(&[(Min, 41.0)]), "min 41");
+        assert_eq!(
+            format_entropies(&[(Shannon, 60.5), (Min, 41.0)]),
+            "60.5, min 41"
+        );
+    }
+
+    #[test]
+    fn test_log_session() {
+        let path = std::env::temp_dir().join(format!("markovpass-log-{}", std::process::id()));
+        let cli = Cli::parse_from(["markovpass", "-e", "50", "-e", "60"]);
+        let session = Session {
+            model: Some("0fdd62e0".to_string()),
+            count: 2,
+        };
+        log_session(&path, &cli.generate, &session, 0).unwrap();
+        log_session(&path, &cli.generate, &Session::default(), 1).unwrap();
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"time\": \""));
+        assert!(lines[0].ends_with(
+            "\"command\": \"generate\", \"count\": 2, \"min_entropy\": [50.0, 60.0], \
+             \"model\": \"0fdd62e0\", \"exit_status\": 0}"
+        ));
+        assert!(lines[1].ends_with(
+            "\"count\": 0, \"min_entropy\": [50.0, 60.0], \"model\": null, \"exit_status\": 1}"
+        ));
+    }
+
+    #[test]
+    fn test_check_no_disk() {
+        let check = |args: &[&str]| {
+            let cli = Cli::parse_from(["markovpass"].iter().chain(args));
+            cli.generate
+                .check_no_disk()
+                .map_err(|error| error.to_string())
+        };
+        for flag in ["--paranoid", "--no-disk"] {
+            let error = check(&[flag, "--audit-seeds", "seeds", "--audit", "audit"]).unwrap_err();
+            assert_eq!(
+                error,
+                format!("{} doesn't allow --audit, --audit-seeds.", flag)
+            );
+            let error = check(&[flag, "https://example.com/corpus.txt"]).unwrap_err();
+            assert!(error.contains("corpus URLs"));
+        }
+        assert!(check(&["--audit-seeds", "seeds", "--audit", "audit"]).is_ok());
+    }
+
+    #[test]
+    fn test_positive_transform_entropy() {
+        let cli = Cli::parse_from([
+            "markovpass",
+            "--transform-cmd",
+            "sed s/$/-acme/",
+            "--transform-entropy",
+            "30",
+        ]);
+        let error = cli.generate.gen_passphrase_options().unwrap_err();
+        assert!(error.to_string().contains("can't be positive"));
+    }
+}
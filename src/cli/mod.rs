@@ -0,0 +1,287 @@
+//! The command line interface: a module for each group of subcommands, and the helpers they
+//! share for messages, translations and interrupts.
+
+pub mod corpus;
+pub mod data_dir;
+pub mod generate;
+pub mod model;
+pub mod records;
+pub mod secrets;
+pub mod tools;
+pub mod train;
+
+use markovpass::output::Color;
+
+/// How to render messages on stderr.
+pub fn stderr_renderer() -> &'static markovpass::output::Renderer {
+    static RENDERER: std::sync::OnceLock<markovpass::output::Renderer> = std::sync::OnceLock::new();
+    RENDERER.get_or_init(markovpass::output::Renderer::stderr)
+}
+
+/// Clap picks colors for help and usage errors itself unless the environment says otherwise.
+pub fn clap_color() -> clap::ColorChoice {
+    let var = |name: &str| std::env::var(name).ok();
+    if !markovpass::output::use_color(true, var) {
+        clap::ColorChoice::Never
+    } else if markovpass::output::use_color(false, var) {
+        clap::ColorChoice::Always
+    } else {
+        clap::ColorChoice::Auto
+    }
+}
+
+/// Translations of messages and help for the user's locale.
+pub static CATALOG: std::sync::OnceLock<markovpass::i18n::Catalog> = std::sync::OnceLock::new();
+
+/// Directories to look for message catalogs in: `$MARKOVPASS_LOCALEDIR`, then the `locale`
+/// directory of each data directory (e.g. '/usr/share/locale').
+pub fn load_catalog() -> Result<markovpass::i18n::Catalog, Box<dyn std::error::Error>> {
+    let languages = markovpass::i18n::languages_from_env();
+    if languages.is_empty() {
+        return Ok(Default::default());
+    }
+    let mut dirs: Vec<std::path::PathBuf> = std::env::var_os("MARKOVPASS_LOCALEDIR")
+        .map(|dir| vec![dir.into()])
+        .unwrap_or_default();
+    if let Some(base_dirs) = directories::BaseDirs::new() {
+        dirs.push(base_dirs.data_dir().join("locale"));
+    }
+    if cfg!(target_os = "linux") {
+        dirs.extend(
+            std::env::var("XDG_DATA_DIRS")
+                .unwrap_or_else(|_| "/usr/local/share/:/usr/share/".to_string())
+                .split(':')
+                .map(|s| std::path::PathBuf::from(s).join("locale")),
+        );
+    }
+
+    Ok(markovpass::i18n::Catalog::find(&languages, &dirs)?.unwrap_or_default())
+}
+
+/// The already formatted `message` translated for the user's locale. Messages formatted here
+/// should use `tr_format` instead, so their templates are what's translated.
+pub fn tr(message: &str) -> std::borrow::Cow<'_, str> {
+    match CATALOG.get() {
+        Some(catalog) => catalog.translate(message),
+        None => message.into(),
+    }
+}
+
+/// The template `template` translated for the user's locale, with `args` substituted for its
+/// `{}`s.
+fn tr_format(template: &str, args: &[&dyn std::fmt::Display]) -> String {
+    match CATALOG.get() {
+        Some(catalog) => catalog.format(template, args),
+        None => markovpass::i18n::Catalog::default().format(template, args),
+    }
+}
+
+/// Like `tr`, for the `'static` strings clap needs. Each translation is leaked once and reused.
+fn tr_static(message: &'static str) -> &'static str {
+    type Translations = std::collections::HashMap<&'static str, &'static str>;
+    static TRANSLATIONS: std::sync::OnceLock<std::sync::Mutex<Translations>> =
+        std::sync::OnceLock::new();
+    let mut translations = TRANSLATIONS.get_or_init(Default::default).lock().unwrap();
+    translations
+        .entry(message)
+        .or_insert_with(|| match tr(message) {
+            std::borrow::Cow::Borrowed(message) => message,
+            std::borrow::Cow::Owned(translation) => Box::leak(translation.into_boxed_str()),
+        })
+}
+
+/// Translate the descriptions and argument help of `command` and its subcommands.
+pub fn localize_command(mut command: clap::Command<'static>) -> clap::Command<'static> {
+    if CATALOG.get().is_none_or(|catalog| catalog.is_empty()) {
+        return command;
+    }
+    if let Some(about) = command.get_about() {
+        command = command.about(tr_static(about));
+    }
+    if let Some(long_about) = command.get_long_about() {
+        command = command.long_about(tr_static(long_about));
+    }
+    // clap generates the help and version flags itself when it builds the command.
+    let ids: Vec<&'static str> = command
+        .get_arguments()
+        .map(|arg| arg.get_id())
+        .filter(|&id| id != "help" && id != "version")
+        .collect();
+    for id in ids {
+        command = command.mut_arg(id, |mut arg| {
+            if let Some(help) = arg.get_help() {
+                arg = arg.help(tr_static(help));
+            }
+            if let Some(long_help) = arg.get_long_help() {
+                arg = arg.long_help(tr_static(long_help));
+            }
+            arg
+        });
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in &names {
+        command = command.mut_subcommand(name.as_str(), localize_command);
+    }
+
+    command
+}
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+/// Whether to suppress warnings and progress messages.
+///
+/// Everything else written to stderr is either an error or explicitly requested, and stdout only
+/// ever carries the requested output.
+pub static QUIET: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Set by the SIGINT handler once `catch_interrupts` has installed it.
+static INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// The exit status after stopping early for Ctrl-C, as shells report for processes it kills.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Make Ctrl-C set `INTERRUPTED` instead of killing the process, so whatever is being written
+/// can be finished first.
+#[cfg(unix)]
+fn catch_interrupts() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    // Safety: sigaction is plain old data, and the handler only stores to an atomic.
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = handler as libc::sighandler_t;
+    // Without SA_RESTART blocking reads fail with EINTR, so they can check `interrupted`.
+    action.sa_flags = 0;
+    unsafe {
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_interrupts() {}
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// `text` as a double quoted JSON string.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// `bytes` in B, kB or MB.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=999 => format!("{} B", bytes),
+        1_000..=999_999 => format!("{:.1} kB", bytes as f64 / 1e3),
+        _ => format!("{:.1} MB", bytes as f64 / 1e6),
+    }
+}
+
+/// The current time in seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Format seconds since the Unix epoch as a UTC date and time.
+fn format_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp % 86400;
+    format!(
+        "{} {:02}:{:02}:{:02} UTC",
+        format_date(timestamp),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The UTC date of `timestamp` as YYYY-MM-DD.
+fn format_date(timestamp: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date in the proleptic Gregorian
+/// calendar.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Warn about the template `message` with `args` substituted, translated.
+fn warn(message: &str, args: &[&dyn std::fmt::Display]) {
+    if !QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+        let renderer = stderr_renderer();
+        let prefix = tr("Warning:");
+        let text = renderer.wrap(&format!("{} {}", prefix, tr_format(message, args)));
+        match text.strip_prefix(&*prefix) {
+            Some(rest) => eprintln!("{}{}", renderer.paint(&prefix, Color::Yellow), rest),
+            None => eprintln!("{}", text),
+        }
+    }
+}
+
+/// Report progress with the template `message`, like `warn`.
+fn progress(message: &str, args: &[&dyn std::fmt::Display]) {
+    if !QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+        eprintln!("{}", tr_format(message, args));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1792000000), "2026-10-14 17:46:40 UTC");
+        assert_eq!(format_date(1792000000 + 30 * 86400), "2026-11-13");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("plain"), "\"plain\"");
+        assert_eq!(quote("a \"b\"\\c\n\u{7}"), r#""a \"b\"\\c\n\u0007""#);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(999), "999 B");
+        assert_eq!(format_size(71_876), "71.9 kB");
+        assert_eq!(format_size(104_857_600), "104.9 MB");
+    }
+}
@@ -0,0 +1,130 @@
+//! The 'model' subcommands for inspecting model files.
+
+use crate::cli::{format_size, format_timestamp, warn, CliResult};
+use clap::Subcommand;
+
+pub fn model_info(file: &std::path::Path) -> CliResult {
+    let model = markovpass::model::Model::load(file)?;
+    let metadata = model.metadata();
+    println!("Format version:      {}", metadata.format_version);
+    println!("markovpass version:  {}", metadata.crate_version);
+    println!(
+        "Created:             {}",
+        format_timestamp(metadata.created)
+    );
+    println!("Ngram length:        {}", metadata.ngram_length);
+    if metadata.stride != markovpass::corpus::Stride::default() {
+        let reset = match metadata.stride.reset_at_words {
+            true => " (reset at words)",
+            false => "",
+        };
+        println!("Ngram step:          {}{}", metadata.stride.step, reset);
+    }
+    println!("Minimum word length: {}", metadata.min_word_length);
+    match &metadata.corpus_options {
+        Some(options) => print_corpus_options(options),
+        None => println!("Corpus options:      not recorded"),
+    }
+    println!("Nodes:               {}", model.node_count());
+    let size = std::fs::metadata(file)?.len();
+    let serialized_size = model.serialized_size();
+    match size == serialized_size {
+        true => println!("Size:                {}", format_size(size)),
+        false => println!(
+            "Size:                {} ({} uncompressed)",
+            format_size(size),
+            format_size(serialized_size)
+        ),
+    }
+    println!("Total entropy:       {:.2} bits", metadata.total_entropy);
+    if let Some(quantization) = &metadata.quantization {
+        println!(
+            "Quantization:        {} bits (entropy within {:.4} bits per character)",
+            quantization.bits, quantization.max_entropy_error
+        );
+    }
+    println!("Corpus files:");
+    for file in &metadata.corpus_files {
+        println!("    {}  {}", file.sha256, file.name);
+    }
+
+    Ok(())
+}
+
+/// Print the corpus options a model was trained with, aligned with the rest of `model_info`.
+fn print_corpus_options(options: &markovpass::model::CorpusOptions) {
+    let flags: Vec<&str> = [
+        (options.spell_numbers, "spell numbers"),
+        (options.preserve_case, "preserve case"),
+        (options.dedupe_lines, "dedupe lines"),
+        (options.dedupe_sentences, "dedupe sentences"),
+    ]
+    .into_iter()
+    .filter_map(|(set, name)| set.then_some(name))
+    .collect();
+    println!("Apostrophes:         {}", options.apostrophes);
+    println!("Digits:              {}", options.digits);
+    match options.script {
+        Some(script) => println!("Script:              {}", script),
+        None => println!("Script:              any"),
+    }
+    match options.encoding {
+        Some(encoding) => println!("Encoding:            {}", encoding),
+        None => println!("Encoding:            detected"),
+    }
+    if !options.exclude_chars.is_empty() {
+        println!("Excluded characters: {}", options.exclude_chars);
+    }
+    if !flags.is_empty() {
+        println!("Cleaning:            {}", flags.join(", "));
+    }
+}
+
+pub fn model_diff(first: &std::path::Path, second: &std::path::Path) -> CliResult {
+    let first = markovpass::model::Model::load(first)?;
+    let second = markovpass::model::Model::load(second)?;
+    if first.ngram_length() != second.ngram_length() {
+        warn(
+            "The models have different ngram lengths, so they share no ngrams.",
+            &[],
+        );
+    }
+    let diff = first.diff(&second);
+    println!(
+        "Nodes:               {} / {}",
+        diff.node_counts.0, diff.node_counts.1
+    );
+    println!(
+        "Total entropy:       {:.2} / {:.2} bits",
+        diff.total_entropies.0, diff.total_entropies.1
+    );
+    println!(
+        "Shared ngrams:       {} of {} ({:.1}% overlap)",
+        diff.shared_ngrams,
+        diff.vocabulary,
+        diff.vocabulary_overlap() * 100.0
+    );
+    println!(
+        "KL divergence:       {:.4} bits (first from second), {:.4} bits (second from first)",
+        diff.kl_divergences.0, diff.kl_divergences.1
+    );
+
+    Ok(())
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ModelCommand {
+    /// Show the metadata recorded in a model file
+    Info {
+        /// Model file to inspect
+        #[clap(value_parser)]
+        file: std::path::PathBuf,
+    },
+    /// Compare two model files
+    Diff {
+        #[clap(value_parser)]
+        first: std::path::PathBuf,
+        #[clap(value_parser)]
+        second: std::path::PathBuf,
+    },
+}
@@ -0,0 +1,209 @@
+//! Structured output formats for generated passphrases.
+
+use crate::cli::{quote, tr_format, CliResult};
+
+/// How `generate` writes passphrases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Toml,
+    Yaml,
+    Csv,
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+            Self::Toml => write!(f, "toml"),
+            Self::Yaml => write!(f, "yaml"),
+            Self::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "toml" => Ok(Self::Toml),
+            "yaml" => Ok(Self::Yaml),
+            "csv" => Ok(Self::Csv),
+            _ => Err(tr_format("Unknown output format '{}'.", &[&s])),
+        }
+    }
+}
+
+/// A field value in a structured output record.
+pub enum Value<'a> {
+    Str(&'a str),
+    Float(f64),
+    List(&'a [String]),
+}
+
+/// Writes records of named fields as a single JSON, TOML, YAML or CSV document, one record at a
+/// time so each is output as soon as it's ready.
+pub struct RecordWriter {
+    format: OutputFormat,
+    count: usize,
+}
+
+impl RecordWriter {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format, count: 0 }
+    }
+
+    pub fn write(&mut self, out: &mut impl std::io::Write, fields: &[(&str, Value)]) -> CliResult {
+        if self.format == OutputFormat::Csv {
+            return self.write_csv(out, fields);
+        }
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|(name, value)| (name, Self::value(value)))
+            .collect();
+        match self.format {
+            OutputFormat::Text | OutputFormat::Csv => unreachable!("handled above"),
+            OutputFormat::Json => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", quote(name), value))
+                    .collect();
+                let prefix = if self.count == 0 { "[" } else { "," };
+                writeln!(out, "{}", prefix)?;
+                write!(out, "  {{{}}}", fields.join(", "))?;
+            }
+            OutputFormat::Toml => {
+                if self.count > 0 {
+                    writeln!(out)?;
+                }
+                writeln!(out, "[[passphrases]]")?;
+                for (name, value) in fields {
+                    writeln!(out, "{} = {}", name, value)?;
+                }
+            }
+            OutputFormat::Yaml => {
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    let indent = if i == 0 { "- " } else { "  " };
+                    writeln!(out, "{}{}: {}", indent, name, value)?;
+                }
+            }
+        }
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Write a CSV row, after a header row of the field names for the first record. Lists are
+    /// written as one field, separated by spaces.
+    fn write_csv(&mut self, out: &mut impl std::io::Write, fields: &[(&str, Value)]) -> CliResult {
+        if self.count == 0 {
+            let names: Vec<_> = fields.iter().map(|&(name, _)| csv_field(name)).collect();
+            writeln!(out, "{}", names.join(","))?;
+        }
+        let values: Vec<_> = fields
+            .iter()
+            .map(|(_, value)| match value {
+                Value::Str(value) => csv_field(value),
+                Value::Float(value) => format!("{:?}", value),
+                Value::List(values) => csv_field(&values.join(" ")),
+            })
+            .collect();
+        writeln!(out, "{}", values.join(","))?;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// End the document.
+    pub fn finish(&self, out: &mut impl std::io::Write) -> CliResult {
+        match (self.format, self.count) {
+            (OutputFormat::Json, 0) | (OutputFormat::Yaml, 0) => writeln!(out, "[]")?,
+            (OutputFormat::Json, _) => writeln!(out, "\n]")?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// The value as it's written in every format. JSON's syntax for these values is valid TOML
+    /// and YAML too.
+    fn value(value: &Value) -> String {
+        match value {
+            Value::Str(value) => quote(value),
+            Value::Float(value) => format!("{:?}", value),
+            Value::List(values) => {
+                let values: Vec<_> = values.iter().map(|value| quote(value)).collect();
+                format!("[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+/// `text` as a CSV field, quoted if it needs to be.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) || text.trim() != text {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_writer() {
+        let write = |format, count| {
+            let words = vec!["lydia".to_string(), "bennet".to_string()];
+            let mut records = RecordWriter::new(format);
+            let mut out = vec![];
+            for _ in 0..count {
+                let fields = [
+                    ("passphrase", Value::Str("lydia bennet")),
+                    ("words", Value::List(&words)),
+                    ("entropy", Value::Float(61.0)),
+                ];
+                records.write(&mut out, &fields).unwrap();
+            }
+            records.finish(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let record =
+            r#""passphrase": "lydia bennet", "words": ["lydia", "bennet"], "entropy": 61.0"#;
+        assert_eq!(
+            write(OutputFormat::Json, 2),
+            format!("[\n  {{{}}},\n  {{{}}}\n]\n", record, record)
+        );
+        assert_eq!(write(OutputFormat::Json, 0), "[]\n");
+        let record =
+            "passphrase = \"lydia bennet\"\nwords = [\"lydia\", \"bennet\"]\nentropy = 61.0\n";
+        assert_eq!(
+            write(OutputFormat::Toml, 2),
+            format!("[[passphrases]]\n{}\n[[passphrases]]\n{}", record, record)
+        );
+        assert_eq!(
+            write(OutputFormat::Yaml, 1),
+            "- passphrase: \"lydia bennet\"\n  words: [\"lydia\", \"bennet\"]\n  entropy: 61.0\n"
+        );
+        assert_eq!(write(OutputFormat::Yaml, 0), "[]\n");
+        assert_eq!(
+            write(OutputFormat::Csv, 2),
+            "passphrase,words,entropy\nlydia bennet,lydia bennet,61.0\nlydia bennet,lydia bennet,61.0\n"
+        );
+        assert_eq!(write(OutputFormat::Csv, 0), "");
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("lydia bennet"), "lydia bennet");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(" padded"), "\" padded\"");
+    }
+}
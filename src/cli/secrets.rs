@@ -0,0 +1,346 @@
+//! Handing passphrases to password managers and credential stores, and keeping them off disk.
+
+use crate::cli::generate::{single_passphrase, GenerateArgs};
+use crate::cli::train::build_chain;
+use crate::cli::{
+    catch_interrupts, interrupted, progress, tr_format, CliResult, INTERRUPTED_EXIT_CODE,
+};
+use markovpass::wipe;
+
+pub fn keepassxc(args: &KeepassxcArgs) -> CliResult {
+    if args.generate.no_disk || args.generate.paranoid {
+        return Err(
+            "--no-disk and --paranoid don't allow storing passphrases in a KeePassXC database."
+                .into(),
+        );
+    }
+    let options = args.generate.gen_passphrase_options()?;
+    let chain = build_chain(&options, args.generate.chain.save_model.as_deref())?;
+    args.generate.check_feasible(&chain)?;
+    let passphrase = single_passphrase(&args.generate, &chain, &options)?;
+    let password = read_password(&tr_format(
+        "Password for {}: ",
+        &[&args.database.to_string_lossy()],
+    ))?;
+    keepassxc_add(&args.database, &args.entry, &password, &passphrase)?;
+    wipe(password);
+    wipe(passphrase);
+    progress("Added entry '{}'.", &[&args.entry]);
+
+    Ok(())
+}
+
+/// Store `passphrase` as the password of a new `entry` in a KeePassXC `database`.
+fn keepassxc_add(
+    database: &std::path::Path,
+    entry: &str,
+    password: &str,
+    passphrase: &str,
+) -> CliResult {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("keepassxc-cli")
+        .arg("add")
+        .arg("--quiet")
+        .arg("--password-prompt")
+        .arg(database)
+        .arg(entry)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|error| tr_format("Failed to run keepassxc-cli: {}", &[&error]))?;
+    // keepassxc-cli reads the database password and then the new entry's password.
+    let mut stdin = child.stdin.take().unwrap();
+    for secret in [password, passphrase] {
+        stdin.write_all(secret.as_bytes())?;
+        stdin.write_all(b"\n")?;
+    }
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(tr_format("keepassxc-cli failed ({}).", &[&status]).into());
+    }
+
+    Ok(())
+}
+
+/// Open `path` for appending, creating it if needed. Private files are only readable by their
+/// owner where that's supported.
+pub fn open_append(path: &std::path::Path, private: bool) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    options.open(path)
+}
+
+/// Prompt for a password on the terminal without echoing it.
+#[cfg(unix)]
+fn read_password(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::{BufRead, Write};
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| "A terminal is needed to prompt for the password.")?;
+    tty.write_all(prompt.as_bytes())?;
+    tty.flush()?;
+
+    let fd = tty.as_raw_fd();
+    // Safety: termios is plain old data and `fd` stays open for the duration of the calls.
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let original = termios;
+    termios.c_lflag &= !libc::ECHO;
+    termios.c_lflag |= libc::ECHONL;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut password = String::new();
+    let result = std::io::BufReader::new(&tty).read_line(&mut password);
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    result?;
+    password.truncate(password.trim_end_matches(['\r', '\n']).len());
+
+    Ok(password)
+}
+
+#[cfg(not(unix))]
+fn read_password(_prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Err("Password prompts are only supported on Unix.".into())
+}
+
+/// Encrypt `passphrase` as the systemd credential `name`, writing it to `output`.
+#[cfg(all(target_os = "linux", feature = "systemd-creds"))]
+pub fn systemd_creds_encrypt(passphrase: &str, name: &str, output: &std::path::Path) -> CliResult {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("systemd-creds")
+        .arg("encrypt")
+        .arg(format!("--name={}", name))
+        .arg("-")
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|error| tr_format("Failed to run systemd-creds: {}", &[&error]))?;
+    // Take stdin so it's closed once the passphrase is written.
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(passphrase.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(tr_format("systemd-creds failed ({}).", &[&status]).into());
+    }
+
+    Ok(())
+}
+
+/// Check that stdout is a terminal or pipe rather than a file passphrases would be left in,
+/// explaining why passphrases can't go to a file with `reason`.
+pub fn check_stdout_not_file(reason: &str) -> CliResult {
+    use std::io::IsTerminal;
+
+    if std::io::stdout().is_terminal() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::io::AsFd;
+
+        let stdout = std::fs::File::from(std::io::stdout().as_fd().try_clone_to_owned()?);
+        let file_type = stdout.metadata()?.file_type();
+        if file_type.is_fifo() || file_type.is_socket() || file_type.is_char_device() {
+            return Ok(());
+        }
+    }
+
+    Err(tr_format("Refusing to write passphrases to a file {}.", &[&reason]).into())
+}
+
+/// Write a passphrase straight to stdout, bypassing the formatting buffers of `println!`.
+pub fn write_paranoid(passphrase: &str, entropy: Option<&str>) -> CliResult {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(passphrase.as_bytes())?;
+    if let Some(entropy) = entropy {
+        write!(stdout, " <{}>", entropy)?;
+    }
+    stdout.write_all(b"\n")?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Show `passphrase` on the terminal once a key is pressed, then clear it again after `timeout`
+/// or another key press. Nothing is written to stdout. Ctrl-C exits without ever showing it, or
+/// hides it straight away.
+#[cfg(unix)]
+pub fn confirm_passphrase(passphrase: String, timeout: std::time::Duration) -> CliResult {
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| "A terminal is needed to confirm the passphrase.")?;
+    let fd = tty.as_raw_fd();
+    // Safety: termios is plain old data and `fd` stays open for the duration of the calls.
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let original = termios;
+    // Read single unechoed key presses, with Ctrl-C read as a key rather than sending SIGINT.
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // A SIGINT from elsewhere interrupts the waits too.
+    catch_interrupts();
+    let width = markovpass::output::terminal_width().unwrap_or(markovpass::output::DEFAULT_WIDTH);
+    let rows = passphrase.chars().count().max(1).div_ceil(width);
+    let mut shown = false;
+    let abort_key = original.c_cc[libc::VINTR];
+    let result = show_passphrase(&mut tty, &passphrase, timeout, abort_key, &mut shown);
+    // Clear the prompt or every row of the passphrase, even after an error.
+    let mut clear = String::from("\r");
+    if shown {
+        clear.push_str(&"\x1b[1A".repeat(rows - 1));
+    }
+    clear.push_str("\x1b[J");
+    let _ = tty.write_all(clear.as_bytes());
+    let _ = tty.flush();
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    wipe(passphrase);
+    if interrupted() || matches!(result, Ok(true)) {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    result?;
+
+    Ok(())
+}
+
+/// Prompt on `tty`, show `passphrase` once a key is pressed, and wait for `timeout` or another
+/// key. Returns whether the user aborted with `abort_key` (or SIGINT), in which case the
+/// passphrase is never shown after it. `shown` says whether it was shown, to clear it.
+#[cfg(unix)]
+fn show_passphrase<T>(
+    tty: &mut T,
+    passphrase: &str,
+    timeout: std::time::Duration,
+    abort_key: u8,
+    shown: &mut bool,
+) -> std::io::Result<bool>
+where
+    T: std::io::Read + std::io::Write + std::os::unix::io::AsRawFd,
+{
+    // A key press, or `None` for the abort key or an interrupt.
+    let read_key = |tty: &mut T| -> std::io::Result<Option<u8>> {
+        let mut key = [0; 1];
+        loop {
+            match tty.read(&mut key) {
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => return Ok(Some(key[0]).filter(|&key| key != abort_key)),
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {
+                    if interrupted() {
+                        return Ok(None);
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    };
+    tty.write_all(b"Press any key to show the passphrase...")?;
+    tty.flush()?;
+    if read_key(tty)?.is_none() {
+        return Ok(true);
+    }
+    write!(tty, "\r\x1b[2K{}", passphrase)?;
+    tty.flush()?;
+    *shown = true;
+    let mut poll_fd = libc::pollfd {
+        fd: tty.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    // Safety: `poll_fd` is a single valid pollfd.
+    match unsafe { libc::poll(&mut poll_fd, 1, millis) } {
+        ready if ready > 0 => Ok(read_key(tty)?.is_none()),
+        _ => Ok(interrupted()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn confirm_passphrase(_passphrase: String, _timeout: std::time::Duration) -> CliResult {
+    Err("--confirm is only supported on Unix.".into())
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct KeepassxcArgs {
+    /// The KeePassXC database to add the passphrase to
+    #[clap(long, value_parser)]
+    pub database: std::path::PathBuf,
+
+    /// The title of the new entry
+    #[clap(long, value_parser)]
+    pub entry: String,
+
+    #[clap(flatten)]
+    pub generate: GenerateArgs,
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    /// What `show_passphrase` writes to a terminal which sends `keys`, and what it returns.
+    fn show_with_keys(keys: &[u8]) -> (String, bool, bool) {
+        let (mut tty, mut terminal) = std::os::unix::net::UnixStream::pair().unwrap();
+        terminal.write_all(keys).unwrap();
+        let timeout = std::time::Duration::from_secs(5);
+        let mut shown = false;
+        let aborted = show_passphrase(&mut tty, "lydia bennet", timeout, 0x03, &mut shown);
+        tty.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut output = String::new();
+        terminal.read_to_string(&mut output).unwrap();
+
+        (output, aborted.unwrap(), shown)
+    }
+
+    #[test]
+    fn test_show_passphrase() {
+        let (output, aborted, shown) = show_with_keys(b"xy");
+        assert!(output.contains("lydia bennet"));
+        assert!(!aborted && shown);
+
+        // Ctrl-C at the prompt never shows the passphrase.
+        let (output, aborted, shown) = show_with_keys(b"\x03x");
+        assert!(!output.contains("lydia bennet"));
+        assert!(aborted && !shown);
+
+        // Ctrl-C while it's shown hides it and aborts.
+        let (_, aborted, shown) = show_with_keys(b"x\x03");
+        assert!(aborted && shown);
+    }
+}
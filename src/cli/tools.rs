@@ -0,0 +1,568 @@
+//! Subcommands for inspecting corpora and chains and checking passphrases: 'entropy', 'clean',
+//! 'ngrams', 'stats', 'check', 'attest', 'wordlist', 'bench' and 'selftest'.
+
+use crate::cli::corpus::CorpusArgs;
+use crate::cli::generate::GenerateArgs;
+use crate::cli::train::{build_chain, ChainArgs};
+use crate::cli::{tr_format, warn, CliResult};
+use markovpass::wipe;
+
+pub fn entropy(args: &EntropyArgs) -> CliResult {
+    let weights = match (&args.weights, &args.wordlist) {
+        (Some(weights), _) => weights.clone(),
+        (None, Some(path)) => {
+            let contents = std::fs::read_to_string(path)?;
+            vec![1.0; read_wordlist(&contents).len()]
+        }
+        // clap requires one of the two.
+        (None, None) => unreachable!(),
+    };
+    if weights.iter().any(|&weight| weight < 0.0) || weights.iter().all(|&weight| weight == 0.0) {
+        return Err("Weights must be non-negative and not all zero.".into());
+    }
+    let outcomes = weights.iter().filter(|&&weight| weight > 0.0).count();
+    let count = args.count as f64;
+    println!("Outcomes:        {}", outcomes);
+    println!(
+        "Shannon entropy: {:.4} bits",
+        count * markovpass::entropy::shannon_entropy(&weights)
+    );
+    println!(
+        "Min-entropy:     {:.4} bits",
+        count * markovpass::entropy::min_entropy(&weights)
+    );
+
+    Ok(())
+}
+
+/// The distinct words in a wordlist. Only the last field of each line is used, so diceware style
+/// lists with dice rolls before each word work too.
+fn read_wordlist(contents: &str) -> std::collections::BTreeSet<&str> {
+    contents
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .collect()
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct EntropyArgs {
+    /// Comma separated relative weights of each outcome
+    #[clap(
+        long,
+        value_parser,
+        use_value_delimiter = true,
+        required_unless_present = "wordlist",
+        conflicts_with = "wordlist"
+    )]
+    pub weights: Option<Vec<f64>>,
+
+    /// Wordlist file with one word per line to choose from uniformly
+    #[clap(long, value_parser)]
+    pub wordlist: Option<std::path::PathBuf>,
+
+    /// Number of independent choices to make (e.g. words in a diceware passphrase)
+    #[clap(short = 'n', long, value_parser, default_value_t = 1)]
+    pub count: usize,
+}
+
+pub fn clean(args: &CleanArgs) -> CliResult {
+    use std::io::Write;
+
+    let options = args.corpus.gen_passphrase_options(true)?;
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", markovpass::clean_corpus(&options)?)?;
+
+    Ok(())
+}
+
+pub fn ngrams(args: &NgramsArgs) -> CliResult {
+    use std::io::Write;
+
+    let options = args.corpus.gen_passphrase_options(true)?;
+    let show = |ngram: &str| ngram.replace(' ', "_");
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    if args.counts {
+        let mut counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+        markovpass::for_each_corpus_ngram(&options, |ngram| {
+            match counts.get_mut(ngram) {
+                Some(count) => *count += 1,
+                None => {
+                    counts.insert(ngram.to_string(), 1);
+                }
+            }
+            Ok(())
+        })?;
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (ngram, count) in counts {
+            writeln!(out, "{:>8}  {}", count, show(&ngram))?;
+        }
+    } else {
+        markovpass::for_each_corpus_ngram(&options, |ngram| writeln!(out, "{}", show(ngram)))?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+pub fn stats(args: &StatsArgs) -> CliResult {
+    let options = args.chain.gen_passphrase_options(&args.corpus)?;
+    let chain = build_chain(&options, args.chain.save_model.as_deref())?;
+    println!("Nodes:             {}", chain.node_count());
+    println!("Starting entropy:  {:.2} bits", chain.starting_entropy());
+    println!(
+        "Entropy rate:      {:.2} bits per character",
+        chain.entropy_rate()
+    );
+
+    let letters = chain.starting_letters();
+    println!("Starting letters:");
+    for (letter, probability) in &letters {
+        println!("    {}  {:6.2}%", letter, probability * 100.0);
+    }
+    // Check coverage of the alphabet of whichever script passphrases most often start in.
+    let mut scripts: Vec<(markovpass::script::Script, f64)> = vec![];
+    for (&letter, &probability) in &letters {
+        if let Some(script) = markovpass::script::Script::of(letter) {
+            match scripts.iter_mut().find(|(s, _)| *s == script) {
+                Some((_, total)) => *total += probability,
+                None => scripts.push((script, probability)),
+            }
+        }
+    }
+    if let Some(&(script, _)) = scripts.iter().max_by(|a, b| a.1.total_cmp(&b.1)) {
+        let alphabet = script.alphabet();
+        let missing: Vec<char> = alphabet
+            .chars()
+            .filter(|&c| !letters.keys().any(|l| l.to_lowercase().any(|l| l == c)))
+            .collect();
+        if !missing.is_empty() {
+            let missing: Vec<String> = missing.iter().map(char::to_string).collect();
+            println!("Unreachable starting letters: {}", missing.join(" "));
+            if missing.len() * 4 > alphabet.chars().count() {
+                warn(
+                    "{} of the {} {} letters can't start a passphrase.",
+                    &[&missing.len(), &alphabet.chars().count(), &script],
+                );
+            }
+        }
+    }
+
+    if options.files.len() > 1 {
+        println!("Corpus files:");
+        println!("    Ngrams  Unique  Entropy  Marginal  Name");
+        for contribution in markovpass::file_contributions(&options)? {
+            println!(
+                "    {:6}  {:6}  {:7.1}  {:8.1}  {}",
+                contribution.ngrams,
+                contribution.unique_ngrams,
+                contribution.entropy,
+                contribution.marginal_entropy,
+                contribution.file.name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn check(args: &CheckArgs) -> CliResult {
+    let policy = markovpass::policy::Policy::load(&args.policy)?;
+    let passphrase = match &args.passphrase {
+        Some(passphrase) => passphrase.clone(),
+        None => {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+            line.truncate(line.trim_end_matches(['\r', '\n']).len());
+            line
+        }
+    };
+    let violations = policy.violations(&passphrase);
+    wipe(passphrase);
+    if violations.is_empty() {
+        println!("Passphrase complies with the policy.");
+        return Ok(());
+    }
+    for violation in &violations {
+        println!("Policy violation: {}", violation);
+    }
+
+    Err(tr_format("Passphrase breaks {} policy rule(s).", &[&violations.len()]).into())
+}
+
+pub fn attest(args: &AttestArgs) -> CliResult {
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    // Accept lines from an '--audit-seeds' file as well as bare seeds.
+    let seed = line.split_whitespace().next().unwrap_or("");
+    let seed =
+        markovpass::audit::parse_seed(seed).ok_or("Expected a seed of 64 hex digits on stdin.")?;
+    args.generate.check_no_disk()?;
+    let options = markovpass::GenPassphraseOptions {
+        audit: true,
+        ..args.generate.gen_passphrase_options()?
+    };
+    let chain = build_chain(&options, args.generate.chain.save_model.as_deref())?;
+    let commitment = markovpass::audit::commitment(&chain.fingerprint(), &seed);
+    if !commitment.eq_ignore_ascii_case(&args.commitment) {
+        return Err(
+            "The seed and chain don't match the commitment. Check the seed and that the \
+                    corpus or model and chain options match the original run."
+                .into(),
+        );
+    }
+    let passphrase = markovpass::replay_passphrase(&chain, &options, seed)?;
+    println!("{}", passphrase.text);
+
+    Ok(())
+}
+
+pub fn wordlist(args: &WordlistArgs) -> CliResult {
+    use std::io::Write;
+
+    let options = args.chain.gen_passphrase_options(&args.corpus)?;
+    let chain = build_chain(&options, args.chain.save_model.as_deref())?;
+    let seed = match &args.seed {
+        Some(seed) => {
+            markovpass::audit::parse_seed(seed).ok_or("Expected a seed of 64 hex digits.")?
+        }
+        None => {
+            let mut seed = [0; 32];
+            rand::RngCore::fill_bytes(&mut rand::rngs::OsRng, &mut seed);
+            eprintln!("Seed: {}", markovpass::sha256::to_hex(&seed));
+            seed
+        }
+    };
+    if let Some(path) = &args.verify {
+        let manifest: markovpass::wordlist::Manifest = std::fs::read_to_string(path)?.parse()?;
+        let words = manifest.verify(&chain, seed)?;
+        println!(
+            "Wordlist verified: {} words with SHA-256 {}",
+            words.len(),
+            manifest.wordlist_sha256
+        );
+        return Ok(());
+    }
+
+    let wordlist_options = markovpass::wordlist::WordlistOptions {
+        size: args.size,
+        min_length: args.min_length,
+        max_length: args.max_length,
+    };
+    let words = markovpass::wordlist::generate(&chain, &wordlist_options, seed)?;
+    let thresholds = markovpass::wordlist::QualityThresholds {
+        min_edit_distance: args.min_edit_distance,
+        max_unique_prefix_length: args.max_unique_prefix,
+    };
+    if args.analyze || thresholds != Default::default() {
+        let analysis = markovpass::wordlist::analyze(&words);
+        if args.analyze {
+            eprint!("{}", analysis);
+        }
+        analysis.check(&thresholds)?;
+    }
+    if let Some(path) = &args.manifest {
+        let manifest = markovpass::wordlist::Manifest::new(
+            &chain,
+            &wordlist_options,
+            &seed,
+            &words,
+            manifest_chain_options(&options)?,
+        );
+        std::fs::write(path, manifest.to_string())?;
+    }
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for word in &words {
+        writeln!(out, "{}", word)?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+/// The corpus and chain options behind a wordlist, with the corpus files' digests, for its
+/// manifest.
+fn manifest_chain_options(
+    options: &markovpass::GenPassphraseOptions,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let mut chain_options = vec![];
+    let mut add = |key: &str, value: String| chain_options.push((key.to_string(), value));
+    match &options.model {
+        Some(model) => add(
+            "model_file",
+            format!(
+                "{}  {}",
+                markovpass::sha256::file_hex_digest(model)?,
+                model.display()
+            ),
+        ),
+        None => {
+            for file in &options.files {
+                add(
+                    "corpus_file",
+                    format!(
+                        "{}  {}",
+                        markovpass::sha256::file_hex_digest(file)?,
+                        file.display()
+                    ),
+                );
+            }
+            if options.embedded_corpus {
+                for (name, text) in markovpass::EMBEDDED_CORPUS {
+                    let digest = markovpass::sha256::hex_digest(text.as_bytes());
+                    add("corpus_file", format!("{}  {} (embedded)", digest, name));
+                }
+            }
+            add("ngram_length", options.ngram_length.to_string());
+            add("ngram_step", options.ngram_step.to_string());
+            add("reset_at_words", options.reset_at_words.to_string());
+            add(
+                "min_corpus_word_length",
+                options.min_word_length.to_string(),
+            );
+            add("apostrophes", options.apostrophes.to_string());
+            add("digits", options.digits.to_string());
+            add("spell_numbers", options.spell_numbers.to_string());
+            add("preserve_case", options.preserve_case.to_string());
+            add("dedupe_lines", options.dedupe_lines.to_string());
+            add("dedupe_sentences", options.dedupe_sentences.to_string());
+            let script = options
+                .script
+                .map_or("any".to_string(), |script| script.to_string());
+            add("script", script);
+            let encoding = options
+                .encoding
+                .map_or("auto".to_string(), |encoding| encoding.to_string());
+            add("encoding", encoding);
+        }
+    }
+    add("min_node_entropy", options.min_node_entropy.to_string());
+    add("uniform_start", options.uniform_start.to_string());
+    add("start_anywhere", options.start_anywhere.to_string());
+    add("single_start", options.single_start.to_string());
+
+    Ok(chain_options)
+}
+
+pub fn bench(args: &BenchArgs) -> CliResult {
+    let options = markovpass::GenPassphraseOptions {
+        min_entropy: args.min_entropy,
+        number: usize::MAX,
+        ..args.corpus.gen_passphrase_options(true)?
+    };
+    if options.files.is_empty() {
+        return Err("Benchmarking needs corpus files.".into());
+    }
+    let bytes = options
+        .files
+        .iter()
+        .map(|path| Ok(std::fs::metadata(path)?.len()))
+        .sum::<std::io::Result<u64>>()?;
+    let megabytes = bytes as f64 / 1e6;
+
+    let start = std::time::Instant::now();
+    let chain = build_chain(&options, None)?;
+    let training = start.elapsed().as_secs_f64();
+
+    let duration = std::time::Duration::from_secs_f64(args.duration.max(0.0));
+    let start = std::time::Instant::now();
+    let mut generated: u64 = 0;
+    for passphrase in markovpass::iter_passphrases_from_chain(&chain, &options)? {
+        wipe(passphrase?.text);
+        generated += 1;
+        if start.elapsed() >= duration {
+            break;
+        }
+    }
+    let generating = start.elapsed().as_secs_f64();
+
+    println!(
+        "Corpus:          {:.2} MB in {} file(s)",
+        megabytes,
+        options.files.len()
+    );
+    println!("Ngram length:    {}", options.ngram_length);
+    println!("Nodes:           {}", chain.node_count());
+    println!(
+        "Training:        {:.3} s ({:.2} MB/s)",
+        training,
+        megabytes / training
+    );
+    println!(
+        "Generation:      {:.0} passphrases/s at {} bits",
+        generated as f64 / generating,
+        args.min_entropy
+    );
+    match peak_memory() {
+        Some(bytes) => println!("Peak memory:     {:.1} MB", bytes as f64 / 1e6),
+        None => println!("Peak memory:     unavailable"),
+    }
+
+    Ok(())
+}
+
+/// The peak resident memory of the process in bytes, where the platform reports it.
+#[cfg(unix)]
+fn peak_memory() -> Option<u64> {
+    // Safety: rusage is plain old data, filled in by getrusage.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let max_rss = u64::try_from(usage.ru_maxrss).ok()?;
+    // macOS reports bytes, and other systems kilobytes.
+    if cfg!(target_os = "macos") {
+        Some(max_rss)
+    } else {
+        Some(max_rss * 1024)
+    }
+}
+
+#[cfg(not(unix))]
+fn peak_memory() -> Option<u64> {
+    None
+}
+
+pub fn selftest() -> CliResult {
+    let passphrases = markovpass::selftest::run()?;
+    println!(
+        "Self-test passed ({} passphrases checked).",
+        passphrases.len()
+    );
+
+    Ok(())
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StatsArgs {
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    #[clap(flatten)]
+    pub chain: ChainArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct WordlistArgs {
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    #[clap(flatten)]
+    pub chain: ChainArgs,
+
+    /// Number of words (7776 suits five dice)
+    #[clap(long, value_parser, default_value_t = 7776, value_name = "N")]
+    pub size: usize,
+
+    /// Shortest allowed word
+    #[clap(long, value_parser, default_value_t = 3, value_name = "N")]
+    pub min_length: usize,
+
+    /// Longest allowed word
+    #[clap(long, value_parser, default_value_t = 9, value_name = "N")]
+    pub max_length: usize,
+
+    /// Print the list's minimum edit distance, unique prefix length and word length
+    /// distribution to stderr
+    #[clap(long, value_parser, default_value_t = false)]
+    pub analyze: bool,
+
+    /// Fail unless every two words are at least N edits (insertions, deletions or
+    /// substitutions) apart
+    #[clap(long, value_parser, value_name = "N")]
+    pub min_edit_distance: Option<usize>,
+
+    /// Fail unless the first N characters of each word identify it, as for autocompletion
+    #[clap(long, value_parser, value_name = "N")]
+    pub max_unique_prefix: Option<usize>,
+
+    /// Seed to generate the list from, as 64 hex digits. A random seed is used and printed to
+    /// stderr if it's omitted
+    #[clap(long, value_parser, value_name = "HEX")]
+    pub seed: Option<String>,
+
+    /// Write a manifest to FILE recording a commitment to the seed and chain, the list's digest
+    /// and the options used
+    #[clap(long, value_parser, value_name = "FILE")]
+    pub manifest: Option<std::path::PathBuf>,
+
+    /// Instead of printing the list, rebuild it from the seed and check it against the
+    /// manifest FILE
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        requires = "seed",
+        conflicts_with = "manifest"
+    )]
+    pub verify: Option<std::path::PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    /// Minimum entropy of the generated passphrases
+    #[clap(short = 'e', value_parser, default_value_t = 60.0)]
+    pub min_entropy: f64,
+
+    /// How long to spend generating passphrases
+    #[clap(long, value_parser, default_value_t = 2.0, value_name = "SECONDS")]
+    pub duration: f64,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CleanArgs {
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct NgramsArgs {
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    /// Print how often each distinct ngram occurs, most common first, instead of the ngrams in
+    /// order
+    #[clap(long, value_parser, default_value_t = false)]
+    pub counts: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AttestArgs {
+    /// The commitment recorded in the audit log
+    #[clap(long, value_parser, value_name = "HEX")]
+    pub commitment: String,
+
+    /// The corpus or model and generation options of the original run
+    #[clap(flatten)]
+    pub generate: GenerateArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CheckArgs {
+    /// Policy file with 'min_length', 'max_length', 'required_classes' (lowercase, uppercase,
+    /// digit, space, symbol) and 'banned_substrings' keys in TOML syntax
+    #[clap(long, value_parser, value_name = "FILE")]
+    pub policy: std::path::PathBuf,
+
+    /// The passphrase to check. If omitted it's read from stdin, which keeps it out of the
+    /// process list and shell history
+    #[clap(value_parser)]
+    pub passphrase: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_wordlist() {
+        let words = read_wordlist("11111\ta\n11112\tab\n\nab\nabc\n");
+        assert_eq!(
+            words.into_iter().collect::<Vec<_>>(),
+            vec!["a", "ab", "abc"]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Training chains and models: the 'train' subcommand, and the chain options other subcommands
+//! share.
+
+use crate::cli::corpus::CorpusArgs;
+use crate::cli::{catch_interrupts, interrupted, progress, warn, CliResult, INTERRUPTED_EXIT_CODE};
+
+/// Save `model` to `path` without leaving a partial file if interrupted, exiting afterwards.
+fn save_model(model: &markovpass::model::Model, path: &std::path::Path) -> CliResult {
+    catch_interrupts();
+    model.save(path)?;
+    if interrupted() {
+        eprintln!("Interrupted after saving {}", path.display());
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+
+    Ok(())
+}
+
+/// Training memory estimates (in bytes) over this are reported before training.
+const TRAINING_MEMORY_WARNING: u64 = 200_000_000;
+
+/// Train a model, warning about any skipped corpus files.
+fn train_model(
+    options: &markovpass::GenPassphraseOptions,
+) -> Result<markovpass::model::Model, Box<dyn std::error::Error>> {
+    if options.ngram_length == markovpass::MIN_NGRAM_LENGTH {
+        warn(
+            "With ngram length 2 passphrases will hardly look like words.",
+            &[],
+        );
+    }
+    // Files which can't be read yet are reported by training.
+    let corpus_bytes: u64 = options
+        .files
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let memory = markovpass::estimate_training_memory(corpus_bytes, options.ngram_length);
+    if memory > TRAINING_MEMORY_WARNING {
+        progress(
+            "Training with ngram length {} may take up to {} MB of memory.",
+            &[
+                &options.ngram_length,
+                &format!("{:.0}", memory as f64 / 1e6),
+            ],
+        );
+    }
+    let (model, report) = markovpass::train_with_report(options)?;
+    for bad_file in report.bad_files {
+        warn("Skipping {}", &[&bad_file]);
+    }
+    for duplicate in report.duplicate_files {
+        warn(
+            "Skipping {}, which has the same contents as {}.",
+            &[&duplicate.path.display(), &duplicate.original.display()],
+        );
+    }
+
+    Ok(model)
+}
+
+/// Build the chain from the model or corpus in `options`, saving a trained model to `save_to` so
+/// later runs can skip training.
+pub fn build_chain(
+    options: &markovpass::GenPassphraseOptions,
+    save_to: Option<&std::path::Path>,
+) -> Result<markovpass::PassphraseMarkovChain, Box<dyn std::error::Error>> {
+    let model = match &options.model {
+        Some(path) => markovpass::load_model(path, options)?,
+        None => train_model(options)?,
+    };
+    if let Some(path) = save_to {
+        save_model(&model, path)?;
+    }
+    let chain = markovpass::chain_from_model(model, options)?;
+    if chain.starting_entropy() == 0.0 {
+        warn(
+            "Every passphrase starts the same way, since the corpus words all do.",
+            &[],
+        );
+    }
+
+    Ok(chain)
+}
+
+pub fn train(args: &TrainArgs) -> CliResult {
+    let mut options = args.corpus.gen_passphrase_options(true)?;
+    match &args.update {
+        Some(path) => {
+            let digests = args
+                .corpus
+                .verify
+                .expected_digests(&[(path.clone(), None)])?;
+            options.expected_sha256.extend(digests);
+            // The new corpus has to be cleaned the same way as the one the model was built from.
+            let mut model = markovpass::load_model(path, &options)?;
+            if model.metadata().corpus_options.is_none() {
+                warn(
+                    "The model doesn't record its corpus options, so the new corpus is cleaned \
+                     with the options given.",
+                    &[],
+                );
+            }
+            let options = markovpass::model_training_options(options, model.metadata());
+            model.merge(&train_model(&options)?)?;
+            if let Some(bits) = args.quantize {
+                model.quantize(bits);
+            }
+            save_model(&model, args.output.as_ref().unwrap_or(path))?;
+        }
+        None => {
+            let mut model = train_model(&options)?;
+            if let Some(bits) = args.quantize {
+                model.quantize(bits);
+            }
+            // clap guarantees an output file when not updating.
+            save_model(&model, args.output.as_ref().unwrap())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Options for building the chain from a corpus or model.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ChainArgs {
+    /// Use a model file created with 'markovpass train' instead of a corpus
+    #[clap(long, value_parser, conflicts_with = "files")]
+    pub model: Option<std::path::PathBuf>,
+
+    /// Also save the model trained from the corpus to FILE, for later runs to load with
+    /// '--model' instead of training again
+    #[clap(long, value_parser, value_name = "FILE", conflicts_with = "model")]
+    pub save_model: Option<std::path::PathBuf>,
+
+    /// Prune ngrams with less transition entropy than this (in bits), avoiding long
+    /// deterministic runs
+    #[clap(long, value_parser, default_value_t = 0.0, value_name = "BITS")]
+    pub min_node_entropy: f64,
+
+    /// Choose the start of each passphrase uniformly from the word-initial ngrams instead of by
+    /// corpus frequency
+    #[clap(long, value_parser, default_value_t = false)]
+    pub uniform_start: bool,
+
+    /// Allow passphrases to start at any ngram rather than only at the start of a word
+    #[clap(long, value_parser, default_value_t = false)]
+    pub start_anywhere: bool,
+
+    /// What to do when every corpus word starts the same way, as in tiny corpora: fail, also
+    /// start at the next ngram of each word, or allow it and get all the entropy from later
+    /// transitions
+    #[clap(long, value_parser, default_value_t = markovpass::SingleStart::Fail, possible_values = ["fail", "widen", "allow"])]
+    pub single_start: markovpass::SingleStart,
+}
+
+impl ChainArgs {
+    /// Options for building the chain, with generation options left at their defaults.
+    pub fn gen_passphrase_options(
+        &self,
+        corpus: &CorpusArgs,
+    ) -> Result<markovpass::GenPassphraseOptions, Box<dyn std::error::Error>> {
+        let mut options = corpus.gen_passphrase_options(self.model.is_none())?;
+        if let Some(model) = &self.model {
+            let digests = corpus.verify.expected_digests(&[(model.clone(), None)])?;
+            options.expected_sha256.extend(digests);
+        }
+
+        Ok(markovpass::GenPassphraseOptions {
+            model: self.model.clone(),
+            min_node_entropy: self.min_node_entropy,
+            uniform_start: self.uniform_start,
+            start_anywhere: self.start_anywhere,
+            single_start: self.single_start,
+            ..options
+        })
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TrainArgs {
+    #[clap(flatten)]
+    pub corpus: CorpusArgs,
+
+    /// File to write the trained model to. Defaults to the updated model file with '--update'
+    #[clap(short = 'o', long, value_parser, required_unless_present = "update")]
+    pub output: Option<std::path::PathBuf>,
+
+    /// Add the corpus to an existing model instead of training a new one. The corpus is read and
+    /// cleaned with the model's ngram length, ngram step, minimum word length and corpus options
+    #[clap(long, value_parser)]
+    pub update: Option<std::path::PathBuf>,
+
+    /// Store each transition count in BITS bits for a smaller model file. 'model info' shows how
+    /// much this can change the entropy
+    #[clap(long, value_parser = clap::value_parser!(u32).range(2..=8), value_name = "BITS")]
+    pub quantize: Option<u32>,
+}
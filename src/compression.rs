@@ -0,0 +1,237 @@
+//! Transparent decompression of gzip, bzip2, xz and zstd compressed corpus and model files, and
+//! compression of saved models.
+//!
+//! Compressed files are recognized by their magic bytes whatever they're called, and decompressed
+//! by piping them through the format's usual command line tool, which saves bundling a
+//! decompressor for each format.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression of data starting with `bytes`, if it's compressed.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::Xz)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// The program which decompresses the format with `-dc` and compresses it with `-c`.
+    fn program(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Bzip2 => "bzip2",
+            Self::Xz => "xz",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.program())
+    }
+}
+
+/// `bytes` decompressed if they're compressed, and otherwise unchanged.
+pub fn decompress(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    let Some(compression) = Compression::detect(&bytes) else {
+        return Ok(bytes);
+    };
+    decompress_reader(compression, &mut bytes.as_slice(), |output| {
+        let mut bytes = vec![];
+        output.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })
+}
+
+/// The result of `f` reading `input` decompressed from `compression` as it streams through the
+/// decompressor.
+///
+/// `f` should read to the end of its input: if it stops early the decompressor is killed by a
+/// broken pipe, and its failure is only ignored if `f` fails too.
+pub fn decompress_reader<T>(
+    compression: Compression,
+    input: &mut (dyn Read + Send),
+    f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+) -> io::Result<T> {
+    let program = compression.program();
+    let mut child = Command::new(program)
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!(
+                    "Can't decompress {} data without '{}': {}",
+                    compression, program, error
+                ),
+            )
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let (output, errors, status, written) = std::thread::scope(|scope| {
+        // Write from another thread so a full stdout pipe can't deadlock the two.
+        let writer = scope.spawn(move || io::copy(input, &mut stdin).and_then(|_| stdin.flush()));
+        let output = f(&mut stdout);
+        drop(stdout);
+        let mut errors = String::new();
+        child.stderr.take().unwrap().read_to_string(&mut errors)?;
+        let status = child.wait()?;
+        io::Result::Ok((output, errors, status, writer.join().unwrap()))
+    })?;
+    let output = output?;
+    if !status.success() {
+        // Only the first line says what went wrong; bzip2 follows it with lots of advice.
+        let error = errors
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("'{} -dc' failed ({}): {}", program, status, error),
+        ));
+    }
+    // A broken pipe just means the program stopped reading, which its status explains.
+    written?;
+
+    Ok(output)
+}
+
+/// The result of `f` writing to `output` through `compression`'s compressor.
+///
+/// If the compressor isn't installed, the error is `io::ErrorKind::NotFound` and nothing is
+/// written.
+pub fn compress_writer<T>(
+    compression: Compression,
+    output: &mut (dyn Write + Send),
+    f: impl FnOnce(&mut dyn Write) -> io::Result<T>,
+) -> io::Result<T> {
+    let program = compression.program();
+    let mut child = Command::new(program)
+        .args(["-c", "-q"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!(
+                    "Can't compress {} data without '{}': {}",
+                    compression, program, error
+                ),
+            )
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let (result, errors, status, copied) = std::thread::scope(|scope| {
+        // Copy from another thread so a full stdout pipe can't deadlock the two.
+        let copier =
+            scope.spawn(move || io::copy(&mut stdout, output).and_then(|_| output.flush()));
+        let result = f(&mut stdin).and_then(|result| stdin.flush().map(|_| result));
+        drop(stdin);
+        let mut errors = String::new();
+        child.stderr.take().unwrap().read_to_string(&mut errors)?;
+        let status = child.wait()?;
+        io::Result::Ok((result, errors, status, copier.join().unwrap()))
+    })?;
+    let result = result?;
+    if !status.success() {
+        let error = errors.lines().map(str::trim).next().unwrap_or_default();
+        return Err(io::Error::other(format!(
+            "'{} -c' failed ({}): {}",
+            program, status, error
+        )));
+    }
+    copied?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello corpus\n", compressed with gzip.
+    const GZIPPED: [u8; 33] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0x48, 0xce, 0x2f, 0x2a, 0x28, 0x2d, 0xe6, 0x02, 0x00, 0xdf, 0x09, 0xe0, 0x1d, 0x0d,
+        0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(&GZIPPED), Some(Compression::Gzip));
+        assert_eq!(Compression::detect(b"BZh91AY&SY"), Some(Compression::Bzip2));
+        assert_eq!(
+            Compression::detect(b"\xfd7zXZ\x00\x00\x04"),
+            Some(Compression::Xz)
+        );
+        assert_eq!(
+            Compression::detect(b"\x28\xb5\x2f\xfd\x04"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::detect(b"plain text"), None);
+        assert_eq!(Compression::detect(b""), None);
+    }
+
+    #[test]
+    fn test_compress_writer() {
+        // Compressing needs zstd installed.
+        if Command::new("zstd").arg("--version").output().is_err() {
+            return;
+        }
+        let mut compressed = vec![];
+        let written = compress_writer(Compression::Zstd, &mut compressed, |input| {
+            input.write_all(b"hello corpus\n")?;
+            Ok(13)
+        });
+        assert_eq!(written.unwrap(), 13);
+        assert_eq!(Compression::detect(&compressed), Some(Compression::Zstd));
+        assert_eq!(decompress(compressed).unwrap(), b"hello corpus\n");
+    }
+
+    #[test]
+    fn test_decompress() {
+        let text = b"plain text".to_vec();
+        assert_eq!(decompress(text.clone()).unwrap(), text);
+        // Decompressing needs gzip installed.
+        if Command::new("gzip").arg("--version").output().is_err() {
+            return;
+        }
+        assert_eq!(decompress(GZIPPED.to_vec()).unwrap(), b"hello corpus\n");
+        let words = decompress_reader(Compression::Gzip, &mut GZIPPED.as_slice(), |output| {
+            let mut text = String::new();
+            output.read_to_string(&mut text)?;
+            Ok(text.split_whitespace().count())
+        });
+        assert_eq!(words.unwrap(), 2);
+        let truncated = GZIPPED[..20].to_vec();
+        assert_eq!(
+            decompress(truncated).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -1,56 +1,541 @@
+//! Cleaning corpus text and splitting it into ngrams.
+//!
+//! ```
+//! use markovpass::corpus::{CleaningOptions, Corpus, Ngrams};
+//!
+//! let options = CleaningOptions { min_word_length: 3, ..Default::default() };
+//! let corpus = Corpus::from_text("This is a test.", 3, &options);
+//! assert_eq!(corpus.text(), " this test");
+//! let ngrams: Vec<_> = Ngrams::new(corpus.text(), 4).step(2).collect();
+//! assert_eq!(ngrams, [" thi", "his ", "s te", "test"]);
+//! ```
+
+use crate::bloom::BloomFilter;
+use crate::encoding::{Decoder, Encoding};
+use crate::script::Script;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// The character separating words in cleaned text and ngrams.
+///
+/// Cleaning splits the input on any Unicode whitespace and joins the surviving words with exactly
+/// one boundary, so ngrams never contain two boundaries in a row and nothing else in the cleaned
+/// text is ever a boundary.
+pub const WORD_BOUNDARY: char = ' ';
+
+/// How many bytes `CorpusReader` reads at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Lines longer than this many bytes are cleaned a piece at a time, split at whitespace, so text
+/// without line breaks doesn't have to fit in memory.
+const MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+/// What to do with apostrophes in corpus words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApostrophePolicy {
+    /// Keep apostrophes ("don't").
+    #[default]
+    Keep,
+    /// Remove apostrophes from words ("dont").
+    Strip,
+    /// Discard words containing apostrophes.
+    DropWord,
+}
+
+impl fmt::Display for ApostrophePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Keep => write!(f, "keep"),
+            Self::Strip => write!(f, "strip"),
+            Self::DropWord => write!(f, "drop-word"),
+        }
+    }
+}
+
+impl FromStr for ApostrophePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "strip" => Ok(Self::Strip),
+            "drop-word" => Ok(Self::DropWord),
+            _ => Err(format!(
+                "Unknown apostrophe policy '{}'. Expected keep, strip or drop-word.",
+                s
+            )),
+        }
+    }
+}
+
+/// Where digits may appear in corpus words.
+///
+/// Words always need at least one letter, so standalone numbers are still dropped (or spelled
+/// out).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DigitPolicy {
+    /// Trim digits off words ("sha256" becomes "sha") and drop words with digits inside them.
+    #[default]
+    Drop,
+    /// Allow digits at the end of words ("sha256", "ipv6").
+    Trailing,
+    /// Allow digits anywhere but the start of words ("x86", "h2o").
+    NotLeading,
+    /// Allow digits anywhere in words ("3des").
+    Anywhere,
+}
+
+impl DigitPolicy {
+    /// Whether the digits in `word` are where this policy allows.
+    fn allows(self, word: &str) -> bool {
+        let is_digit = |c: char| c.is_ascii_digit();
+        let has_letter = word.chars().any(char::is_alphabetic);
+        match self {
+            Self::Drop => !word.contains(is_digit),
+            Self::Trailing => has_letter && !word.trim_end_matches(is_digit).contains(is_digit),
+            Self::NotLeading => has_letter && !word.starts_with(is_digit),
+            Self::Anywhere => has_letter,
+        }
+    }
+}
+
+impl fmt::Display for DigitPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Drop => write!(f, "drop"),
+            Self::Trailing => write!(f, "trailing"),
+            Self::NotLeading => write!(f, "not-leading"),
+            Self::Anywhere => write!(f, "anywhere"),
+        }
+    }
+}
+
+impl FromStr for DigitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(Self::Drop),
+            "trailing" => Ok(Self::Trailing),
+            "not-leading" => Ok(Self::NotLeading),
+            "anywhere" => Ok(Self::Anywhere),
+            _ => Err(format!(
+                "Unknown digit policy '{}'. Expected drop, trailing, not-leading or anywhere.",
+                s
+            )),
+        }
+    }
+}
+
+/// Rules deciding which words of the input make it into the cleaned corpus.
+#[derive(Debug, Clone, Default)]
+pub struct CleaningOptions {
+    pub min_word_length: usize,
+    /// Drop words containing any of these (lowercase) characters.
+    pub exclude_chars: Vec<char>,
+    pub apostrophes: ApostrophePolicy,
+    /// Where (ASCII) digits may appear in words.
+    pub digits: DigitPolicy,
+    /// Replace standalone numbers with their spelled out words instead of dropping them.
+    pub spell_numbers: bool,
+    /// Drop words with letters from any other script.
+    pub script: Option<Script>,
+    /// Keep the case of corpus words instead of lowercasing them.
+    pub preserve_case: bool,
+    /// Drop lines which repeat an earlier line, ignoring case and whitespace.
+    pub dedupe_lines: bool,
+    /// Drop sentences which repeat an earlier sentence, ignoring case and whitespace.
+    pub dedupe_sentences: bool,
+}
+
+/// Drops repeated lines or sentences from text, remembering them across texts so repeats in
+/// later files of a corpus are dropped too.
+///
+/// Repeated boilerplate, like licenses or headers in every file, skews transition weights
+/// without adding any variety.
+#[derive(Debug, Clone, Default)]
+pub struct Deduplicator {
+    lines: Option<HashSet<u64>>,
+    sentences: Option<HashSet<u64>>,
+    /// The end of the last chunk, if it stopped part way through a sentence.
+    partial_sentence: String,
+}
+
+impl Deduplicator {
+    pub fn new(options: &CleaningOptions) -> Self {
+        Self {
+            lines: options.dedupe_lines.then(HashSet::new),
+            sentences: options.dedupe_sentences.then(HashSet::new),
+            partial_sentence: String::new(),
+        }
+    }
+
+    /// `text` without the lines and sentences seen before.
+    pub fn dedupe<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        self.dedupe_chunk(text, true)
+    }
+
+    /// The next chunk of a text split after line breaks, without the lines and sentences seen
+    /// before. Unless it's the `last` chunk, a sentence it stops part way through is held back
+    /// and finished with the next chunk.
+    pub fn dedupe_chunk<'a>(&mut self, text: &'a str, last: bool) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if let Some(seen) = &mut self.lines {
+            let lines: Vec<&str> = text.lines().filter(|line| is_new(seen, line)).collect();
+            text = Cow::Owned(lines.join("\n"));
+        }
+        if let Some(seen) = &mut self.sentences {
+            if !self.partial_sentence.is_empty() {
+                let mut joined = std::mem::take(&mut self.partial_sentence);
+                joined.push('\n');
+                joined.push_str(&text);
+                text = Cow::Owned(joined);
+            }
+            let mut sentences: Vec<&str> = sentences(&text).collect();
+            if !last
+                && sentences
+                    .last()
+                    .is_some_and(|s| !s.ends_with(['.', '!', '?']))
+            {
+                self.partial_sentence = sentences.pop().unwrap().to_string();
+            }
+            let sentences: Vec<&str> = sentences
+                .into_iter()
+                .filter(|sentence| is_new(seen, sentence))
+                .collect();
+            text = Cow::Owned(sentences.join(" "));
+        }
+
+        text
+    }
+}
+
+/// Whether `seen` didn't have `text` yet (ignoring case and whitespace), adding it. Blank text
+/// is never a repeat.
+fn is_new(seen: &mut HashSet<u64>, text: &str) -> bool {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return true;
+    }
+    let mut hasher = DefaultHasher::new();
+    words.hash(&mut hasher);
+
+    seen.insert(hasher.finish())
+}
+
+/// The sentences of `text`, each ending with its punctuation: a ".", "!" or "?" followed by
+/// whitespace or the end of the text.
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.trim().is_empty() {
+            return None;
+        }
+        let mut chars = rest.char_indices().peekable();
+        let mut end = rest.len();
+        while let Some((i, c)) = chars.next() {
+            if matches!(c, '.' | '!' | '?')
+                && chars.peek().is_none_or(|&(_, next)| next.is_whitespace())
+            {
+                end = i + c.len_utf8();
+                break;
+            }
+        }
+        let (sentence, remainder) = rest.split_at(end);
+        rest = remainder;
+        Some(sentence.trim())
+    })
+}
+
+/// Reads corpus text a chunk at a time and cleans it into words, so the whole text is never in
+/// memory at once.
+pub struct CorpusReader<'a, R> {
+    reader: R,
+    decoder: Decoder,
+    options: &'a CleaningOptions,
+    deduplicator: &'a mut Deduplicator,
+}
+
+impl<'a, R: Read> CorpusReader<'a, R> {
+    /// A reader of text in `encoding`, or the encoding `Decoder` detects if it's `None`.
+    pub fn new(
+        reader: R,
+        encoding: Option<Encoding>,
+        options: &'a CleaningOptions,
+        deduplicator: &'a mut Deduplicator,
+    ) -> Self {
+        Self {
+            reader,
+            decoder: Decoder::new(encoding),
+            options,
+            deduplicator,
+        }
+    }
+
+    /// Call `f` with each cleaned word of the text in order, returning how many words cleaning
+    /// looked at.
+    pub fn for_each_word(mut self, mut f: impl FnMut(&str)) -> io::Result<usize> {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut text = String::new();
+        let mut input_words = 0;
+        loop {
+            let read = match self.reader.read(&mut buffer) {
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            let last = read == 0;
+            text.push_str(&self.decoder.decode(&buffer[..read], last)?);
+            let end = match last {
+                true => text.len(),
+                false => chunk_end(&text),
+            };
+            let chunk = self.deduplicator.dedupe_chunk(&text[..end], last);
+            input_words += Corpus::clean_words(&chunk, self.options, &mut f);
+            text.drain(..end);
+            if last {
+                return Ok(input_words);
+            }
+        }
+    }
+}
+
+/// Where the part of `text` which can be cleaned without the text after it ends: after the last
+/// line break, or the last whitespace if the last line is too long to wait for its end.
+fn chunk_end(text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(index) => index + 1,
+        None if text.len() > MAX_LINE_LENGTH => text
+            .char_indices()
+            .rfind(|(_, c)| c.is_whitespace())
+            .map_or(0, |(index, c)| index + c.len_utf8()),
+        None => 0,
+    }
+}
+
+/// Cleaned corpus text.
 pub struct Corpus {
     text: String,
     ngram_length: usize,
     original_byte_length: usize,
+    /// How many whitespace separated words cleaning looked at.
+    input_words: usize,
 }
 
 impl Corpus {
+    /// Read and clean a corpus, for ngrams of `ngram_length` characters. Text is read as
+    /// Windows-1252 from the first byte which isn't valid UTF-8.
     pub fn new(
-        mut reader: Box<dyn std::io::Read>,
+        reader: Box<dyn std::io::Read>,
         ngram_length: usize,
-        min_word_length: usize,
+        options: &CleaningOptions,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        // TODO: Process the input to generate text efficiently.
+        let mut deduplicator = Deduplicator::new(options);
         let mut text = String::new();
-        reader.read_to_string(&mut text)?;
-        let mut text = Self::clean_text(&text, min_word_length);
+        let input_words = CorpusReader::new(reader, None, options, &mut deduplicator)
+            .for_each_word(|word| push_word(&mut text, word))?;
+
+        Ok(Self::from_cleaned(text, ngram_length, input_words))
+    }
+
+    /// Clean a corpus, for ngrams of `ngram_length` characters.
+    pub fn from_text(text: &str, ngram_length: usize, options: &CleaningOptions) -> Self {
+        Self::from_text_deduplicated(text, ngram_length, options, &mut Deduplicator::new(options))
+    }
+
+    /// Clean a corpus like `from_text`, dropping repeats of lines or sentences `deduplicator`
+    /// has seen.
+    pub fn from_text_deduplicated(
+        text: &str,
+        ngram_length: usize,
+        options: &CleaningOptions,
+        deduplicator: &mut Deduplicator,
+    ) -> Self {
+        let text = deduplicator.dedupe(text);
+        let (text, input_words) = Self::clean_and_count(&text, options);
+
+        Self::from_cleaned(text, ngram_length, input_words)
+    }
+
+    /// A corpus of already cleaned `text`, from `input_words` words before cleaning.
+    pub(crate) fn from_cleaned(mut text: String, ngram_length: usize, input_words: usize) -> Self {
         let original_byte_length = text.len();
         // Push the first few characters onto the end so we can return `&str`s for the wrap around.
         text.push_str(&text.chars().take(ngram_length).collect::<String>());
 
-        Ok(Self {
+        Self {
             text,
             ngram_length,
             original_byte_length,
-        })
+            input_words,
+        }
+    }
+
+    /// The ngrams the chain is trained on: one starting at each character of `text`, wrapping
+    /// around from the end of the corpus to its start.
+    pub fn ngrams(&self) -> Ngrams<'_> {
+        self.ngrams_with_stride(Stride::default())
     }
 
-    pub fn ngrams(&self) -> impl Iterator<Item = &str> {
+    /// The ngrams of `text` advancing by `stride`, wrapping around like `ngrams`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the step is 0.
+    pub fn ngrams_with_stride(&self, stride: Stride) -> Ngrams<'_> {
+        assert!(stride.step > 0, "ngram step must be positive");
         Ngrams {
-            corpus: self,
+            text: &self.text,
+            window: self.ngram_length,
+            stride,
             byte_index: 0,
+            end: self.original_byte_length,
         }
     }
 
-    fn clean_text(text: &str, min_word_length: usize) -> String {
-        let text = text.to_lowercase();
+    /// The cleaned text: each word preceded by a single `WORD_BOUNDARY`.
+    pub fn text(&self) -> &str {
+        &self.text[..self.original_byte_length]
+    }
+
+    /// The cleaned words of the corpus, in order.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.text()
+            .split(WORD_BOUNDARY)
+            .filter(|word| !word.is_empty())
+    }
+
+    /// The number of words in the cleaned corpus.
+    pub fn word_count(&self) -> usize {
+        self.words().count()
+    }
+
+    /// The number of distinct words in the cleaned corpus.
+    pub fn distinct_word_count(&self) -> usize {
+        self.words().collect::<HashSet<_>>().len()
+    }
+
+    /// The number of whitespace separated words in the text before cleaning (after
+    /// deduplicating and spelling out numbers).
+    pub fn input_word_count(&self) -> usize {
+        self.input_words
+    }
+
+    /// The fraction of input words cleaning dropped, from 0 to 1.
+    pub fn dropped_word_ratio(&self) -> f64 {
+        match self.input_words {
+            0 => 0.0,
+            input => input.saturating_sub(self.word_count()) as f64 / input as f64,
+        }
+    }
+
+    /// How often each character appears in the cleaned words.
+    pub fn char_histogram(&self) -> BTreeMap<char, usize> {
+        let mut histogram = BTreeMap::new();
+        for c in self.words().flat_map(str::chars) {
+            *histogram.entry(c).or_insert(0) += 1;
+        }
+
+        histogram
+    }
+
+    /// A bloom filter of the distinct words of the corpus, for fast membership checks with
+    /// about `false_positive_rate` chance of false positives.
+    pub fn word_filter(&self, false_positive_rate: f64) -> BloomFilter {
+        let words: std::collections::HashSet<&str> = self.words().collect();
+        BloomFilter::from_words(words.into_iter(), false_positive_rate)
+    }
+
+    #[cfg(test)]
+    fn clean_text(text: &str, options: &CleaningOptions) -> String {
+        Self::clean_and_count(text, options).0
+    }
+
+    /// The cleaned text, and how many words cleaning looked at.
+    fn clean_and_count(text: &str, options: &CleaningOptions) -> (String, usize) {
+        let mut cleaned = String::new();
+        let input_words = Self::clean_words(text, options, |word| push_word(&mut cleaned, word));
+
+        (cleaned, input_words)
+    }
+
+    /// Call `f` with each cleaned word of `text`, returning how many words cleaning looked at.
+    fn clean_words(text: &str, options: &CleaningOptions, mut f: impl FnMut(&str)) -> usize {
+        let text = if options.preserve_case {
+            text.to_string()
+        } else {
+            text.to_lowercase()
+        };
+        let text = if options.spell_numbers {
+            Self::spell_out_numbers(&text)
+        } else {
+            text
+        };
+        let input_words = text.split_whitespace().count();
         let words = text
             .split_whitespace()
-            .filter_map(|word| Self::clean_word(word, min_word_length));
+            .filter_map(|word| Self::clean_word(word, options.min_word_length, options.digits))
+            .filter_map(|word| match options.apostrophes {
+                ApostrophePolicy::Keep => Some(Cow::Borrowed(word)),
+                ApostrophePolicy::Strip if word.contains('\'') => {
+                    let word = word.replace('\'', "");
+                    (word.len() >= options.min_word_length).then_some(Cow::Owned(word))
+                }
+                ApostrophePolicy::Strip => Some(Cow::Borrowed(word)),
+                ApostrophePolicy::DropWord => (!word.contains('\'')).then_some(Cow::Borrowed(word)),
+            })
+            .filter(|word| !word.is_empty())
+            .filter(|word| {
+                !word
+                    .chars()
+                    .flat_map(char::to_lowercase)
+                    .any(|c| options.exclude_chars.contains(&c))
+            })
+            .filter(|word| {
+                options.script.is_none_or(|script| {
+                    word.chars()
+                        .filter(|c| c.is_alphabetic())
+                        .all(|c| Script::of(c) == Some(script))
+                })
+            });
+        for word in words {
+            f(&word);
+        }
+
+        input_words
+    }
+
+    /// Replace each whitespace separated number in `text` (ignoring surrounding punctuation) with
+    /// its words.
+    fn spell_out_numbers(text: &str) -> String {
+        let words: Vec<Cow<str>> = text
+            .split_whitespace()
+            .map(|word| {
+                let digits = word.trim_matches(|c: char| !c.is_alphanumeric());
+                match digits.parse::<u64>() {
+                    Ok(n) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                        Cow::Owned(spell_number(n))
+                    }
+                    _ => Cow::Borrowed(word),
+                }
+            })
+            .collect();
 
-        // Insert a space at the start of the corpus so that every word begins with a space.
-        Some("")
-            .into_iter()
-            .chain(words)
-            .collect::<Vec<&str>>()
-            .join(" ")
+        words.join(" ")
     }
 
-    fn clean_word(word: &str, min_length: usize) -> Option<&str> {
-        let is_word_char = |c: char| c.is_alphabetic() || c == '\'';
+    fn clean_word(word: &str, min_length: usize, digits: DigitPolicy) -> Option<&str> {
+        let is_word_char = |c: char| {
+            c.is_alphabetic() || c == '\'' || (digits != DigitPolicy::Drop && c.is_ascii_digit())
+        };
         let word = word.trim_matches(|c| !is_word_char(c));
 
-        if word.chars().all(is_word_char) && word.len() >= min_length {
+        if word.chars().all(is_word_char) && word.len() >= min_length && digits.allows(word) {
             Some(word)
         } else {
             None
@@ -58,33 +543,198 @@ impl Corpus {
     }
 }
 
-struct Ngrams<'corpus> {
-    corpus: &'corpus Corpus,
+/// Add `word` to cleaned text, after the boundary every word begins with.
+pub(crate) fn push_word(text: &mut String, word: &str) {
+    text.push(WORD_BOUNDARY);
+    text.push_str(word);
+}
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const SCALES: [&str; 7] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+
+/// The English words for `n`, separated by spaces ("forty two").
+fn spell_number(n: u64) -> String {
+    if n == 0 {
+        return ONES[0].to_string();
+    }
+    let mut groups = vec![];
+    let mut rest = n;
+    for scale in SCALES {
+        let group = rest % 1000;
+        if group > 0 {
+            let mut words = spell_below_thousand(group as usize);
+            if !scale.is_empty() {
+                words.push(scale);
+            }
+            groups.push(words.join(" "));
+        }
+        rest /= 1000;
+        if rest == 0 {
+            break;
+        }
+    }
+    groups.reverse();
+
+    groups.join(" ")
+}
+
+fn spell_below_thousand(n: usize) -> Vec<&'static str> {
+    let mut words = vec![];
+    if n >= 100 {
+        words.extend([ONES[n / 100], "hundred"]);
+    }
+    match n % 100 {
+        0 => {}
+        m @ 1..=19 => words.push(ONES[m]),
+        m => {
+            words.push(TENS[m / 10]);
+            if m % 10 > 0 {
+                words.push(ONES[m % 10]);
+            }
+        }
+    }
+
+    words
+}
+
+/// How far each ngram starts from the one before it.
+///
+/// The chain spells a passphrase out with the characters each ngram moves the window past, so
+/// it has to know the stride its counts were collected with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stride {
+    /// Characters between the starts of consecutive ngrams. A step of 1 gives every ngram.
+    pub step: usize,
+    /// Never step past a word boundary: an ngram containing the next word's boundary is followed
+    /// by one starting at it, so every word starts an ngram whatever the step.
+    pub reset_at_words: bool,
+}
+
+impl Default for Stride {
+    fn default() -> Self {
+        Self {
+            step: 1,
+            reset_at_words: false,
+        }
+    }
+}
+
+impl Stride {
+    /// How many characters the window moves from `ngram` to the ngram after it.
+    pub fn shift(&self, ngram: &str) -> usize {
+        let next_word = match self.reset_at_words {
+            true => ngram.chars().skip(1).position(|c| c == WORD_BOUNDARY),
+            false => None,
+        };
+
+        next_word.map_or(self.step, |index| self.step.min(index + 1))
+    }
+}
+
+/// An iterator over the substrings of `window` characters of a text, starting every `step`
+/// characters.
+#[derive(Debug, Clone)]
+pub struct Ngrams<'a> {
+    text: &'a str,
+    window: usize,
+    stride: Stride,
     byte_index: usize,
+    /// No ngrams start at or after this byte index.
+    end: usize,
 }
 
-impl<'corpus> Iterator for Ngrams<'corpus> {
-    type Item = &'corpus str;
+impl<'a> Ngrams<'a> {
+    /// Every substring of `window` characters of `text`, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    pub fn new(text: &'a str, window: usize) -> Self {
+        assert!(window > 0, "ngram window must be positive");
+        Self {
+            text,
+            window,
+            stride: Stride::default(),
+            byte_index: 0,
+            end: text.len(),
+        }
+    }
+
+    /// Start each ngram `step` characters after the last rather than one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is 0.
+    pub fn step(mut self, step: usize) -> Self {
+        assert!(step > 0, "ngram step must be positive");
+        self.stride.step = step;
+        self
+    }
+
+    /// Start an ngram at every word boundary, even between steps.
+    pub fn reset_at_words(mut self) -> Self {
+        self.stride.reset_at_words = true;
+        self
+    }
+}
+
+impl<'a> Iterator for Ngrams<'a> {
+    type Item = &'a str;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.byte_index >= self.corpus.original_byte_length {
+        if self.byte_index >= self.end {
             return None;
         }
 
-        let (_, ngram_start) = self.corpus.text.split_at(self.byte_index);
-        let mut ngram_char_indices = ngram_start
+        let rest = &self.text[self.byte_index..];
+        // The byte offsets of each character boundary in `rest`.
+        let mut boundaries = rest
             .char_indices()
-            .take(self.corpus.ngram_length + 1)
-            .skip(1);
-
-        let first_char_byte_length = ngram_char_indices.next().unwrap().0;
-        let ngram_byte_length = ngram_char_indices
-            .last()
-            .map_or(first_char_byte_length, |(i, _)| i);
-        let ngram_start_index = self.byte_index;
-        self.byte_index += first_char_byte_length;
+            .map(|(i, _)| i)
+            .chain(std::iter::once(rest.len()));
+        let Some(length) = boundaries.clone().nth(self.window) else {
+            self.byte_index = self.end;
+            return None;
+        };
+        let ngram = &rest[..length];
+        let shift = self.stride.shift(ngram);
+        self.byte_index += boundaries.nth(shift).unwrap_or(rest.len());
 
-        Some(&self.corpus.text[ngram_start_index..ngram_start_index + ngram_byte_length])
+        Some(ngram)
     }
 }
 
@@ -94,31 +744,167 @@ mod tests {
 
     #[test]
     fn test_clean_word() {
-        assert_eq!(Corpus::clean_word("Test", 3), Some("Test"));
-        assert_eq!(Corpus::clean_word("123test@314", 3), Some("test"));
-        assert_eq!(Corpus::clean_word("2#@test'in23", 3), Some("test'in"));
-        assert_eq!(Corpus::clean_word("31ld;Test", 3), None);
-        assert_eq!(Corpus::clean_word("a", 2), None);
-        assert_eq!(Corpus::clean_word("Test", 5), None);
+        let clean_word = |word, min_length| Corpus::clean_word(word, min_length, DigitPolicy::Drop);
+        assert_eq!(clean_word("Test", 3), Some("Test"));
+        assert_eq!(clean_word("123test@314", 3), Some("test"));
+        assert_eq!(clean_word("2#@test'in23", 3), Some("test'in"));
+        assert_eq!(clean_word("31ld;Test", 3), None);
+        assert_eq!(clean_word("a", 2), None);
+        assert_eq!(clean_word("Test", 5), None);
+    }
+
+    #[test]
+    fn test_clean_word_digits() {
+        let words = ["sha256,", "(ipv6)", "x86", "h2o", "3des", "1999", "a1b2"];
+        let clean = |digits| {
+            words
+                .iter()
+                .filter_map(|word| Corpus::clean_word(word, 3, digits))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(clean(DigitPolicy::Drop), vec!["sha", "ipv", "des"]);
+        assert_eq!(clean(DigitPolicy::Trailing), vec!["sha256", "ipv6", "x86"]);
+        assert_eq!(
+            clean(DigitPolicy::NotLeading),
+            vec!["sha256", "ipv6", "x86", "h2o", "a1b2"]
+        );
+        assert_eq!(
+            clean(DigitPolicy::Anywhere),
+            vec!["sha256", "ipv6", "x86", "h2o", "3des", "a1b2"]
+        );
     }
 
     #[test]
     fn test_clean_corpus() {
-        assert_eq!(Corpus::clean_text("this is a test", 3), " this test");
-        assert_eq!(Corpus::clean_text("Some awes0me test", 3), " some test");
-        assert_eq!(Corpus::clean_text("test'in", 3), " test'in");
-        assert_eq!(Corpus::clean_text("this is a test", 5), "");
+        assert_eq!(
+            Corpus::clean_text("this is a test", &cleaning(3)),
+            " this test"
+        );
+        assert_eq!(
+            Corpus::clean_text("Some awes0me test", &cleaning(3)),
+            " some test"
+        );
+        assert_eq!(Corpus::clean_text("test'in", &cleaning(3)), " test'in");
+        assert_eq!(Corpus::clean_text("this is a test", &cleaning(5)), "");
+    }
+
+    #[test]
+    fn test_clean_corpus_boundaries() {
+        assert_eq!(
+            Corpus::clean_text("odd\u{a0}spacing\t\r\n here\u{3000}too", &cleaning(3)),
+            " odd spacing here too"
+        );
+        assert_eq!(Corpus::clean_text("a -- b ... c", &cleaning(0)), " a b c");
+    }
+
+    #[test]
+    fn test_clean_corpus_exclude_chars() {
+        let options = CleaningOptions {
+            min_word_length: 3,
+            exclude_chars: vec!['q', '\''],
+            ..Default::default()
+        };
+        assert_eq!(
+            Corpus::clean_text("The quick fox isn't here", &options),
+            " the fox here"
+        );
+    }
+
+    #[test]
+    fn test_clean_corpus_apostrophes() {
+        let text = "Don't stop, they're o'clock";
+        let with_policy = |apostrophes| CleaningOptions {
+            min_word_length: 5,
+            apostrophes,
+            ..Default::default()
+        };
+        assert_eq!(
+            Corpus::clean_text(text, &with_policy(ApostrophePolicy::Keep)),
+            " don't they're o'clock"
+        );
+        assert_eq!(
+            Corpus::clean_text(text, &with_policy(ApostrophePolicy::Strip)),
+            " theyre oclock"
+        );
+        assert_eq!(
+            Corpus::clean_text(text, &with_policy(ApostrophePolicy::DropWord)),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_clean_corpus_spell_numbers() {
+        let options = CleaningOptions {
+            min_word_length: 3,
+            spell_numbers: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Corpus::clean_text("Chapter 42. Page 3.14 of 1,805", &options),
+            " chapter forty two page"
+        );
+        assert_eq!(Corpus::clean_text("Chapter 42", &cleaning(3)), " chapter");
+    }
+
+    #[test]
+    fn test_clean_corpus_script() {
+        let options = CleaningOptions {
+            min_word_length: 3,
+            script: Some(Script::Greek),
+            ..Default::default()
+        };
+        assert_eq!(
+            Corpus::clean_text("Hello καλημέρα κόσμε world λaθος", &options),
+            " καλημέρα κόσμε"
+        );
+    }
+
+    #[test]
+    fn test_clean_corpus_preserve_case() {
+        let text = "Der Hund sah die Katze. QUIET!";
+        let options = CleaningOptions {
+            preserve_case: true,
+            exclude_chars: vec!['q'],
+            ..cleaning(3)
+        };
+        assert_eq!(
+            Corpus::clean_text(text, &options),
+            " Der Hund sah die Katze"
+        );
+        assert_eq!(
+            Corpus::clean_text(text, &cleaning(3)),
+            " der hund sah die katze quiet"
+        );
+    }
+
+    #[test]
+    fn test_spell_number() {
+        assert_eq!(spell_number(0), "zero");
+        assert_eq!(spell_number(13), "thirteen");
+        assert_eq!(spell_number(40), "forty");
+        assert_eq!(spell_number(105), "one hundred five");
+        assert_eq!(spell_number(1_000_001), "one million one");
+        assert_eq!(
+            spell_number(987_654),
+            "nine hundred eighty seven thousand six hundred fifty four"
+        );
+        assert_eq!(
+            spell_number(u64::MAX),
+            "eighteen quintillion four hundred forty six quadrillion seven hundred forty four \
+             trillion seventy three billion seven hundred nine million five hundred fifty one \
+             thousand six hundred fifteen"
+        );
     }
 
     #[test]
     fn test_ngrams() {
-        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 3, 3).unwrap();
+        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 3, &cleaning(3)).unwrap();
         let ngrams = corpus.ngrams();
         assert_eq!(
             ngrams.collect::<Vec<_>>(),
             vec![" th", "thi", "his", "is ", "s t", " te", "tes", "est", "st ", "t t"]
         );
-        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 5, 3).unwrap();
+        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 5, &cleaning(3)).unwrap();
         let ngrams = corpus.ngrams();
         assert_eq!(
             ngrams.collect::<Vec<_>>(),
@@ -127,7 +913,7 @@ mod tests {
                 "t thi",
             ]
         );
-        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 3, 2).unwrap();
+        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 3, &cleaning(2)).unwrap();
         let ngrams = corpus.ngrams();
         assert_eq!(
             ngrams.collect::<Vec<_>>(),
@@ -137,4 +923,153 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_ngrams_window_and_step() {
+        let ngrams = |window, step| Ngrams::new("abcdé", window).step(step).collect::<Vec<_>>();
+        assert_eq!(ngrams(2, 1), vec!["ab", "bc", "cd", "dé"]);
+        assert_eq!(ngrams(2, 2), vec!["ab", "cd"]);
+        assert_eq!(ngrams(3, 2), vec!["abc", "cdé"]);
+        assert_eq!(ngrams(1, 3), vec!["a", "d"]);
+        assert_eq!(ngrams(6, 1), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_ngrams_reset_at_words() {
+        let text = " abcd efg";
+        let ngrams: Vec<_> = Ngrams::new(text, 4).step(3).reset_at_words().collect();
+        assert_eq!(ngrams, [" abc", "cd e", " efg"]);
+        let ngrams: Vec<_> = Ngrams::new(text, 4).step(3).collect();
+        assert_eq!(ngrams, [" abc", "cd e"]);
+        // A step of 1 already starts an ngram at every character.
+        let reset: Vec<_> = Ngrams::new(text, 4).reset_at_words().collect();
+        assert_eq!(reset, Ngrams::new(text, 4).collect::<Vec<_>>());
+        let stride = Stride {
+            step: 3,
+            reset_at_words: true,
+        };
+        assert_eq!(stride.shift(" abc"), 3);
+        assert_eq!(stride.shift("cd e"), 2);
+        assert_eq!(stride.shift(" efg"), 3);
+        assert_eq!(Stride::default().shift("cd e"), 1);
+    }
+
+    #[test]
+    fn test_words() {
+        let corpus = Corpus::new(Box::new("this is a test".as_bytes()), 3, &cleaning(3)).unwrap();
+        assert_eq!(corpus.words().collect::<Vec<_>>(), vec!["this", "test"]);
+    }
+
+    #[test]
+    fn test_corpus_stats() {
+        let corpus = Corpus::from_text("This is a test, this test.", 3, &cleaning(3));
+        assert_eq!(corpus.word_count(), 4);
+        assert_eq!(corpus.distinct_word_count(), 2);
+        assert_eq!(corpus.input_word_count(), 6);
+        assert!((corpus.dropped_word_ratio() - 2.0 / 6.0).abs() < 1e-12);
+        let histogram = corpus.char_histogram();
+        assert_eq!(histogram[&'t'], 6);
+        assert_eq!(histogram.get(&WORD_BOUNDARY), None);
+        assert_eq!(histogram.values().sum::<usize>(), 16);
+        let empty = Corpus::from_text("", 3, &cleaning(3));
+        assert_eq!(empty.dropped_word_ratio(), 0.0);
+        assert!(empty.char_histogram().is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_lines() {
+        let options = CleaningOptions {
+            dedupe_lines: true,
+            ..cleaning(1)
+        };
+        let mut deduplicator = Deduplicator::new(&options);
+        let text = "Licensed under MIT\nfirst  chapter\n\nLICENSED under MIT\n\nend";
+        assert_eq!(
+            deduplicator.dedupe(text),
+            "Licensed under MIT\nfirst  chapter\n\n\nend"
+        );
+        let corpus = Corpus::from_text_deduplicated(
+            "licensed   under mit\nmore",
+            3,
+            &options,
+            &mut deduplicator,
+        );
+        assert_eq!(corpus.text(), " more");
+        assert_eq!(Deduplicator::default().dedupe(text), text);
+    }
+
+    #[test]
+    fn test_dedupe_sentences() {
+        let options = CleaningOptions {
+            dedupe_sentences: true,
+            ..cleaning(1)
+        };
+        let mut deduplicator = Deduplicator::new(&options);
+        let text = "It rained. Mr. Darcy left!\nIt  rained.\nWhy? It rained";
+        assert_eq!(
+            deduplicator.dedupe(text),
+            "It rained. Mr. Darcy left! Why? It rained"
+        );
+        assert_eq!(
+            sentences("one. two.three? ").collect::<Vec<_>>(),
+            ["one.", "two.three?"]
+        );
+    }
+
+    /// A reader returning a few bytes at a time, to split text at awkward places.
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        reads: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            let length = (self.reads % 7 + 1).min(buf.len()).min(self.bytes.len());
+            buf[..length].copy_from_slice(&self.bytes[..length]);
+            self.bytes = &self.bytes[length..];
+            Ok(length)
+        }
+    }
+
+    #[test]
+    fn test_corpus_reader() {
+        let text = "Élan vital. Mr. Darcy\nleft! Élan vital.\nΚαλημέρα κόσμε\nMr. Darcy\nleft!\n\
+                    Élan vital. Naïve café 42";
+        let options = CleaningOptions {
+            dedupe_lines: true,
+            dedupe_sentences: true,
+            spell_numbers: true,
+            ..cleaning(1)
+        };
+        let expected = Corpus::from_text(text, 3, &options);
+        let mut deduplicator = Deduplicator::new(&options);
+        let reader = Trickle {
+            bytes: text.as_bytes(),
+            reads: 0,
+        };
+        let mut words = vec![];
+        let input_words = CorpusReader::new(reader, None, &options, &mut deduplicator)
+            .for_each_word(|word| words.push(word.to_string()))
+            .unwrap();
+        assert_eq!(words, expected.words().collect::<Vec<_>>());
+        assert_eq!(input_words, expected.input_word_count());
+        assert_eq!(words.first().map(String::as_str), Some("élan"));
+        assert!(words.len() < Corpus::from_text(text, 3, &cleaning(1)).word_count());
+    }
+
+    #[test]
+    fn test_chunk_end() {
+        assert_eq!(chunk_end("one\ntwo"), 4);
+        assert_eq!(chunk_end("one two"), 0);
+        let long = format!("{} end", "a".repeat(MAX_LINE_LENGTH));
+        assert_eq!(chunk_end(&long), MAX_LINE_LENGTH + 1);
+    }
+
+    fn cleaning(min_word_length: usize) -> CleaningOptions {
+        CleaningOptions {
+            min_word_length,
+            ..Default::default()
+        }
+    }
 }
@@ -0,0 +1,247 @@
+//! Downloading corpus files from `http://` and `https://` URLs.
+//!
+//! Downloads go through `curl`, the same way compressed files go through their command line
+//! tools, and are cached so each URL is only fetched once. `CORPUS_SETS` lists public domain
+//! texts which make good corpora, for populating a data directory.
+
+use crate::sha256;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A curated set of public domain texts to train on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorpusSet {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub texts: &'static [CorpusText],
+}
+
+/// A text in a `CorpusSet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorpusText {
+    /// The name the text is saved under.
+    pub file_name: &'static str,
+    pub url: &'static str,
+    /// The hex SHA-256 digest the download has to match.
+    pub sha256: &'static str,
+}
+
+/// Where the texts of `CORPUS_SETS` are downloaded from: the copies in the markovpass
+/// repository, which (unlike Project Gutenberg's, which are regenerated from time to time) don't
+/// change, so their digests can be pinned.
+macro_rules! repository_url {
+    ($path:literal) => {
+        concat!(
+            "https://raw.githubusercontent.com/julianandrews/markovpass/master/",
+            $path
+        )
+    };
+}
+
+/// The corpus sets which can be fetched.
+pub const CORPUS_SETS: &[CorpusSet] = &[CorpusSet {
+    name: "english",
+    description: "English novels and stories by Austen, Lovecraft and Wodehouse",
+    texts: &[
+        CorpusText {
+            file_name: "austen-pride-and-prejudice.txt",
+            url: repository_url!("testdata/Jane%20Austen%20-%20Pride%20and%20Prejudice.txt"),
+            sha256: "4fff6f740cc4cb3ff8d35d811e24f8799d80c73292c63e6692976f079951008d",
+        },
+        CorpusText {
+            file_name: "lovecraft-the-call-of-cthulhu.txt",
+            url: repository_url!("pkg/call-of-cthulu.txt"),
+            sha256: "745df1982a0348e55da139e3c208f79df7a2a3f5de41ae6d4c2b016449f7ac98",
+        },
+        CorpusText {
+            file_name: "wodehouse-my-man-jeeves.txt",
+            url: repository_url!("pkg/my-man-jeeves.txt"),
+            sha256: "49d84bd78db2bc075bef2bb119eaff521b9cb5969ea39bd145b01fbc57119054",
+        },
+    ],
+}];
+
+/// The corpus set called `name`.
+pub fn corpus_set(name: &str) -> Option<&'static CorpusSet> {
+    CORPUS_SETS.iter().find(|set| set.name == name)
+}
+
+/// Whether a corpus argument is a URL to download rather than a file.
+pub fn is_url(arg: &str) -> bool {
+    let scheme = arg.split_once("://").map(|(scheme, _)| scheme);
+    scheme.is_some_and(|scheme| {
+        scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+    })
+}
+
+/// The last part of the path of `url`, which names the file it downloads.
+pub fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+/// Where `url` is cached in `cache_dir`: named after its `file_name`, after a hash of the whole
+/// URL so different URLs never share a file.
+pub fn cache_path(url: &str, cache_dir: &Path) -> PathBuf {
+    let name: String = file_name(url)
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    let name = match name.trim_start_matches('.') {
+        "" => "index",
+        name => name,
+    };
+    let hash = &sha256::hex_digest(url.as_bytes())[..16];
+
+    cache_dir.join(format!("{}-{}", hash, name))
+}
+
+/// The cached copy of `url` in `cache_dir`, downloading it first if there isn't one.
+pub fn fetch(url: &str, cache_dir: &Path) -> io::Result<PathBuf> {
+    let path = cache_path(url, cache_dir);
+    if path.is_file() {
+        return Ok(path);
+    }
+    std::fs::create_dir_all(cache_dir)?;
+    download(url, &path)?;
+
+    Ok(path)
+}
+
+/// Download `url` to `path`, which is only created once the download succeeds.
+pub fn download(url: &str, path: &Path) -> io::Result<()> {
+    download_checked(url, path, None)
+}
+
+/// Download `text` to `path`, which is only created if the download matches its digest.
+pub fn download_text(text: &CorpusText, path: &Path) -> io::Result<()> {
+    download_checked(text.url, path, Some(text.sha256))
+}
+
+fn download_checked(url: &str, path: &Path, sha256: Option<&str>) -> io::Result<()> {
+    // Download next to the cached file so an interrupted download is never mistaken for one.
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let output = Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error"])
+        .args(["--proto", "=http,https", "--output"])
+        .arg(&partial)
+        .arg(url)
+        .output()
+        .map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                format!("Can't download '{}' without 'curl': {}", url, error),
+            )
+        })?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&partial);
+        let errors = String::from_utf8_lossy(&output.stderr);
+        let error = errors
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        return Err(io::Error::other(format!(
+            "Downloading '{}' failed ({}): {}",
+            url, output.status, error
+        )));
+    }
+    if let Some(expected) = sha256 {
+        let found = sha256::file_hex_digest(&partial)?;
+        if !found.eq_ignore_ascii_case(expected) {
+            let _ = std::fs::remove_file(&partial);
+            let mismatch = crate::ChecksumMismatch {
+                expected: expected.to_string(),
+                found,
+            };
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Downloaded '{}' but {}", url, mismatch),
+            ));
+        }
+    }
+    std::fs::rename(&partial, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://www.gutenberg.org/files/1342/1342-0.txt"));
+        assert!(is_url("HTTP://example.com"));
+        assert!(!is_url("ftp://example.com/corpus.txt"));
+        assert!(!is_url("corpus.txt"));
+        assert!(!is_url("http:corpus.txt"));
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let dir = Path::new("cache");
+        let path = cache_path("https://www.gutenberg.org/files/1342/1342-0.txt", dir);
+        assert!(path.starts_with(dir));
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(name.len(), 16 + "-1342-0.txt".len());
+        assert!(name.ends_with("-1342-0.txt"));
+        let other = cache_path("https://example.com/files/1342/1342-0.txt", dir);
+        assert_ne!(path, other);
+        let name = |url| {
+            let path = cache_path(url, dir);
+            path.file_name().unwrap().to_string_lossy()[17..].to_string()
+        };
+        assert_eq!(name("https://example.com/"), "index");
+        assert_eq!(name("https://example.com/a b.txt?x=1#y"), "a_b.txt");
+        assert_eq!(name("https://example.com/.."), "index");
+    }
+
+    #[test]
+    fn test_corpus_sets() {
+        assert_eq!(corpus_set("english").unwrap().name, "english");
+        assert_eq!(corpus_set("klingon"), None);
+        for set in CORPUS_SETS {
+            let names: std::collections::HashSet<_> =
+                set.texts.iter().map(|text| text.file_name).collect();
+            assert_eq!(names.len(), set.texts.len());
+            assert!(set.texts.iter().all(|text| is_url(text.url)));
+            assert!(set.texts.iter().all(|text| text.sha256.len() == 64));
+        }
+    }
+
+    #[test]
+    fn test_corpus_set_digests() {
+        // The pinned digests are those of the files in this repository.
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let prefix = repository_url!("");
+        for text in CORPUS_SETS.iter().flat_map(|set| set.texts) {
+            let path = text.url.strip_prefix(prefix).unwrap().replace("%20", " ");
+            let digest = sha256::file_hex_digest(&root.join(path)).unwrap();
+            assert_eq!(digest, text.sha256, "{}", text.file_name);
+        }
+    }
+
+    #[test]
+    fn test_fetch() {
+        let dir = std::env::temp_dir().join(format!("markovpass-download-{}", std::process::id()));
+        // Cached files are used without downloading anything.
+        let url = "https://example.com/corpus.txt";
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(cache_path(url, &dir), "cached").unwrap();
+        let cached = fetch(url, &dir).unwrap();
+        assert_eq!(std::fs::read_to_string(cached).unwrap(), "cached");
+        // Nothing listens on port 1, so this fails quickly without leaving a file behind.
+        let failed = fetch("http://127.0.0.1:1/corpus.txt", &dir);
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(failed.is_err());
+        assert_eq!(files, 1);
+    }
+}
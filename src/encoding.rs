@@ -0,0 +1,186 @@
+//! Decoding corpus text in the legacy encodings of older texts, like many Project Gutenberg ones.
+//!
+//! Unless told otherwise, text is read as UTF-8 up to the first byte which isn't valid UTF-8, and
+//! as Windows-1252 from there on, so it can be decoded as it streams in. Legacy text is almost
+//! never valid UTF-8 for long, so in practice it's all read as Windows-1252. That's what
+//! "Latin-1" text almost always really is: the two only differ for bytes 0x80 to 0x9f, which are
+//! control characters in Latin-1 and don't appear in real text.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    Windows1252,
+}
+
+/// The characters Windows-1252 has for bytes 0x80 to 0x9f. The five bytes it leaves undefined
+/// decode as the Latin-1 control characters.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    /// `bytes` decoded from the encoding. Only UTF-8 can fail, on invalid bytes.
+    pub fn decode(self, bytes: &[u8]) -> io::Result<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+            Self::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+            Self::Windows1252 => Ok(bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    b => char::from(b),
+                })
+                .collect()),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Utf8 => write!(f, "utf-8"),
+            Self::Latin1 => write!(f, "latin-1"),
+            Self::Windows1252 => write!(f, "windows-1252"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Self::Latin1),
+            "windows-1252" | "cp1252" => Ok(Self::Windows1252),
+            _ => Err(format!("Unknown encoding '{}'.", s)),
+        }
+    }
+}
+
+/// Decodes text a chunk at a time, keeping characters split between chunks until they're
+/// complete.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    encoding: Encoding,
+    /// Whether the encoding was given, rather than UTF-8 until the text turns out not to be.
+    fixed: bool,
+    /// The start of a UTF-8 character the last chunk ended part way through.
+    pending: Vec<u8>,
+    /// How many bytes have been decoded, for pointing out invalid ones.
+    position: usize,
+}
+
+impl Decoder {
+    /// A decoder for text in `encoding`, or UTF-8 switching to Windows-1252 if it's `None`.
+    pub fn new(encoding: Option<Encoding>) -> Self {
+        Self {
+            encoding: encoding.unwrap_or(Encoding::Utf8),
+            fixed: encoding.is_some(),
+            pending: vec![],
+            position: 0,
+        }
+    }
+
+    /// The text of the next chunk of `bytes`. `last` marks the end of the text, where a
+    /// character can't be incomplete any more.
+    pub fn decode(&mut self, bytes: &[u8], last: bool) -> io::Result<String> {
+        if self.encoding != Encoding::Utf8 {
+            return self.encoding.decode(bytes);
+        }
+        self.pending.extend_from_slice(bytes);
+        let (valid, error) = match std::str::from_utf8(&self.pending) {
+            Ok(_) => (self.pending.len(), None),
+            Err(error) => (error.valid_up_to(), Some(error)),
+        };
+        let rest = self.pending.split_off(valid);
+        let mut text = String::from_utf8(std::mem::replace(&mut self.pending, rest)).unwrap();
+        self.position += valid;
+        match error {
+            None => {}
+            // The chunk ends part way through a character.
+            Some(error) if error.error_len().is_none() && !last => {}
+            Some(_) if self.fixed => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid UTF-8 at byte {}.", self.position),
+                ))
+            }
+            Some(_) => {
+                self.encoding = Encoding::Windows1252;
+                text.push_str(&self.encoding.decode(&std::mem::take(&mut self.pending))?);
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+/// `bytes` decoded from `encoding`, or as UTF-8 switching to Windows-1252 at the first invalid
+/// byte if it's `None`.
+pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> io::Result<String> {
+    Decoder::new(encoding).decode(bytes, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let text = b"\x93caf\xe9\x94 \x80";
+        assert_eq!(decode(text, None).unwrap(), "“café” €");
+        assert_eq!(
+            decode(text, Some(Encoding::Latin1)).unwrap(),
+            "\u{93}café\u{94} \u{80}"
+        );
+        assert_eq!(decode(b"\x81", None).unwrap(), "\u{81}");
+        assert_eq!(
+            decode(text, Some(Encoding::Utf8)).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(decode("café".as_bytes(), None).unwrap(), "café");
+        // Text which stops being UTF-8 part way through is only Windows-1252 from there on.
+        assert_eq!(decode(b"caf\xc3\xa9 \xe9", None).unwrap(), "café é");
+    }
+
+    #[test]
+    fn test_decoder() {
+        let text = "naïve café";
+        let mut decoder = Decoder::new(None);
+        let mut decoded = String::new();
+        for byte in text.as_bytes() {
+            decoded.push_str(&decoder.decode(&[*byte], false).unwrap());
+        }
+        decoded.push_str(&decoder.decode(b"", true).unwrap());
+        assert_eq!(decoded, text);
+        // A character cut off at the end of the text is invalid.
+        let mut decoder = Decoder::new(None);
+        assert_eq!(decoder.decode(b"caf\xc3", false).unwrap(), "caf");
+        assert_eq!(decoder.decode(b"", true).unwrap(), "Ã");
+        let mut decoder = Decoder::new(Some(Encoding::Utf8));
+        assert_eq!(decoder.decode(b"caf\xc3", false).unwrap(), "caf");
+        assert_eq!(decoder.decode(b"\xa9 na\xef", false).unwrap(), "é na");
+        let error = decoder.decode(b"ve", true).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid UTF-8 at byte 8.");
+        let mut decoder = Decoder::new(Some(Encoding::Latin1));
+        assert_eq!(decoder.decode(b"caf\xe9", true).unwrap(), "café");
+    }
+
+    #[test]
+    fn test_parse() {
+        for encoding in [Encoding::Utf8, Encoding::Latin1, Encoding::Windows1252] {
+            assert_eq!(encoding.to_string().parse(), Ok(encoding));
+        }
+        assert_eq!("CP1252".parse(), Ok(Encoding::Windows1252));
+        assert_eq!("ISO-8859-1".parse(), Ok(Encoding::Latin1));
+        assert!("ebcdic".parse::<Encoding>().is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Experimental ensemble checks of how natural invented words look.
+//!
+//! Each `WordModel` scores words by their surprisal: the bits of information per character
+//! needed to pick the word under the model. An `Ensemble` calibrates a threshold for each model
+//! from the corpus words, and only accepts words every model finds no more surprising than most
+//! real words. A character model catches unusual letter runs and a syllable model unusual
+//! syllable sequences, which a chain of short ngrams can't see.
+
+use crate::generator::VOWELS;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Marks the start and end of words in contexts.
+const BOUNDARY: &str = " ";
+
+pub trait WordModel: fmt::Debug + Send + Sync {
+    /// The bits of information needed to pick `word` under the model.
+    fn surprisal(&self, word: &str) -> f64;
+
+    /// `surprisal` per character, so words of different lengths can be compared.
+    fn surprisal_per_char(&self, word: &str) -> f64 {
+        let length = word.chars().count().max(1);
+        self.surprisal(word) / length as f64
+    }
+}
+
+/// Counts of the units following each context, with additive smoothing.
+#[derive(Debug, Clone, Default)]
+struct SmoothedCounts {
+    counts: HashMap<String, HashMap<String, u64>>,
+    totals: HashMap<String, u64>,
+    vocabulary: usize,
+}
+
+impl SmoothedCounts {
+    /// How much weight every unit gets in every context, seen or not.
+    const SMOOTHING: f64 = 0.5;
+
+    fn add(&mut self, context: &str, unit: &str) {
+        *self
+            .counts
+            .entry(context.to_string())
+            .or_default()
+            .entry(unit.to_string())
+            .or_insert(0) += 1;
+        *self.totals.entry(context.to_string()).or_insert(0) += 1;
+    }
+
+    /// Set the number of distinct units, with one more for unseen ones.
+    fn finish(&mut self) {
+        let units: std::collections::HashSet<&String> =
+            self.counts.values().flat_map(HashMap::keys).collect();
+        self.vocabulary = units.len() + 1;
+    }
+
+    fn bits(&self, context: &str, unit: &str) -> f64 {
+        let count = self
+            .counts
+            .get(context)
+            .and_then(|units| units.get(unit))
+            .copied()
+            .unwrap_or(0);
+        let total = self.totals.get(context).copied().unwrap_or(0);
+        let probability = (count as f64 + Self::SMOOTHING)
+            / (total as f64 + Self::SMOOTHING * self.vocabulary as f64);
+
+        -probability.log2()
+    }
+}
+
+/// A character model predicting each character (and the end of the word) from the `order`
+/// characters before it.
+#[derive(Debug, Clone)]
+pub struct CharacterModel {
+    order: usize,
+    counts: SmoothedCounts,
+}
+
+impl CharacterModel {
+    /// Panics if `order` is 0.
+    pub fn train<'a>(words: impl Iterator<Item = &'a str>, order: usize) -> Self {
+        assert!(order > 0, "order must be positive");
+        let mut counts = SmoothedCounts::default();
+        for word in words {
+            for (context, unit) in Self::events(word, order) {
+                counts.add(&context, &unit);
+            }
+        }
+        counts.finish();
+
+        Self { order, counts }
+    }
+
+    /// Each character of `word` and its end, with the characters before them, padded with
+    /// boundaries.
+    fn events(word: &str, order: usize) -> Vec<(String, String)> {
+        let mut chars: Vec<String> = vec![BOUNDARY.to_string(); order];
+        chars.extend(word.to_lowercase().chars().map(String::from));
+        chars.push(BOUNDARY.to_string());
+        (order..chars.len())
+            .map(|i| (chars[i - order..i].concat(), chars[i].clone()))
+            .collect()
+    }
+}
+
+impl WordModel for CharacterModel {
+    fn surprisal(&self, word: &str) -> f64 {
+        Self::events(word, self.order)
+            .iter()
+            .map(|(context, unit)| self.counts.bits(context, unit))
+            .sum()
+    }
+}
+
+/// A bigram model of syllables, predicting each syllable (and the end of the word) from the one
+/// before it.
+#[derive(Debug, Clone)]
+pub struct SyllableModel {
+    counts: SmoothedCounts,
+}
+
+impl SyllableModel {
+    pub fn train<'a>(words: impl Iterator<Item = &'a str>) -> Self {
+        let mut counts = SmoothedCounts::default();
+        for word in words {
+            for (context, unit) in Self::events(word) {
+                counts.add(&context, &unit);
+            }
+        }
+        counts.finish();
+
+        Self { counts }
+    }
+
+    fn events(word: &str) -> Vec<(String, String)> {
+        let mut units = vec![BOUNDARY.to_string()];
+        units.extend(syllables(&word.to_lowercase()));
+        units.push(BOUNDARY.to_string());
+        units
+            .windows(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+}
+
+impl WordModel for SyllableModel {
+    fn surprisal(&self, word: &str) -> f64 {
+        Self::events(word)
+            .iter()
+            .map(|(context, unit)| self.counts.bits(context, unit))
+            .sum()
+    }
+}
+
+/// Split `word` into rough syllables, each a vowel group with the consonants around it. Of the
+/// consonants between two vowel groups, a single one starts the next syllable, and otherwise the
+/// first ends the previous one. "y" is a vowel unless it starts the word or comes before a
+/// vowel.
+pub fn syllables(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let is_vowel: Vec<bool> = chars
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| match c {
+            'y' => i > 0 && !chars.get(i + 1).is_some_and(|&c| VOWELS.contains(c)),
+            c => VOWELS.contains(c),
+        })
+        .collect();
+    // Where each vowel group starts and ends.
+    let mut groups: Vec<(usize, usize)> = vec![];
+    for (i, &vowel) in is_vowel.iter().enumerate() {
+        if vowel {
+            match groups.last_mut() {
+                Some((_, end)) if *end == i => *end = i + 1,
+                _ => groups.push((i, i + 1)),
+            }
+        }
+    }
+    if groups.len() < 2 {
+        return vec![word.to_string()];
+    }
+    let mut breaks = vec![];
+    for pair in groups.windows(2) {
+        let (end, next) = (pair[0].1, pair[1].0);
+        breaks.push(if next - end > 1 { end + 1 } else { end });
+    }
+    let mut syllables = vec![];
+    let mut start = 0;
+    for end in breaks.into_iter().chain([chars.len()]) {
+        syllables.push(chars[start..end].iter().collect());
+        start = end;
+    }
+
+    syllables
+}
+
+/// Word models with the highest surprisal per character each accepts.
+#[derive(Debug, Default)]
+pub struct Ensemble {
+    members: Vec<(Box<dyn WordModel>, f64)>,
+}
+
+impl Ensemble {
+    /// A character model one character longer than `ngram_length` and a syllable model trained
+    /// on `words`, each accepting words up to the `percentile` (0 to 100) of their surprisal over
+    /// the distinct corpus words.
+    pub fn train(words: &[&str], ngram_length: usize, percentile: f64) -> Self {
+        let mut distinct: Vec<&str> = words.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let mut ensemble = Self::default();
+        let character = CharacterModel::train(words.iter().copied(), ngram_length + 1);
+        ensemble.add(Box::new(character), &distinct, percentile);
+        let syllable = SyllableModel::train(words.iter().copied());
+        ensemble.add(Box::new(syllable), &distinct, percentile);
+
+        ensemble
+    }
+
+    /// Add `model`, accepting words up to the `percentile` of its surprisal over `words`.
+    pub fn add(&mut self, model: Box<dyn WordModel>, words: &[&str], percentile: f64) {
+        let mut surprisals: Vec<f64> = words
+            .iter()
+            .map(|word| model.surprisal_per_char(word))
+            .collect();
+        surprisals.sort_by(f64::total_cmp);
+        let threshold = match surprisals.len() {
+            0 => f64::INFINITY,
+            len => {
+                let index = (percentile.clamp(0.0, 100.0) / 100.0 * (len - 1) as f64).round();
+                surprisals[index as usize]
+            }
+        };
+        self.members.push((model, threshold));
+    }
+
+    /// Whether every model finds `word` natural enough.
+    pub fn accepts(&self, word: &str) -> bool {
+        self.members
+            .iter()
+            .all(|(model, threshold)| model.surprisal_per_char(word) <= *threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: [&str; 12] = [
+        "banana", "bandana", "cabana", "canal", "panama", "banal", "nanny", "cannon", "pecan",
+        "cancan", "canny", "manna",
+    ];
+
+    #[test]
+    fn test_syllables() {
+        assert_eq!(syllables("banana"), ["ba", "na", "na"]);
+        assert_eq!(syllables("bandana"), ["ban", "da", "na"]);
+        assert_eq!(syllables("strength"), ["strength"]);
+        assert_eq!(syllables("queueing"), ["queueing"]);
+        assert_eq!(syllables("canny"), ["can", "ny"]);
+        assert_eq!(syllables("yoyo"), ["yo", "yo"]);
+        assert_eq!(syllables(""), [""]);
+    }
+
+    #[test]
+    fn test_word_models() {
+        let character = CharacterModel::train(WORDS.into_iter(), 2);
+        assert!(character.surprisal("banana") < character.surprisal("bnaana"));
+        assert!(character.surprisal_per_char("canna") < character.surprisal_per_char("xqzv"));
+        let syllable = SyllableModel::train(WORDS.into_iter());
+        assert!(syllable.surprisal("banana") < syllable.surprisal("bazuzo"));
+    }
+
+    #[test]
+    fn test_ensemble() {
+        let ensemble = Ensemble::train(&WORDS, 2, 100.0);
+        assert!(WORDS.iter().all(|word| ensemble.accepts(word)));
+        assert!(!ensemble.accepts("xqzvk"));
+        assert!(!ensemble.accepts("nbanaa"));
+        assert!(Ensemble::default().accepts("xqzvk"));
+    }
+}
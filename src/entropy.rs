@@ -0,0 +1,134 @@
+//! Entropy of discrete probability distributions.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Ways of measuring a passphrase's entropy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntropyMeasure {
+    /// The Shannon entropy: the average information of the choices made. Entropy requirements like
+    /// `min_entropy` are in Shannon entropy.
+    Shannon,
+    /// The min-entropy, counting only the most likely option of each choice: the worst case, for
+    /// attackers who try the likeliest passphrases first.
+    Min,
+}
+
+impl fmt::Display for EntropyMeasure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Shannon => write!(f, "shannon"),
+            Self::Min => write!(f, "min"),
+        }
+    }
+}
+
+impl FromStr for EntropyMeasure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "shannon" => Ok(Self::Shannon),
+            "min" => Ok(Self::Min),
+            _ => Err(format!("Unknown entropy measure '{}'.", s)),
+        }
+    }
+}
+
+/// The Shannon entropy in bits of the distribution with the given relative weights.
+pub fn shannon_entropy(weights: &[f64]) -> f64 {
+    let total: f64 = weights.iter().sum();
+    weights.iter().fold(0.0, |acc, weight| {
+        let prob = weight / total;
+        if prob > 0.0 {
+            acc - prob * prob.log2()
+        } else {
+            acc
+        }
+    })
+}
+
+/// The Shannon entropy in bits of the distribution with the given integer counts.
+///
+/// The total is summed exactly, so unlike `shannon_entropy` this doesn't lose precision to
+/// rounding when there are very many or very large counts.
+pub fn count_entropy(counts: &[u64]) -> f64 {
+    let total: u128 = counts.iter().map(|&count| u128::from(count)).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    counts.iter().fold(0.0, |acc, &count| {
+        if count > 0 {
+            let prob = count as f64 / total;
+            acc - prob * prob.log2()
+        } else {
+            acc
+        }
+    })
+}
+
+/// The min-entropy in bits of the distribution with the given integer counts, with the total
+/// summed exactly as in `count_entropy`.
+pub fn count_min_entropy(counts: &[u64]) -> f64 {
+    let total: u128 = counts.iter().map(|&count| u128::from(count)).sum();
+    match counts.iter().max() {
+        Some(&max) if max > 0 => (total as f64 / max as f64).log2(),
+        _ => 0.0,
+    }
+}
+
+/// The min-entropy in bits of the distribution with the given relative weights.
+///
+/// Min-entropy only depends on the most likely outcome, so it measures how well an attacker does
+/// with a single best guess.
+pub fn min_entropy(weights: &[f64]) -> f64 {
+    let total: f64 = weights.iter().sum();
+    let max = weights.iter().cloned().fold(0.0, f64::max);
+    if max > 0.0 {
+        -(max / total).log2()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shannon_entropy() {
+        assert_eq!(shannon_entropy(&[1.0, 1.0]), 1.0);
+        assert_eq!(shannon_entropy(&[5.0; 8]), 3.0);
+        assert_eq!(shannon_entropy(&[1.0, 0.0]), 0.0);
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert!((shannon_entropy(&[3.0, 1.0, 1.0]) - 1.370951).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_count_entropy() {
+        assert_eq!(count_entropy(&[1, 1]), 1.0);
+        assert_eq!(count_entropy(&[5; 8]), 3.0);
+        assert_eq!(count_entropy(&[1, 0]), 0.0);
+        assert_eq!(count_entropy(&[]), 0.0);
+        assert!((count_entropy(&[3, 1, 1]) - 1.370951).abs() < 1e-6);
+        assert_eq!(count_entropy(&[u64::MAX, u64::MAX]), 1.0);
+    }
+
+    #[test]
+    fn test_min_entropy() {
+        assert_eq!(min_entropy(&[1.0, 1.0]), 1.0);
+        assert_eq!(min_entropy(&[2.0, 1.0, 1.0]), 1.0);
+        assert_eq!(min_entropy(&[1.0]), 0.0);
+        assert_eq!(min_entropy(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_count_min_entropy() {
+        assert_eq!(count_min_entropy(&[1, 1]), 1.0);
+        assert_eq!(count_min_entropy(&[2, 1, 1]), 1.0);
+        assert_eq!(count_min_entropy(&[1]), 0.0);
+        assert_eq!(count_min_entropy(&[]), 0.0);
+        assert_eq!(count_min_entropy(&[u64::MAX, u64::MAX]), 1.0);
+    }
+}
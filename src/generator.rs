@@ -0,0 +1,563 @@
+//! Passphrase generation with rejection of candidates that don't meet the options.
+
+use crate::audit::AuditRecord;
+use crate::corpus::WORD_BOUNDARY;
+use crate::markovchain::{Normalization, PassphraseMarkovChain};
+use crate::{GenPassphraseOptions, Passphrase};
+use rand::{RngCore, SeedableRng};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How the words of a passphrase are put together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Style {
+    /// Words joined with the separator ("lydia bennet").
+    #[default]
+    Plain,
+    /// Words joined with no separator, capitalizing all but the first ("lydiaBennet").
+    CompactCamel,
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Plain => write!(f, "plain"),
+            Self::CompactCamel => write!(f, "compactCamel"),
+        }
+    }
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "compactCamel" => Ok(Self::CompactCamel),
+            _ => Err(format!(
+                "Unknown style '{}'. Expected plain or compactCamel.",
+                s
+            )),
+        }
+    }
+}
+
+/// Why a candidate passphrase was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rejection {
+    MaxEntropy,
+    ExcludedChars,
+    Homoglyphs,
+    ConsonantRun,
+    ShortWord,
+    KnownWord,
+    CommonSubstring,
+    Unnatural,
+    Policy,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MaxEntropy => write!(f, "max entropy"),
+            Self::ExcludedChars => write!(f, "excluded characters"),
+            Self::Homoglyphs => write!(f, "homoglyphs"),
+            Self::ConsonantRun => write!(f, "consonant runs"),
+            Self::ShortWord => write!(f, "short words"),
+            Self::KnownWord => write!(f, "known words"),
+            Self::CommonSubstring => write!(f, "common substrings"),
+            Self::Unnatural => write!(f, "unnatural words"),
+            Self::Policy => write!(f, "policy"),
+        }
+    }
+}
+
+/// Counts of rejected candidates by reason.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rejections(BTreeMap<Rejection, usize>);
+
+impl Rejections {
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+
+    pub fn count(&self, rejection: Rejection) -> usize {
+        self.0.get(&rejection).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, rejection: Rejection) {
+        *self.0.entry(rejection).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: &Rejections) {
+        for (&rejection, &count) in &other.0 {
+            *self.0.entry(rejection).or_insert(0) += count;
+        }
+    }
+}
+
+impl fmt::Display for Rejections {
+    /// The most common reasons first, e.g. "12 max entropy, 3 policy".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut counts: Vec<_> = self.0.iter().collect();
+        counts.sort_by_key(|&(_, &count)| std::cmp::Reverse(count));
+        let counts: Vec<_> = counts
+            .into_iter()
+            .map(|(rejection, count)| format!("{} {}", count, rejection))
+            .collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
+/// How much work generating passphrases took, for monitoring how efficiently the options can be
+/// met.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerationStats {
+    /// Candidates generated, including the rejected ones.
+    pub attempts: usize,
+    /// The rejected candidates by reason.
+    pub rejections: Rejections,
+    /// Time spent generating.
+    pub elapsed: Duration,
+}
+
+impl GenerationStats {
+    /// The fraction of candidates which weren't rejected, from 0 to 1 (1 if there weren't any).
+    pub fn acceptance_rate(&self) -> f64 {
+        match self.attempts {
+            0 => 1.0,
+            attempts => attempts.saturating_sub(self.rejections.total()) as f64 / attempts as f64,
+        }
+    }
+
+    pub fn merge(&mut self, other: &GenerationStats) {
+        self.attempts += other.attempts;
+        self.rejections.merge(&other.rejections);
+        self.elapsed += other.elapsed;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerationError {
+    InvalidEntropyRange(f64, f64),
+    TooManyRejections(Rejections),
+    Transform(String),
+}
+
+impl std::error::Error for GenerationError {}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidEntropyRange(min, max) => write!(
+                f,
+                "Maximum entropy {} is less than the minimum entropy {}.",
+                max, min
+            ),
+            Self::TooManyRejections(rejections) => write!(
+                f,
+                "No acceptable passphrase found after {} rejected candidates ({}). Try relaxing \
+                 the constraints.",
+                rejections.total(),
+                rejections
+            ),
+            Self::Transform(error) => write!(f, "Output transform failed: {}", error),
+        }
+    }
+}
+
+pub struct Generator<'a> {
+    candidates: Candidates<'a>,
+    rng: Box<dyn RngCore>,
+    stats: GenerationStats,
+    /// The chain's fingerprint, once it's needed for audit records.
+    fingerprint: Option<String>,
+}
+
+/// Generates candidates and checks them against the options.
+struct Candidates<'a> {
+    chain: &'a PassphraseMarkovChain,
+    options: &'a GenPassphraseOptions,
+    exclude_chars: Vec<char>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(
+        chain: &'a PassphraseMarkovChain,
+        options: &'a GenPassphraseOptions,
+    ) -> Result<Self, GenerationError> {
+        if let Some(max_entropy) = options.max_entropy {
+            if max_entropy < options.min_entropy {
+                return Err(GenerationError::InvalidEntropyRange(
+                    options.min_entropy,
+                    max_entropy,
+                ));
+            }
+        }
+
+        Ok(Self {
+            candidates: Candidates {
+                chain,
+                options,
+                exclude_chars: options.exclude_chars.to_lowercase().chars().collect(),
+            },
+            rng: match options.seed {
+                Some(seed) => Box::new(rand_chacha::ChaCha20Rng::seed_from_u64(seed)),
+                None => Box::new(rand::rngs::OsRng),
+            },
+            stats: GenerationStats::default(),
+            fingerprint: None,
+        })
+    }
+
+    /// Generate a passphrase, rejecting candidates until one meets all the options.
+    ///
+    /// Gives up once `max_retries` candidates in a row have been rejected. In audit mode each
+    /// passphrase is generated from its own stream, seeded from the generator's random source.
+    pub fn passphrase(&mut self) -> Result<Passphrase, GenerationError> {
+        if self.candidates.options.audit {
+            let mut seed = [0; 32];
+            self.rng.fill_bytes(&mut seed);
+            return self.replay(seed);
+        }
+        let start = Instant::now();
+        let (result, stats) = self.candidates.search(&mut *self.rng);
+        self.record(&stats, start);
+
+        result
+    }
+
+    /// Generate the passphrase audit mode generates from `seed`, with its audit record.
+    pub fn replay(&mut self, seed: [u8; 32]) -> Result<Passphrase, GenerationError> {
+        let start = Instant::now();
+        let chain = self.candidates.chain;
+        let fingerprint = self.fingerprint.get_or_insert_with(|| chain.fingerprint());
+        let mut rng = rand_chacha::ChaCha20Rng::from_seed(seed);
+        let (result, stats) = self.candidates.search(&mut rng);
+        let result = result.map(|passphrase| Passphrase {
+            audit: Some(AuditRecord::new(fingerprint, seed)),
+            ..passphrase
+        });
+        self.record(&stats, start);
+
+        result
+    }
+
+    /// Every candidate rejected so far.
+    pub fn rejections(&self) -> &Rejections {
+        &self.stats.rejections
+    }
+
+    /// The work done generating so far.
+    pub fn stats(&self) -> &GenerationStats {
+        &self.stats
+    }
+
+    /// Add the candidates tried by a search started at `start` to the stats.
+    fn record(&mut self, stats: &GenerationStats, start: Instant) {
+        self.stats.merge(stats);
+        self.stats.elapsed += start.elapsed();
+    }
+}
+
+impl Candidates<'_> {
+    /// Find a passphrase meeting the options, with the candidates tried (but not the time taken).
+    /// With a scorer, the best scoring of `best_of` passphrases is picked.
+    fn search(
+        &self,
+        rng: &mut dyn RngCore,
+    ) -> (Result<Passphrase, GenerationError>, GenerationStats) {
+        let scorer = match &self.options.scorer {
+            Some(scorer) if self.options.best_of > 1 => scorer,
+            _ => return self.search_one(rng),
+        };
+        let (mut best, mut stats) = self.search_one(rng);
+        let mut best_score = match &best {
+            Ok(passphrase) => scorer.score_passphrase(&passphrase.words),
+            Err(_) => return (best, stats),
+        };
+        for _ in 1..self.options.best_of {
+            let (result, tried) = self.search_one(rng);
+            stats.merge(&tried);
+            let passphrase = match result {
+                Ok(passphrase) => passphrase,
+                Err(error) => return (Err(error), stats),
+            };
+            let score = scorer.score_passphrase(&passphrase.words);
+            if score > best_score {
+                if let Ok(beaten) = std::mem::replace(&mut best, Ok(passphrase)) {
+                    wipe_passphrase(beaten);
+                }
+                best_score = score;
+            } else {
+                wipe_passphrase(passphrase);
+            }
+        }
+
+        (best, stats)
+    }
+
+    /// The bits of entropy output differs from the chain's passphrases by.
+    fn entropy_adjustment(&self) -> f64 {
+        let transform = self
+            .options
+            .transform
+            .as_ref()
+            .map_or(0.0, |transform| transform.entropy_adjustment());
+        // An attacker knowing the scorer only has to guess among the best scoring passphrases.
+        let selection = match &self.options.scorer {
+            Some(_) if self.options.best_of > 1 => (self.options.best_of as f64).log2(),
+            _ => 0.0,
+        };
+
+        transform - selection
+    }
+
+    /// Generate candidates from `rng` until one meets the options, with the candidates tried.
+    fn search_one(
+        &self,
+        rng: &mut dyn RngCore,
+    ) -> (Result<Passphrase, GenerationError>, GenerationStats) {
+        let mut stats = GenerationStats::default();
+        let adjustment = self.entropy_adjustment();
+        // Attackers can lowercase guesses, and separators are the only sign of word boundaries
+        // except in camel case.
+        let normalization = Normalization {
+            fold_case: true,
+            drop_boundaries: self.options.style == Style::Plain
+                && self.options.separator.is_empty(),
+        };
+        let min_entropy = self.options.min_entropy - adjustment;
+        let min_normalized_entropy = match self.options.enforce_normalized_entropy {
+            true => min_entropy,
+            false => 0.0,
+        };
+        let result = loop {
+            stats.attempts += 1;
+            let candidate = self.chain.normalized_passphrase_with_rng(
+                min_entropy,
+                min_normalized_entropy,
+                normalization,
+                rng,
+            );
+            let passphrase = candidate.text;
+            let entropy = candidate.entropy + adjustment;
+            let normalized_entropy = candidate.normalized_entropy + adjustment;
+            let min_entropy_bits = candidate.min_entropy + adjustment;
+            let checked = self.check(&passphrase, entropy);
+            let assembled = checked.map(|()| self.assemble(&passphrase));
+            // Rejected candidates and the chain's text are as sensitive as the output.
+            crate::wipe(passphrase);
+            let rejection = match assembled {
+                Ok(assembled) => {
+                    let (text, words) = match assembled {
+                        Ok(assembled) => assembled,
+                        Err(error) => break Err(error),
+                    };
+                    // Policies apply to the passphrase as it's output.
+                    match &self.options.policy {
+                        Some(policy) if !policy.allows(&text) => {
+                            crate::wipe(text);
+                            words.into_iter().for_each(crate::wipe);
+                            Rejection::Policy
+                        }
+                        _ => {
+                            break Ok(Passphrase {
+                                text,
+                                words,
+                                entropy,
+                                normalized_entropy,
+                                min_entropy: min_entropy_bits,
+                                audit: None,
+                            })
+                        }
+                    }
+                }
+                Err(rejection) => rejection,
+            };
+            stats.rejections.add(rejection);
+            if stats.rejections.total() > self.options.max_retries {
+                break Err(GenerationError::TooManyRejections(stats.rejections.clone()));
+            }
+        };
+
+        (result, stats)
+    }
+
+    /// Check the chain's `passphrase` against the options which apply to its words.
+    fn check(&self, passphrase: &str, entropy: f64) -> Result<(), Rejection> {
+        if self.options.max_entropy.is_some_and(|max| entropy > max) {
+            return Err(Rejection::MaxEntropy);
+        }
+        // Models may have been trained on words containing excluded characters.
+        let excluded = |c: char| c.to_lowercase().any(|c| self.exclude_chars.contains(&c));
+        if passphrase.chars().any(excluded) {
+            return Err(Rejection::ExcludedChars);
+        }
+        if self.options.no_homoglyphs && crate::script::has_homoglyphs(passphrase) {
+            return Err(Rejection::Homoglyphs);
+        }
+        if let Some(max_consonants) = self.options.max_consonants {
+            if passphrase
+                .split(WORD_BOUNDARY)
+                .any(|word| longest_consonant_run(word) > max_consonants)
+            {
+                return Err(Rejection::ConsonantRun);
+            }
+        }
+        if let Some(min_length) = self.options.min_output_word_length {
+            if passphrase
+                .split(WORD_BOUNDARY)
+                .any(|word| word.chars().count() < min_length)
+            {
+                return Err(Rejection::ShortWord);
+            }
+        }
+        if let Some(filter) = &self.options.reject_words {
+            if passphrase
+                .split(WORD_BOUNDARY)
+                .any(|word| filter.contains(word))
+            {
+                return Err(Rejection::KnownWord);
+            }
+        }
+        if let Some(substrings) = &self.options.reject_substrings {
+            if substrings.find(passphrase).is_some() {
+                return Err(Rejection::CommonSubstring);
+            }
+        }
+        if let Some(ensemble) = &self.options.naturalness {
+            if !passphrase
+                .split(WORD_BOUNDARY)
+                .all(|word| ensemble.accepts(word))
+            {
+                return Err(Rejection::Unnatural);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The output form of `passphrase` and its words.
+    fn assemble(&self, passphrase: &str) -> Result<(String, Vec<String>), GenerationError> {
+        let words = self.style_words(passphrase);
+        let passphrase = match self.options.style {
+            Style::Plain => words.join(&self.options.separator),
+            Style::CompactCamel => words.concat(),
+        };
+        let transform = match &self.options.transform {
+            Some(transform) => transform,
+            None => return Ok((passphrase, words)),
+        };
+        let transformed = transform.transform(&passphrase);
+        crate::wipe(passphrase);
+        match transformed {
+            Ok(transformed) => Ok((transformed, words)),
+            Err(error) => {
+                words.into_iter().for_each(crate::wipe);
+                Err(GenerationError::Transform(error.to_string()))
+            }
+        }
+    }
+
+    /// The words of `passphrase` as they appear in the output.
+    fn style_words(&self, passphrase: &str) -> Vec<String> {
+        let words = passphrase.split(WORD_BOUNDARY);
+        match self.options.style {
+            Style::Plain => words.map(str::to_string).collect(),
+            Style::CompactCamel => words
+                .enumerate()
+                .map(|(i, word)| match i {
+                    0 => word.to_string(),
+                    _ => capitalize(word),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Wipe the text and words of a passphrase which won't be output.
+fn wipe_passphrase(passphrase: Passphrase) {
+    crate::wipe(passphrase.text);
+    passphrase.words.into_iter().for_each(crate::wipe);
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Vowels (including 'y') in the Latin, Greek and Cyrillic scripts.
+pub(crate) const VOWELS: &str = "aeiouyàáâãäåæèéêëìíîïòóôõöøùúûüýÿαεηιουωάέήίόύώаеёиоуыэюя";
+
+/// The length of the longest run of consecutive consonants in `word`.
+fn longest_consonant_run(word: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in word.chars() {
+        let is_consonant =
+            c.is_alphabetic() && !c.to_lowercase().all(|lower| VOWELS.contains(lower));
+        current = if is_consonant { current + 1 } else { 0 };
+        longest = longest.max(current);
+    }
+
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejections() {
+        let mut rejections = Rejections::default();
+        assert_eq!(rejections.to_string(), "");
+        rejections.add(Rejection::Policy);
+        for _ in 0..3 {
+            rejections.add(Rejection::MaxEntropy);
+        }
+        assert_eq!(rejections.total(), 4);
+        assert_eq!(rejections.count(Rejection::MaxEntropy), 3);
+        assert_eq!(rejections.to_string(), "3 max entropy, 1 policy");
+    }
+
+    #[test]
+    fn test_generation_stats() {
+        let mut stats = GenerationStats::default();
+        assert_eq!(stats.acceptance_rate(), 1.0);
+        let mut rejections = Rejections::default();
+        rejections.add(Rejection::Policy);
+        stats.merge(&GenerationStats {
+            attempts: 4,
+            rejections,
+            elapsed: Duration::from_millis(3),
+        });
+        stats.merge(&stats.clone());
+        assert_eq!(stats.attempts, 8);
+        assert_eq!(stats.rejections.count(Rejection::Policy), 2);
+        assert_eq!(stats.elapsed, Duration::from_millis(6));
+        assert_eq!(stats.acceptance_rate(), 0.75);
+    }
+
+    #[test]
+    fn test_capitalize() {
+        assert_eq!(capitalize("bennet"), "Bennet");
+        assert_eq!(capitalize("ßtraße"), "SStraße");
+        assert_eq!(capitalize(""), "");
+    }
+
+    #[test]
+    fn test_longest_consonant_run() {
+        assert_eq!(longest_consonant_run("strength"), 4);
+        assert_eq!(longest_consonant_run("Rhythm"), 3);
+        assert_eq!(longest_consonant_run("aeiou"), 0);
+        assert_eq!(longest_consonant_run("don't"), 1);
+        assert_eq!(longest_consonant_run("schön"), 3);
+        assert_eq!(longest_consonant_run(""), 0);
+    }
+}
@@ -0,0 +1,62 @@
+//! Memorization hints for generated passphrases.
+//!
+//! A hint is a sentence of real corpus words, each sharing as long a prefix as possible with the
+//! corresponding passphrase word. It's only an aid to remembering the passphrase, never a way to
+//! generate one, and since it gives away much of the passphrase it should be kept just as secret.
+
+/// Build a hint sentence for the passphrase `words` from the sorted, distinct `corpus_words`.
+///
+/// Returns `None` if some word shares no prefix with any other corpus word.
+pub fn mnemonic_hint(words: &[&str], corpus_words: &[String]) -> Option<String> {
+    let hint_words = words
+        .iter()
+        .map(|word| hint_word(word, corpus_words))
+        .collect::<Option<Vec<_>>>()?;
+    let mut sentence = hint_words.join(" ");
+    if let Some(first) = sentence.chars().next() {
+        let rest = sentence.split_off(first.len_utf8());
+        sentence = first.to_uppercase().chain(rest.chars()).collect();
+        sentence.push('.');
+    }
+
+    Some(sentence)
+}
+
+/// The corpus word, other than `word` itself, with the longest common prefix with `word`.
+///
+/// Ties go to the shortest candidate so hints stay short.
+fn hint_word<'a>(word: &str, corpus_words: &'a [String]) -> Option<&'a str> {
+    corpus_words
+        .iter()
+        .filter(|candidate| candidate.as_str() != word)
+        .map(|candidate| (common_prefix_length(word, candidate), candidate))
+        .filter(|&(length, _)| length > 0)
+        .min_by_key(|&(length, candidate)| (std::cmp::Reverse(length), candidate.chars().count()))
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+fn common_prefix_length(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mnemonic_hint() {
+        let corpus_words: Vec<String> = ["bennet", "darcy", "dared", "lizzy", "longbourn", "lydia"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            mnemonic_hint(&["darling", "lyre", "bent"], &corpus_words),
+            Some("Darcy lydia bennet.".to_string())
+        );
+        assert_eq!(
+            mnemonic_hint(&["dared"], &corpus_words),
+            Some("Darcy.".to_string())
+        );
+        assert_eq!(mnemonic_hint(&["zebra"], &corpus_words), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Translating user facing messages.
+//!
+//! Catalogs use a subset of the gettext `.po` format: `msgid`/`msgstr` pairs of double quoted
+//! strings, optionally continued on following quoted lines, with `#` comments. A `msgid` with
+//! `{}` is a template: `Catalog::format` looks it up before interpolating its arguments, which
+//! are substituted in order for `{}` in the `msgstr` (or by position for `{0}`, `{1}`, ...).
+//! Messages formatted elsewhere, like library errors, are matched against the templates at
+//! display time instead, with each `{}` matching any text:
+//!
+//! ```text
+//! msgid "Unknown style '{}'. Expected plain or compactCamel."
+//! msgstr "Unbekannter Stil '{}'. Erwartet: plain oder compactCamel."
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The name catalogs are installed under, as `<dir>/<language>/LC_MESSAGES/markovpass.po`.
+pub const DOMAIN: &str = "markovpass";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl std::error::Error for CatalogError {}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid catalog on line {}: {}.",
+            self.line, self.message
+        )
+    }
+}
+
+/// Translations for one language.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    /// Translations by `msgid`, including templates.
+    exact: HashMap<String, String>,
+    /// `msgid`s containing `{}`, split at each `{}`, with their translations.
+    templates: Vec<(Vec<String>, String)>,
+}
+
+impl Catalog {
+    /// Parse a `.po` catalog. Untranslated entries (with an empty `msgstr`) are skipped.
+    pub fn parse(text: &str) -> Result<Self, CatalogError> {
+        let mut catalog = Self::default();
+        let mut msgid: Option<String> = None;
+        let mut msgstr: Option<String> = None;
+        for (i, line) in text.lines().enumerate() {
+            let error = |message| CatalogError {
+                line: i + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("msgid ") {
+                if let (Some(id), Some(translation)) = (msgid.take(), msgstr.take()) {
+                    catalog.insert(id, translation);
+                }
+                msgid = Some(unquote(rest).ok_or_else(|| error("expected a quoted string"))?);
+            } else if let Some(rest) = line.strip_prefix("msgstr ") {
+                if msgid.is_none() || msgstr.is_some() {
+                    return Err(error("msgstr without msgid"));
+                }
+                msgstr = Some(unquote(rest).ok_or_else(|| error("expected a quoted string"))?);
+            } else if line.starts_with('"') {
+                let continued = unquote(line).ok_or_else(|| error("expected a quoted string"))?;
+                match (&mut msgid, &mut msgstr) {
+                    (_, Some(text)) | (Some(text), None) => text.push_str(&continued),
+                    (None, None) => return Err(error("string outside an entry")),
+                }
+            } else {
+                return Err(error("expected msgid, msgstr or a quoted string"));
+            }
+        }
+        if let (Some(id), Some(translation)) = (msgid, msgstr) {
+            catalog.insert(id, translation);
+        }
+
+        Ok(catalog)
+    }
+
+    /// Load the catalog for the first of `languages` found in any of `dirs`, trying each
+    /// region specific language (like "de_AT") before its base language ("de").
+    pub fn find(
+        languages: &[String],
+        dirs: &[PathBuf],
+    ) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        for language in languages {
+            let base = language.split('_').next().unwrap_or(language);
+            for language in [language.as_str(), base] {
+                for dir in dirs {
+                    let path = catalog_path(dir, language);
+                    if path.exists() {
+                        let text = std::fs::read_to_string(&path)?;
+                        return Ok(Some(Self::parse(&text)?));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.templates.is_empty()
+    }
+
+    /// The translation of the template `template` with `args` substituted for its `{}`s.
+    pub fn format(&self, template: &str, args: &[&dyn fmt::Display]) -> String {
+        let template = self.exact.get(template).map_or(template, String::as_str);
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        substitute(template, &args)
+    }
+
+    /// The translation of the already formatted `message`, or `message` itself if there isn't
+    /// one.
+    pub fn translate<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        if let Some(translation) = self.exact.get(message) {
+            return Cow::Owned(translation.clone());
+        }
+        for (parts, translation) in &self.templates {
+            if let Some(args) = match_template(parts, message) {
+                return Cow::Owned(substitute(translation, &args));
+            }
+        }
+
+        Cow::Borrowed(message)
+    }
+
+    fn insert(&mut self, msgid: String, msgstr: String) {
+        // The empty msgid holds the catalog header.
+        if msgid.is_empty() || msgstr.is_empty() {
+            return;
+        }
+        if msgid.contains("{}") {
+            let parts = msgid.split("{}").map(str::to_string).collect();
+            self.templates.push((parts, msgstr.clone()));
+        }
+        self.exact.insert(msgid, msgstr);
+    }
+}
+
+/// The user's preferred languages from the environment, most preferred first, following gettext:
+/// `LANGUAGE` (a colon separated list), then the first of `LC_ALL`, `LC_MESSAGES` and `LANG`.
+///
+/// Encodings and modifiers are dropped ("de_DE.UTF-8@euro" becomes "de_DE"), and the "C" and
+/// "POSIX" locales mean untranslated messages.
+pub fn languages_from_env() -> Vec<String> {
+    let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let Some(locale) = var("LC_ALL")
+        .or_else(|| var("LC_MESSAGES"))
+        .or_else(|| var("LANG"))
+    else {
+        return vec![];
+    };
+    if locale == "C" || locale == "POSIX" || locale.starts_with("C.") {
+        return vec![];
+    }
+    let strip = |locale: &str| locale.split(['.', '@']).next().unwrap_or("").to_string();
+    let mut languages: Vec<String> = var("LANGUAGE")
+        .map(|list| list.split(':').map(strip).collect())
+        .unwrap_or_default();
+    languages.push(strip(&locale));
+    languages.retain(|language| !language.is_empty());
+
+    languages
+}
+
+fn catalog_path(dir: &Path, language: &str) -> PathBuf {
+    dir.join(language)
+        .join("LC_MESSAGES")
+        .join(format!("{}.po", DOMAIN))
+}
+
+fn unquote(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'n' => unquoted.push('\n'),
+                't' => unquoted.push('\t'),
+                c @ ('"' | '\\') => unquoted.push(c),
+                _ => return None,
+            },
+            '"' => return None,
+            c => unquoted.push(c),
+        }
+    }
+
+    Some(unquoted)
+}
+
+/// The texts matching each `{}` if `message` matches the template split into `parts`.
+///
+/// Each `{}` matches as little as possible, except the last, which runs to the final part.
+fn match_template<'a>(parts: &[String], message: &'a str) -> Option<Vec<&'a str>> {
+    let (first, rest) = parts.split_first()?;
+    let (last, middle) = rest.split_last()?;
+    let mut remaining = message.strip_prefix(first.as_str())?;
+    let mut args = vec![];
+    for part in middle {
+        let index = remaining.find(part.as_str())?;
+        args.push(&remaining[..index]);
+        remaining = &remaining[index + part.len()..];
+    }
+    args.push(remaining.strip_suffix(last.as_str())?);
+
+    Some(args)
+}
+
+fn substitute(translation: &str, args: &[&str]) -> String {
+    let mut result = String::new();
+    let mut next = 0;
+    let mut rest = translation;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let arg = after.find('}').and_then(|end| {
+            let index = match &after[..end] {
+                "" => {
+                    next += 1;
+                    next - 1
+                }
+                index => index.parse().ok()?,
+            };
+            Some((args.get(index)?, end))
+        });
+        match arg {
+            Some((arg, end)) => {
+                result.push_str(arg);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CATALOG: &str = r#"
+# German
+msgid ""
+msgstr "Content-Type: text/plain; charset=UTF-8\n"
+
+msgid "Print the entropy for each passphrase"
+msgstr "Die Entropie jeder Passphrase ausgeben"
+
+msgid "Skipping {}: {}"
+msgstr "{1}: {0} wird übersprungen"
+
+msgid "Unknown style '{}'. "
+"Expected plain or compactCamel."
+msgstr "Unbekannter Stil '{}'."
+
+msgid "Untranslated"
+msgstr ""
+"#;
+
+    #[test]
+    fn test_translate() {
+        let catalog = Catalog::parse(CATALOG).unwrap();
+        assert_eq!(
+            catalog.translate("Print the entropy for each passphrase"),
+            "Die Entropie jeder Passphrase ausgeben"
+        );
+        assert_eq!(
+            catalog.translate("Skipping a.txt: No such file"),
+            "No such file: a.txt wird übersprungen"
+        );
+        assert_eq!(
+            catalog.translate("Unknown style 'x'. Expected plain or compactCamel."),
+            "Unbekannter Stil 'x'."
+        );
+        assert_eq!(catalog.translate("Untranslated"), "Untranslated");
+        assert_eq!(catalog.translate("Something else"), "Something else");
+    }
+
+    #[test]
+    fn test_format() {
+        let catalog = Catalog::parse(CATALOG).unwrap();
+        // Arguments can't be mistaken for the template's text.
+        assert_eq!(
+            catalog.format("Skipping {}: {}", &[&"a: b.txt", &"No such file"]),
+            "No such file: a: b.txt wird übersprungen"
+        );
+        assert_eq!(
+            catalog.format("Downloading {}...", &[&3]),
+            "Downloading 3..."
+        );
+        assert_eq!(
+            catalog.format("Print the entropy for each passphrase", &[]),
+            "Die Entropie jeder Passphrase ausgeben"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Catalog::parse("msgstr \"x\"").unwrap_err(),
+            CatalogError {
+                line: 1,
+                message: "msgstr without msgid"
+            }
+        );
+        assert!(Catalog::parse("msgid \"unterminated").is_err());
+        assert!(Catalog::parse("msgid \"a\"\nbogus").is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(substitute("{} and {}", &["a", "b"]), "a and b");
+        assert_eq!(substitute("{1} then {0}", &["a", "b"]), "b then a");
+        assert_eq!(substitute("{2} {x}", &["a"]), "{2} {x}");
+    }
+}
@@ -2,47 +2,1195 @@
 #[cfg(feature = "benchmarks")]
 extern crate test;
 
-mod corpus;
+pub mod archive;
+pub mod attacker;
+pub mod audit;
+pub mod bloom;
+pub mod compression;
+pub mod corpus;
+pub mod download;
+pub mod encoding;
+pub mod ensemble;
+pub mod entropy;
+mod generator;
+pub mod hint;
+pub mod i18n;
 mod markovchain;
+pub mod model;
+pub mod output;
+pub mod phonetic;
+pub mod policy;
+pub mod scoring;
+pub mod script;
+pub mod selftest;
+pub mod sha256;
+pub mod transform;
+pub mod wordlist;
 
+pub use corpus::{ApostrophePolicy, DigitPolicy};
+pub use entropy::EntropyMeasure;
+pub use generator::{GenerationStats, Rejection, Rejections, Style};
+pub use markovchain::{ChainPassphrase, Normalization, PassphraseMarkovChain, SingleStart};
+
+use std::collections::HashMap;
 use std::fs::File;
-use std::io;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::PathBuf;
 
+/// The shortest ngrams a chain can be trained on. A single character can't show where words
+/// start.
+pub const MIN_NGRAM_LENGTH: usize = 2;
+
+/// The longest ngrams which give useful chains from typical corpora. Longer ngrams take far more
+/// memory and mostly reproduce the corpus, with under a bit of entropy per character.
+pub const MAX_NGRAM_LENGTH: usize = 7;
+
+/// Roughly how many bytes of memory each node takes while training.
+const BYTES_PER_NODE: u64 = 1000;
+
+/// The corpus built into markovpass with the `embedded-corpus` feature, as (file name, text)
+/// pairs. It's empty without the feature.
+#[cfg(feature = "embedded-corpus")]
+pub const EMBEDDED_CORPUS: &[(&str, &str)] = &[
+    (
+        "call-of-cthulu.txt",
+        include_str!("../pkg/call-of-cthulu.txt"),
+    ),
+    (
+        "my-man-jeeves.txt",
+        include_str!("../pkg/my-man-jeeves.txt"),
+    ),
+    (
+        "pride-and-prejudice.txt",
+        include_str!("../pkg/pride-and-prejudice.txt"),
+    ),
+];
+#[cfg(not(feature = "embedded-corpus"))]
+pub const EMBEDDED_CORPUS: &[(&str, &str)] = &[];
+
 #[derive(Debug, Clone)]
 pub struct GenPassphraseOptions {
     pub files: Vec<PathBuf>,
     pub number: usize,
     pub min_entropy: f64,
+    /// Reject passphrases with more entropy than this.
+    pub max_entropy: Option<f64>,
+    /// The length of the ngrams the chain is trained on, at least `MIN_NGRAM_LENGTH`.
     pub ngram_length: usize,
+    /// Drop corpus words shorter than this when training.
     pub min_word_length: usize,
+    /// Train on ngrams starting this many characters apart rather than on every ngram, from 1
+    /// to `ngram_length`. Each transition then adds up to this many characters to a passphrase.
+    pub ngram_step: usize,
+    /// Start an ngram at every word boundary even between steps, so longer steps still learn
+    /// how words start.
+    pub reset_at_words: bool,
+    /// How apostrophes in corpus words are handled.
+    pub apostrophes: ApostrophePolicy,
+    /// Spell out standalone numbers in the corpus ("42" becomes "forty two") rather than dropping
+    /// them.
+    pub spell_numbers: bool,
+    /// Where digits may appear in corpus words, for technical corpora with words like "ipv6".
+    pub digits: DigitPolicy,
+    /// Only train on corpus words written entirely in this script.
+    pub script: Option<script::Script>,
+    /// Keep the case of corpus words instead of lowercasing them.
+    ///
+    /// Case variants of a word become distinct ngrams, so the chain (and its entropy) counts the
+    /// choice of case, but each variant is trained on less of the corpus.
+    pub preserve_case: bool,
+    /// Drop corpus lines which repeat an earlier one, in any file, before training.
+    pub dedupe_lines: bool,
+    /// Drop corpus sentences which repeat an earlier one, in any file, before training.
+    pub dedupe_sentences: bool,
+    /// Train on `EMBEDDED_CORPUS` instead of `files`.
+    pub embedded_corpus: bool,
+    /// A saved model to generate from instead of training on `files`.
+    pub model: Option<PathBuf>,
+    /// Prune chain nodes with less transition entropy (in bits) than this.
+    pub min_node_entropy: f64,
+    /// Choose the starting ngram of each passphrase uniformly rather than by corpus frequency.
+    pub uniform_start: bool,
+    /// Allow passphrases to start at any ngram rather than only at the start of a word.
+    pub start_anywhere: bool,
+    /// What to do when every corpus word starts with the same ngram, which leaves no entropy in
+    /// the start of a passphrase.
+    pub single_start: SingleStart,
+    /// The string placed between words in generated passphrases.
+    pub separator: String,
+    /// How words are put together. Styles other than `Style::Plain` ignore `separator`.
+    pub style: Style,
+    /// Reject passphrases with words containing longer runs of consonants than this.
+    ///
+    /// Like every rejection based option, this makes the reported entropy an overestimate.
+    pub max_consonants: Option<usize>,
+    /// Reject passphrases with words shorter than this, independently of `min_word_length`, so
+    /// short corpus words can inform transitions without appearing in passphrases.
+    pub min_output_word_length: Option<usize>,
+    /// Reject passphrases containing any word in this filter, e.g. from `corpus_word_filter` for
+    /// passphrases made only of invented words. False positives only cause extra rejections.
+    pub reject_words: Option<std::sync::Arc<bloom::BloomFilter>>,
+    /// Reject passphrases containing any of these substrings (ignoring case and separators),
+    /// e.g. ones common in leaked password lists.
+    pub reject_substrings: Option<std::sync::Arc<attacker::CommonSubstrings>>,
+    /// Reject passphrases with a word any of these models finds unnatural, e.g. from
+    /// `corpus_ensemble`. Experimental.
+    pub naturalness: Option<std::sync::Arc<ensemble::Ensemble>>,
+    /// Make passphrases meet `min_entropy` even for an attacker who ignores case and (when words
+    /// are joined without a separator) word boundaries, as reported by
+    /// `Passphrase::normalized_entropy`.
+    pub enforce_normalized_entropy: bool,
+    /// Give up on a passphrase after rejecting this many candidates in a row.
+    pub max_retries: usize,
+    /// Reject passphrases mixing scripts or containing easily confused characters.
+    pub no_homoglyphs: bool,
+    /// The encoding of the corpus files, or `None` to read each as UTF-8 if it's valid UTF-8 and
+    /// Windows-1252 otherwise.
+    pub encoding: Option<encoding::Encoding>,
+    /// Skip corpus files which can't be read or decoded instead of failing, as long as some are
+    /// left.
+    pub skip_bad_files: bool,
+    /// Skip corpus files which no longer exist, as long as some are left, for files listed from
+    /// a data directory which may change before they're read.
+    pub skip_missing_files: bool,
+    /// How many threads read and count corpus files at once, or `None` for one per available
+    /// core. `Some(1)` reads the files one after another.
+    pub training_threads: Option<usize>,
+    /// Expected SHA-256 hex digests of corpus files and the model. Files are hashed as they're
+    /// read for use and fail with `ChecksumMismatch` if they don't match, so they can't change
+    /// between being verified and being used.
+    pub expected_sha256: HashMap<PathBuf, String>,
+    /// Characters which must not appear in passphrases. Corpus words containing them are dropped
+    /// when training, and passphrases containing them are rejected.
+    pub exclude_chars: String,
+    /// Seed the random number generator to get reproducible output.
+    ///
+    /// Seeded passphrases are only as secret as the seed, so this is for testing only.
+    pub seed: Option<u64>,
+    /// Generate each passphrase from its own seeded stream, giving each an `audit::AuditRecord`.
+    pub audit: bool,
+    /// Reject passphrases (as output, with separators) which break this policy.
+    pub policy: Option<policy::Policy>,
+    /// Post-processing for each passphrase, applied before the policy is checked.
+    pub transform: Option<std::sync::Arc<dyn transform::OutputTransform>>,
+    /// Generate `best_of` passphrases and keep the one `scorer` rates highest. Picking from
+    /// several costs log2(`best_of`) bits of entropy, which is made up with longer passphrases.
+    pub scorer: Option<std::sync::Arc<dyn scoring::WordScorer>>,
+    /// How many passphrases to pick each from. Ignored without a `scorer`.
+    pub best_of: usize,
+}
+
+impl Default for GenPassphraseOptions {
+    fn default() -> Self {
+        Self {
+            files: vec![],
+            number: 1,
+            min_entropy: 60.0,
+            max_entropy: None,
+            ngram_length: 3,
+            min_word_length: 5,
+            ngram_step: 1,
+            reset_at_words: false,
+            apostrophes: ApostrophePolicy::Keep,
+            spell_numbers: false,
+            digits: DigitPolicy::Drop,
+            script: None,
+            preserve_case: false,
+            dedupe_lines: false,
+            dedupe_sentences: false,
+            encoding: None,
+            skip_bad_files: false,
+            skip_missing_files: false,
+            training_threads: None,
+            expected_sha256: HashMap::new(),
+            embedded_corpus: false,
+            model: None,
+            min_node_entropy: 0.0,
+            uniform_start: false,
+            start_anywhere: false,
+            single_start: SingleStart::Fail,
+            separator: " ".to_string(),
+            style: Style::Plain,
+            max_consonants: None,
+            min_output_word_length: None,
+            reject_words: None,
+            reject_substrings: None,
+            naturalness: None,
+            enforce_normalized_entropy: false,
+            max_retries: 1000,
+            no_homoglyphs: false,
+            exclude_chars: String::new(),
+            seed: None,
+            audit: false,
+            policy: None,
+            transform: None,
+            scorer: None,
+            best_of: 1,
+        }
+    }
+}
+
+/// Overwrite the contents of `text` before freeing it, for strings which held (part of) a
+/// passphrase.
+pub fn wipe(mut text: String) {
+    // Safety: zero bytes are valid UTF-8.
+    for byte in unsafe { text.as_bytes_mut() } {
+        // Volatile writes so the compiler can't elide stores to memory that's about to be freed.
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
 }
 
 pub fn gen_passphrases(
     options: &GenPassphraseOptions,
 ) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
-    let reader = get_input_reader(&options.files)?;
-    let corpus = corpus::Corpus::new(reader, options.ngram_length, options.min_word_length)?;
-    let chain = markovchain::PassphraseMarkovChain::new(corpus.ngrams())?;
+    let chain = build_chain(options)?;
+
+    gen_passphrases_from_chain(&chain, options)
+}
+
+/// Build the chain described by `options`, from the saved model if there is one and otherwise by
+/// training on the corpus.
+pub fn build_chain(
+    options: &GenPassphraseOptions,
+) -> Result<PassphraseMarkovChain, Box<dyn std::error::Error>> {
+    let model = match &options.model {
+        Some(path) => load_model(path, options)?,
+        None => train(options)?,
+    };
+
+    chain_from_model(model, options)
+}
+
+/// Load the model at `path`, checking it against its digest in `expected_sha256` if it has one.
+pub fn load_model(
+    path: &std::path::Path,
+    options: &GenPassphraseOptions,
+) -> Result<model::Model, Box<dyn std::error::Error>> {
+    let file = File::open(path).map_err(model::ModelError::from)?;
+    let mut reader = sha256::HashingReader::new(file);
+    let model = model::Model::read_compressed(&mut reader);
+    // Hash the whole file even if parsing stopped early, and only return the model it matches.
+    io::copy(&mut reader, &mut io::sink()).map_err(model::ModelError::from)?;
+    check_sha256(path, &reader.hex_digest(), options)?;
+
+    Ok(model?)
+}
+
+/// A file whose contents didn't match its expected SHA-256 digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub expected: String,
+    pub found: String,
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Checksum mismatch: expected {}, found {}.",
+            self.expected, self.found
+        )
+    }
+}
+
+/// Check the `digest` of the contents of `path` as read against `expected_sha256`.
+fn check_sha256(
+    path: &std::path::Path,
+    digest: &str,
+    options: &GenPassphraseOptions,
+) -> io::Result<()> {
+    match options.expected_sha256.get(path) {
+        Some(expected) if !expected.eq_ignore_ascii_case(digest) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            ChecksumMismatch {
+                expected: expected.clone(),
+                found: digest.to_string(),
+            },
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Build a chain from a trained or loaded model using the chain options from `options`.
+pub fn chain_from_model(
+    model: model::Model,
+    options: &GenPassphraseOptions,
+) -> Result<PassphraseMarkovChain, Box<dyn std::error::Error>> {
+    Ok(model.into_chain(&markovchain::ChainOptions {
+        min_node_entropy: options.min_node_entropy,
+        uniform_start: options.uniform_start,
+        start_anywhere: options.start_anywhere,
+        single_start: options.single_start,
+        ..Default::default()
+    })?)
+}
+
+/// Generate passphrases from a chain built with `build_chain`.
+///
+/// This allows generating several batches with different generation options without rebuilding
+/// the chain each time.
+pub fn gen_passphrases_from_chain(
+    chain: &PassphraseMarkovChain,
+    options: &GenPassphraseOptions,
+) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    Ok(gen_passphrases_from_chain_with_stats(chain, options)?.0)
+}
+
+/// Passphrases with their entropies, and the work generating them took.
+type StatedPassphrases = (Vec<(String, f64)>, GenerationStats);
+
+/// Generate passphrases like `gen_passphrases`, also reporting the work generating them took.
+pub fn gen_passphrases_with_stats(
+    options: &GenPassphraseOptions,
+) -> Result<StatedPassphrases, Box<dyn std::error::Error>> {
+    let chain = build_chain(options)?;
+
+    gen_passphrases_from_chain_with_stats(&chain, options)
+}
+
+/// Generate passphrases like `gen_passphrases_from_chain`, also reporting the work generating
+/// them took.
+pub fn gen_passphrases_from_chain_with_stats(
+    chain: &PassphraseMarkovChain,
+    options: &GenPassphraseOptions,
+) -> Result<StatedPassphrases, Box<dyn std::error::Error>> {
+    let mut passphrases = iter_passphrases_from_chain(chain, options)?;
+    let results = passphrases
+        .by_ref()
+        .map(|result| result.map(|passphrase| (passphrase.text, passphrase.entropy)))
+        .collect::<Result<_, _>>()?;
+
+    Ok((results, passphrases.stats().clone()))
+}
+
+/// Train on `corpus` and generate passphrases from it in one call, for small tools with the
+/// corpus in memory (e.g. from `include_str!`). The `files` and `model` options are ignored.
+pub fn generate_from_str(
+    corpus: &str,
+    options: &GenPassphraseOptions,
+) -> Result<Vec<Passphrase>, Box<dyn std::error::Error>> {
+    check_ngram_options(options)?;
+    let model = train_from_reader("<str>".to_string(), corpus.as_bytes(), options)?;
+    let chain = chain_from_model(model, options)?;
+    let passphrases = iter_passphrases_from_chain(&chain, options)?.collect();
+
+    passphrases
+}
+
+/// Lazily generate passphrases from a chain built with `build_chain`, so they can be used as soon
+/// as each is ready.
+pub fn iter_passphrases_from_chain<'a>(
+    chain: &'a PassphraseMarkovChain,
+    options: &'a GenPassphraseOptions,
+) -> Result<Passphrases<'a>, Box<dyn std::error::Error>> {
+    Ok(Passphrases {
+        generator: generator::Generator::new(chain, options)?,
+        remaining: options.number,
+    })
+}
+
+/// A generated passphrase.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passphrase {
+    /// The passphrase as output, with separators and styling applied.
+    pub text: String,
+    /// The words of the passphrase in order, styled as they appear in `text` but without
+    /// separators or any output transform.
+    pub words: Vec<String>,
+    /// The Shannon entropy in bits of the choices made generating the passphrase, adjusted for
+    /// any output transform.
+    pub entropy: f64,
+    /// A conservative estimate of `entropy` for an attacker who ignores case, and word boundaries
+    /// if the output doesn't show them.
+    pub normalized_entropy: f64,
+    /// The min-entropy in bits of the choices made, adjusted like `entropy`. It only counts the
+    /// most likely option of each choice, so it's never more than `entropy`.
+    pub min_entropy: f64,
+    /// How the passphrase was generated, in audit mode.
+    pub audit: Option<audit::AuditRecord>,
+}
+
+impl Passphrase {
+    /// The passphrase's entropy in bits by `measure`.
+    pub fn entropy_by(&self, measure: EntropyMeasure) -> f64 {
+        match measure {
+            EntropyMeasure::Shannon => self.entropy,
+            EntropyMeasure::Min => self.min_entropy,
+        }
+    }
+}
+
+/// Regenerate the passphrase audit mode generated from `seed`, to attest how it was made.
+///
+/// The chain and generation options (other than `number` and `seed`) must match the original
+/// run's for the same passphrase to be generated.
+pub fn replay_passphrase(
+    chain: &PassphraseMarkovChain,
+    options: &GenPassphraseOptions,
+    seed: [u8; 32],
+) -> Result<Passphrase, Box<dyn std::error::Error>> {
+    Ok(generator::Generator::new(chain, options)?.replay(seed)?)
+}
+
+/// An iterator over generated passphrases.
+pub struct Passphrases<'a> {
+    generator: generator::Generator<'a>,
+    remaining: usize,
+}
+
+impl Passphrases<'_> {
+    /// Every candidate rejected so far.
+    pub fn rejections(&self) -> &Rejections {
+        self.generator.rejections()
+    }
+
+    /// The work generating the passphrases so far took.
+    pub fn stats(&self) -> &GenerationStats {
+        self.generator.stats()
+    }
+}
+
+impl Iterator for Passphrases<'_> {
+    type Item = Result<Passphrase, Box<dyn std::error::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        Some(self.generator.passphrase().map_err(|error| error.into()))
+    }
+}
+
+/// Train a model on the corpus `files` using the corpus options from `options`.
+///
+/// Files are read and cleaned in parallel (on up to `training_threads` threads) and their
+/// transition counts merged, so each file's text wraps around to its own start rather than
+/// running on into the next file.
+pub fn train(options: &GenPassphraseOptions) -> Result<model::Model, Box<dyn std::error::Error>> {
+    Ok(train_with_report(options)?.0)
+}
+
+/// A corpus file which couldn't be used.
+#[derive(Debug)]
+pub struct BadFile {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl std::error::Error for BadFile {}
+
+impl std::fmt::Display for BadFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+/// A corpus file skipped for having the same contents as an earlier one, since duplicated text
+/// inflates the transition counts without adding any diversity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    /// The earlier file with the same contents.
+    pub original: PathBuf,
+}
+
+/// Details of a training run beyond the model itself.
+#[derive(Debug, Default)]
+pub struct TrainReport {
+    /// Files skipped because of `skip_bad_files` or `skip_missing_files`.
+    pub bad_files: Vec<BadFile>,
+    /// Files skipped for duplicating an earlier file.
+    pub duplicate_files: Vec<DuplicateFile>,
+}
+
+/// Train a model like `train`, also reporting on the training.
+pub fn train_with_report(
+    options: &GenPassphraseOptions,
+) -> Result<(model::Model, TrainReport), Box<dyn std::error::Error>> {
+    check_ngram_options(options)?;
+    let results: Vec<(PathBuf, FileCounts)> = if options.embedded_corpus {
+        count_embedded(options)?
+    } else if options.files.is_empty() {
+        let model = train_from_reader("<stdin>".to_string(), io::stdin(), options)?;
+        return Ok((model, TrainReport::default()));
+    } else {
+        let counts = count_files(&options.files, options);
+        options.files.iter().cloned().zip(counts).collect()
+    };
+    let mut report = TrainReport::default();
+    let mut corpus_files = Vec::with_capacity(results.len());
+    let mut counts = markovchain::TransitionCounts::default();
+    let mut originals: HashMap<String, PathBuf> = HashMap::new();
+    for (path, result) in results {
+        match result {
+            Ok((corpus_file, _)) if originals.contains_key(&corpus_file.sha256) => {
+                let original = originals[&corpus_file.sha256].clone();
+                report
+                    .duplicate_files
+                    .push(DuplicateFile { path, original });
+            }
+            Ok((corpus_file, file_counts)) => {
+                originals.insert(corpus_file.sha256.clone(), path);
+                corpus_files.push(corpus_file);
+                counts.merge(&file_counts);
+            }
+            Err(error) => {
+                let bad_file = BadFile { path, error };
+                if !can_skip(&bad_file.error, options) {
+                    return Err(Box::new(bad_file));
+                }
+                report.bad_files.push(bad_file);
+            }
+        }
+    }
+    if corpus_files.is_empty() {
+        return Err("None of the corpus files could be used.".into());
+    }
+    if counts.is_empty() {
+        return Err(Box::new(markovchain::MarkovChainError::NoNgrams));
+    }
+    let model = model::Model::new(
+        options.ngram_length,
+        options.min_word_length,
+        corpus_files,
+        counts,
+    )
+    .with_stride(stride(options))
+    .with_corpus_options(corpus_options(options));
+
+    Ok((model, report))
+}
+
+/// Whether training can go on without a corpus file which failed with `error`. Files which fail
+/// verification never can.
+fn can_skip(error: &io::Error, options: &GenPassphraseOptions) -> bool {
+    let mismatch = error
+        .get_ref()
+        .is_some_and(|error| error.is::<ChecksumMismatch>());
+    !mismatch
+        && (options.skip_bad_files
+            || (options.skip_missing_files && error.kind() == io::ErrorKind::NotFound))
+}
+
+/// An upper bound on the memory in bytes training on `corpus_bytes` of text with ngrams of
+/// `ngram_length` characters takes: there's a node per distinct ngram, and at most one per
+/// character of the corpus or possible ngram of lowercase letters and word boundaries.
+pub fn estimate_training_memory(corpus_bytes: u64, ngram_length: usize) -> u64 {
+    let possible_ngrams = 27u64.saturating_pow(ngram_length.try_into().unwrap_or(u32::MAX));
+    corpus_bytes
+        .min(possible_ngrams)
+        .saturating_mul(BYTES_PER_NODE)
+}
+
+fn check_ngram_options(options: &GenPassphraseOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if options.ngram_length < MIN_NGRAM_LENGTH {
+        return Err(format!(
+            "Ngram length {} is too short: ngrams need at least {} characters.",
+            options.ngram_length, MIN_NGRAM_LENGTH
+        )
+        .into());
+    }
+    if !(1..=options.ngram_length).contains(&options.ngram_step) {
+        return Err(format!(
+            "Ngram step {} must be from 1 to the ngram length ({}), or ngrams would skip \
+             characters.",
+            options.ngram_step, options.ngram_length
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn stride(options: &GenPassphraseOptions) -> corpus::Stride {
+    corpus::Stride {
+        step: options.ngram_step,
+        reset_at_words: options.reset_at_words,
+    }
+}
+
+/// How much one corpus file contributes to a model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileContribution {
+    pub file: model::CorpusFile,
+    /// The number of distinct ngrams in the file.
+    pub ngrams: usize,
+    /// The number of those ngrams which aren't in any of the other files.
+    pub unique_ngrams: usize,
+    /// The total transition entropy of a model trained on just this file.
+    pub entropy: f64,
+    /// How much the total transition entropy drops without this file.
+    pub marginal_entropy: f64,
+}
+
+/// Measure what each of the corpus `files` adds to the model trained on all of them.
+///
+/// Computing the marginal entropy means merging all the other files once per file, so this takes
+/// time quadratic in the number of files. Files duplicating an earlier one are left out, as they
+/// are from training.
+pub fn file_contributions(
+    options: &GenPassphraseOptions,
+) -> Result<Vec<FileContribution>, Box<dyn std::error::Error>> {
+    check_ngram_options(options)?;
+    let mut files: Vec<(model::CorpusFile, markovchain::TransitionCounts)> = vec![];
+    for (path, result) in options
+        .files
+        .iter()
+        .zip(count_files(&options.files, options))
+    {
+        match result {
+            Ok((file, _)) if files.iter().any(|(seen, _)| seen.sha256 == file.sha256) => (),
+            Ok(file) => files.push(file),
+            Err(error) if can_skip(&error, options) => drop(error),
+            Err(error) => {
+                return Err(Box::new(BadFile {
+                    path: path.clone(),
+                    error,
+                }))
+            }
+        }
+    }
+    let mut file_counts: HashMap<&str, usize> = HashMap::new();
+    let mut total = markovchain::TransitionCounts::default();
+    for (_, counts) in &files {
+        for ngram in counts.ngrams() {
+            *file_counts.entry(ngram).or_insert(0) += 1;
+        }
+        total.merge(counts);
+    }
+    let total_entropy = total.total_entropy();
 
-    let passphrases = (0..options.number)
-        .map(|_| chain.passphrase(options.min_entropy))
+    let contributions = files
+        .iter()
+        .enumerate()
+        .map(|(i, (file, counts))| {
+            let mut others = markovchain::TransitionCounts::default();
+            for (_, other) in files.iter().enumerate().filter(|&(j, _)| j != i) {
+                others.merge(&other.1);
+            }
+            FileContribution {
+                file: file.clone(),
+                ngrams: counts.ngrams().len(),
+                unique_ngrams: counts
+                    .ngrams()
+                    .iter()
+                    .filter(|ngram| file_counts[ngram.as_str()] == 1)
+                    .count(),
+                entropy: counts.total_entropy(),
+                marginal_entropy: total_entropy - others.total_entropy(),
+            }
+        })
         .collect();
 
-    Ok(passphrases)
+    Ok(contributions)
+}
+
+type FileCounts = io::Result<(model::CorpusFile, markovchain::TransitionCounts)>;
+
+/// Read, clean and count each of `files` using `training_threads` threads (by default one per
+/// available core), returning the results in the same order as `files`.
+///
+/// Deduplicating across files depends on the order they're read in, so then they're read one
+/// at a time.
+fn count_files(files: &[PathBuf], options: &GenPassphraseOptions) -> Vec<FileCounts> {
+    let cleaning = cleaning_options(options);
+    if cleaning.dedupe_lines || cleaning.dedupe_sentences {
+        let mut deduplicator = corpus::Deduplicator::new(&cleaning);
+        return files
+            .iter()
+            .map(|path| count_file(path, options, &cleaning, &mut deduplicator))
+            .collect();
+    }
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let threads = options
+        .training_threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, files.len().max(1));
+    let mut results: Vec<(usize, FileCounts)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let Some(path) = files.get(i) else {
+                            break results;
+                        };
+                        let mut deduplicator = corpus::Deduplicator::default();
+                        let counts = count_file(path, options, &cleaning, &mut deduplicator);
+                        results.push((i, counts));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|&(i, _)| i);
+
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Clean and count each file of `EMBEDDED_CORPUS`, named by its file name.
+fn count_embedded(
+    options: &GenPassphraseOptions,
+) -> Result<Vec<(PathBuf, FileCounts)>, Box<dyn std::error::Error>> {
+    let texts = embedded_corpus()?;
+    let cleaning = cleaning_options(options);
+    let mut deduplicator = corpus::Deduplicator::new(&cleaning);
+
+    Ok(texts
+        .iter()
+        .map(|&(name, text)| {
+            let counts = count_stream(
+                name.to_string(),
+                text.as_bytes(),
+                options,
+                &cleaning,
+                &mut deduplicator,
+            );
+            (PathBuf::from(name), counts)
+        })
+        .collect())
+}
+
+/// `EMBEDDED_CORPUS`, unless markovpass was built without one.
+fn embedded_corpus() -> Result<&'static [(&'static str, &'static str)], Box<dyn std::error::Error>>
+{
+    match EMBEDDED_CORPUS.is_empty() {
+        true => Err("markovpass was built without an embedded corpus.".into()),
+        false => Ok(EMBEDDED_CORPUS),
+    }
+}
+
+fn count_file(
+    path: &std::path::Path,
+    options: &GenPassphraseOptions,
+    cleaning: &corpus::CleaningOptions,
+    deduplicator: &mut corpus::Deduplicator,
+) -> FileCounts {
+    let file = File::open(path)?;
+    let (corpus_file, counts) =
+        count_stream(file_name(path), file, options, cleaning, deduplicator)?;
+    check_sha256(path, &corpus_file.sha256, options)?;
+
+    Ok((corpus_file, counts))
+}
+
+/// Clean and count the corpus file `name` as it's read from `reader`, hashing it on the way.
+fn count_stream(
+    name: String,
+    reader: impl Read + Send,
+    options: &GenPassphraseOptions,
+    cleaning: &corpus::CleaningOptions,
+    deduplicator: &mut corpus::Deduplicator,
+) -> FileCounts {
+    let mut reader = sha256::HashingReader::new(reader);
+    let mut counter = markovchain::TransitionCounter::new(options.ngram_length, stride(options));
+    let mut push_word = |word: &str| counter.push_word(word);
+    read_words(&mut reader, options, cleaning, deduplicator, &mut push_word)?;
+    // Hash the whole file even if decompressing it stopped early.
+    io::copy(&mut reader, &mut io::sink())?;
+    let corpus_file = model::CorpusFile {
+        name,
+        sha256: reader.hex_digest(),
+    };
+
+    Ok((corpus_file, counter.finish()))
+}
+
+/// Feed the cleaned words of a corpus file read from `input` to `f`, returning how many words
+/// cleaning looked at. Compressed files are decompressed as they're read, and the text entries
+/// of archives (including compressed ones like `.tar.gz`) are cleaned separately.
+fn read_words(
+    input: &mut (dyn Read + Send),
+    options: &GenPassphraseOptions,
+    cleaning: &corpus::CleaningOptions,
+    deduplicator: &mut corpus::Deduplicator,
+    f: &mut dyn FnMut(&str),
+) -> io::Result<usize> {
+    let head = read_head(input)?;
+    let compression = compression::Compression::detect(&head);
+    let mut input = io::Cursor::new(head).chain(input);
+    match compression {
+        Some(compression) => compression::decompress_reader(compression, &mut input, |output| {
+            read_decompressed_words(output, options, cleaning, deduplicator, f)
+        }),
+        None => read_decompressed_words(&mut input, options, cleaning, deduplicator, f),
+    }
+}
+
+fn read_decompressed_words(
+    input: &mut dyn Read,
+    options: &GenPassphraseOptions,
+    cleaning: &corpus::CleaningOptions,
+    deduplicator: &mut corpus::Deduplicator,
+    f: &mut dyn FnMut(&str),
+) -> io::Result<usize> {
+    let head = read_head(input)?;
+    let Some(kind) = archive::ArchiveKind::detect(&head) else {
+        let input = io::Cursor::new(head).chain(input);
+        return corpus::CorpusReader::new(input, options.encoding, cleaning, deduplicator)
+            .for_each_word(f);
+    };
+    // Archives are read whole, since zip files are indexed from their end.
+    let mut bytes = head;
+    input.read_to_end(&mut bytes)?;
+    let mut input_words = 0;
+    for entry in archive::text_entries(kind, &bytes)? {
+        // Entries are decoded separately, since each may be in a different encoding.
+        input_words +=
+            corpus::CorpusReader::new(&entry[..], options.encoding, cleaning, deduplicator)
+                .for_each_word(&mut *f)?;
+    }
+
+    Ok(input_words)
+}
+
+/// Train a model on the single corpus file `name` read from `reader`.
+pub(crate) fn train_from_reader(
+    name: String,
+    reader: impl Read + Send,
+    options: &GenPassphraseOptions,
+) -> Result<model::Model, Box<dyn std::error::Error>> {
+    let cleaning = cleaning_options(options);
+    let mut deduplicator = corpus::Deduplicator::new(&cleaning);
+    let (corpus_file, counts) = count_stream(name, reader, options, &cleaning, &mut deduplicator)?;
+    if counts.is_empty() {
+        return Err(Box::new(markovchain::MarkovChainError::NoNgrams));
+    }
+
+    Ok(model::Model::new(
+        options.ngram_length,
+        options.min_word_length,
+        vec![corpus_file],
+        counts,
+    )
+    .with_stride(stride(options))
+    .with_corpus_options(corpus_options(options)))
+}
+
+/// The first bytes of `input`, enough to recognize compressed files and archives by.
+fn read_head(input: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut head = vec![];
+    input
+        .take(archive::MAGIC_LENGTH as u64)
+        .read_to_end(&mut head)?;
+
+    Ok(head)
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+        .into_owned()
+}
+
+/// The words of the corpus `files` (or stdin if there are none) after cleaning, exactly as the
+/// chain would be trained on them, separated by spaces.
+pub fn clean_corpus(options: &GenPassphraseOptions) -> Result<String, Box<dyn std::error::Error>> {
+    let corpus = read_corpus(options)?;
+
+    Ok(corpus.words().collect::<Vec<_>>().join(" "))
+}
+
+/// Feed the ngrams of the cleaned corpus `files` (or stdin if there are none) to `f` in order,
+/// as the chain would be trained on them, stopping at the first error. The text wraps around, so
+/// with an `ngram_step` of 1 there's one ngram per character.
+pub fn for_each_corpus_ngram(
+    options: &GenPassphraseOptions,
+    mut f: impl FnMut(&str) -> io::Result<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    check_ngram_options(options)?;
+    let corpus = read_corpus(options)?;
+    for ngram in corpus.ngrams_with_stride(stride(options)) {
+        f(ngram)?;
+    }
+
+    Ok(())
 }
 
-fn get_input_reader(files: &[PathBuf]) -> Result<Box<dyn io::Read>, Box<dyn std::error::Error>> {
-    match files {
-        [head, tail @ ..] => {
-            let mut reader: Box<dyn io::Read> = Box::new(File::open(head)?);
-            for f in tail {
-                reader = Box::new(reader.chain(File::open(f)?));
+/// The cleaned corpus `files` (or `EMBEDDED_CORPUS` with `embedded_corpus`, or stdin if there
+/// are no files), for applications which need its
+/// words or statistics without cleaning it again.
+///
+/// Like training, this skips bad files allowed by `skip_bad_files` or `skip_missing_files` and
+/// files duplicating an earlier one, but without reporting them.
+pub fn read_corpus(
+    options: &GenPassphraseOptions,
+) -> Result<corpus::Corpus, Box<dyn std::error::Error>> {
+    let cleaning = cleaning_options(options);
+    let mut deduplicator = corpus::Deduplicator::new(&cleaning);
+    let mut text = String::new();
+    let mut input_words = 0;
+    if options.embedded_corpus {
+        for (_, embedded) in embedded_corpus()? {
+            input_words += read_words(
+                &mut embedded.as_bytes(),
+                options,
+                &cleaning,
+                &mut deduplicator,
+                &mut |word| corpus::push_word(&mut text, word),
+            )?;
+        }
+    } else if options.files.is_empty() {
+        let mut stdin = io::stdin();
+        input_words = read_words(
+            &mut stdin,
+            options,
+            &cleaning,
+            &mut deduplicator,
+            &mut |word| corpus::push_word(&mut text, word),
+        )?;
+    }
+    let mut digests = std::collections::HashSet::new();
+    for path in &options.files {
+        let text_len = text.len();
+        let result = read_file_words(path, options, &cleaning, &mut deduplicator, &mut |word| {
+            corpus::push_word(&mut text, word)
+        });
+        match result {
+            Ok((words, digest)) => match digests.insert(digest) {
+                true => input_words += words,
+                false => text.truncate(text_len),
+            },
+            Err(error) => {
+                text.truncate(text_len);
+                let bad_file = BadFile {
+                    path: path.clone(),
+                    error,
+                };
+                if !can_skip(&bad_file.error, options) {
+                    return Err(Box::new(bad_file));
+                }
             }
-            Ok(Box::new(io::BufReader::new(reader)))
         }
-        [] => Ok(Box::new(io::stdin())),
+    }
+    if !options.files.is_empty() && digests.is_empty() {
+        return Err("None of the corpus files could be used.".into());
+    }
+
+    Ok(corpus::Corpus::from_cleaned(
+        text,
+        options.ngram_length,
+        input_words,
+    ))
+}
+
+/// Feed the cleaned words of the corpus file at `path` to `f`, returning how many words cleaning
+/// looked at and the file's digest, which must match any expected for it.
+fn read_file_words(
+    path: &std::path::Path,
+    options: &GenPassphraseOptions,
+    cleaning: &corpus::CleaningOptions,
+    deduplicator: &mut corpus::Deduplicator,
+    f: &mut dyn FnMut(&str),
+) -> io::Result<(usize, String)> {
+    let mut file = sha256::HashingReader::new(File::open(path)?);
+    let words = read_words(&mut file, options, cleaning, deduplicator, f)?;
+    io::copy(&mut file, &mut io::sink())?;
+    let digest = file.hex_digest();
+    check_sha256(path, &digest, options)?;
+
+    Ok((words, digest))
+}
+
+/// The distinct words of the cleaned corpus `files`, sorted.
+pub fn corpus_words(
+    options: &GenPassphraseOptions,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let corpus = read_corpus(options)?;
+    let words: std::collections::BTreeSet<&str> = corpus.words().collect();
+
+    Ok(words.into_iter().map(str::to_string).collect())
+}
+
+/// A bloom filter of the distinct words of the cleaned corpus `files`.
+pub fn corpus_word_filter(
+    options: &GenPassphraseOptions,
+    false_positive_rate: f64,
+) -> Result<bloom::BloomFilter, Box<dyn std::error::Error>> {
+    let corpus = read_corpus(options)?;
+
+    Ok(corpus.word_filter(false_positive_rate))
+}
+
+/// Character and syllable models of the cleaned corpus `files` for `naturalness`, accepting
+/// words up to the `percentile` of the corpus words' surprisal.
+pub fn corpus_ensemble(
+    options: &GenPassphraseOptions,
+    percentile: f64,
+) -> Result<ensemble::Ensemble, Box<dyn std::error::Error>> {
+    let corpus = read_corpus(options)?;
+    let words: Vec<&str> = corpus.words().collect();
+
+    Ok(ensemble::Ensemble::train(
+        &words,
+        options.ngram_length,
+        percentile,
+    ))
+}
+
+/// The number of corpus words `suggest_ngram_length` trains on.
+pub const SUGGESTION_SAMPLE_WORDS: usize = 200_000;
+
+/// The number of words `suggest_ngram_length` generates from each chain to judge.
+const SUGGESTION_TRIAL_WORDS: usize = 500;
+
+/// The ngram length of the models judging how natural trial words are. It's the same for every
+/// trial so the chains are compared against the same standard.
+const SUGGESTION_JUDGE_NGRAM_LENGTH: usize = 3;
+
+/// How a chain trained with one ngram length performed in `suggest_ngram_length`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NgramLengthTrial {
+    pub ngram_length: usize,
+    /// The number of nodes (distinct ngrams) in the chain.
+    pub nodes: usize,
+    /// The entropy in bits each character of a long passphrase adds.
+    pub entropy_rate: f64,
+    /// The typical length in characters of passphrases with the `min_entropy` option.
+    pub expected_length: f64,
+    /// The fraction of words generated from the chain which models of the corpus find natural.
+    pub naturalness: f64,
+}
+
+impl NgramLengthTrial {
+    /// The bits per character of words which read naturally, for ranking trials.
+    pub fn score(&self) -> f64 {
+        self.entropy_rate * self.naturalness
+    }
+}
+
+/// Train chains at each ngram length from `MIN_NGRAM_LENGTH` to `MAX_NGRAM_LENGTH` on the first
+/// `SUGGESTION_SAMPLE_WORDS` words of the cleaned corpus (read like `read_corpus` does, so
+/// from the embedded corpus with `embedded_corpus`), and measure the entropy per character and
+/// naturalness of each. The best ngram length has the highest `score`, and is
+/// returned alongside the trials.
+///
+/// Lengths giving no usable chain on the sample are left out. Trial words are generated from a
+/// fixed seed, so the suggestion is the same every time for the same corpus.
+pub fn suggest_ngram_length(
+    options: &GenPassphraseOptions,
+) -> Result<(usize, Vec<NgramLengthTrial>), Box<dyn std::error::Error>> {
+    use rand::SeedableRng;
+
+    let corpus = read_corpus(options)?;
+    let words: Vec<&str> = corpus.words().take(SUGGESTION_SAMPLE_WORDS).collect();
+    let judge = ensemble::Ensemble::train(&words, SUGGESTION_JUDGE_NGRAM_LENGTH, 95.0);
+    let mut trials = vec![];
+    for ngram_length in MIN_NGRAM_LENGTH..=MAX_NGRAM_LENGTH {
+        let stride = corpus::Stride {
+            step: options.ngram_step.min(ngram_length),
+            reset_at_words: options.reset_at_words,
+        };
+        let mut counter = markovchain::TransitionCounter::new(ngram_length, stride);
+        for word in &words {
+            counter.push_word(word);
+        }
+        let chain = match PassphraseMarkovChain::new(
+            counter.finish(),
+            &markovchain::ChainOptions {
+                min_node_entropy: options.min_node_entropy,
+                uniform_start: options.uniform_start,
+                start_anywhere: options.start_anywhere,
+                single_start: options.single_start,
+                stride,
+            },
+        ) {
+            Ok(chain) => chain,
+            Err(_) => continue,
+        };
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0);
+        let natural = (0..SUGGESTION_TRIAL_WORDS)
+            .filter(|_| judge.accepts(&chain.passphrase_with_rng(0.0, &mut rng).0))
+            .count();
+        trials.push(NgramLengthTrial {
+            ngram_length,
+            nodes: chain.node_count(),
+            entropy_rate: chain.entropy_rate(),
+            expected_length: chain.expected_length(options.min_entropy),
+            naturalness: natural as f64 / SUGGESTION_TRIAL_WORDS as f64,
+        });
+    }
+    let best = trials
+        .iter()
+        .max_by(|a, b| a.score().total_cmp(&b.score()))
+        .ok_or(markovchain::MarkovChainError::NoNgrams)?
+        .ngram_length;
+
+    Ok((best, trials))
+}
+
+/// The corpus options from `options` which a model records, normalized so options cleaning the
+/// same way compare equal.
+pub fn corpus_options(options: &GenPassphraseOptions) -> model::CorpusOptions {
+    let mut exclude_chars: Vec<char> = options.exclude_chars.to_lowercase().chars().collect();
+    exclude_chars.sort_unstable();
+    exclude_chars.dedup();
+
+    model::CorpusOptions {
+        apostrophes: options.apostrophes,
+        digits: options.digits,
+        spell_numbers: options.spell_numbers,
+        script: options.script,
+        preserve_case: options.preserve_case,
+        dedupe_lines: options.dedupe_lines,
+        dedupe_sentences: options.dedupe_sentences,
+        exclude_chars: exclude_chars.into_iter().collect(),
+        encoding: options.encoding,
+    }
+}
+
+/// `options` with the ngram length, stride and corpus options `metadata` records, so a corpus
+/// trained with them is read and cleaned just like the model's was and the two can be merged.
+/// Models which predate recording corpus options keep those from `options`.
+pub fn model_training_options(
+    options: GenPassphraseOptions,
+    metadata: &model::ModelMetadata,
+) -> GenPassphraseOptions {
+    let options = GenPassphraseOptions {
+        ngram_length: metadata.ngram_length,
+        min_word_length: metadata.min_word_length,
+        ngram_step: metadata.stride.step,
+        reset_at_words: metadata.stride.reset_at_words,
+        ..options
+    };
+    match &metadata.corpus_options {
+        Some(corpus_options) => GenPassphraseOptions {
+            apostrophes: corpus_options.apostrophes,
+            digits: corpus_options.digits,
+            spell_numbers: corpus_options.spell_numbers,
+            script: corpus_options.script,
+            preserve_case: corpus_options.preserve_case,
+            dedupe_lines: corpus_options.dedupe_lines,
+            dedupe_sentences: corpus_options.dedupe_sentences,
+            exclude_chars: corpus_options.exclude_chars.clone(),
+            encoding: corpus_options.encoding,
+            ..options
+        },
+        None => options,
+    }
+}
+
+fn cleaning_options(options: &GenPassphraseOptions) -> corpus::CleaningOptions {
+    corpus::CleaningOptions {
+        min_word_length: options.min_word_length,
+        exclude_chars: options.exclude_chars.to_lowercase().chars().collect(),
+        apostrophes: options.apostrophes,
+        spell_numbers: options.spell_numbers,
+        digits: options.digits,
+        script: options.script,
+        preserve_case: options.preserve_case,
+        dedupe_lines: options.dedupe_lines,
+        dedupe_sentences: options.dedupe_sentences,
     }
 }
 
@@ -58,6 +1206,831 @@ mod tests {
         assert_eq!(passphrases.len(), 5);
     }
 
+    #[test]
+    fn test_train_multiple_files() {
+        let (dir, files) = testdata_copies("multiple", 3);
+        let options = GenPassphraseOptions {
+            files,
+            ..get_test_options()
+        };
+        let model = train(&options);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let model = model.unwrap();
+        let mut expected = train(&get_test_options()).unwrap();
+        let single = expected.clone();
+        expected.merge(&single).unwrap();
+        expected.merge(&single).unwrap();
+        assert_eq!(model.node_count(), expected.node_count());
+        assert_eq!(model.metadata().corpus_files.len(), 3);
+        assert_eq!(
+            model.metadata().total_entropy,
+            expected.metadata().total_entropy
+        );
+    }
+
+    #[test]
+    fn test_train_threads() {
+        let (dir, files) = testdata_copies("threads", 4);
+        let options = |training_threads| GenPassphraseOptions {
+            files: files.clone(),
+            training_threads,
+            ..get_test_options()
+        };
+        let sequential = train(&options(Some(1))).unwrap();
+        let models: Vec<_> = [None, Some(0), Some(3), Some(16)]
+            .into_iter()
+            .map(|threads| train(&options(threads)))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        for model in models {
+            let model = model.unwrap();
+            assert_eq!(model.node_count(), sequential.node_count());
+            assert_eq!(
+                model.metadata().corpus_files,
+                sequential.metadata().corpus_files
+            );
+            assert_eq!(
+                model.metadata().total_entropy,
+                sequential.metadata().total_entropy
+            );
+        }
+    }
+
+    #[test]
+    fn test_train_embedded_corpus() {
+        let options = GenPassphraseOptions {
+            embedded_corpus: true,
+            ..get_test_options()
+        };
+        match EMBEDDED_CORPUS.len() {
+            0 => assert!(train(&options).is_err()),
+            files => assert_eq!(
+                train(&options).unwrap().metadata().corpus_files.len(),
+                files
+            ),
+        }
+    }
+
+    #[test]
+    fn test_train_compressed() {
+        let Ok(output) = std::process::Command::new("gzip")
+            .arg("-c")
+            .arg(get_testdata_pathbuf())
+            .output()
+        else {
+            return;
+        };
+        let path = std::env::temp_dir().join(format!("markovpass-{}.gz", std::process::id()));
+        std::fs::write(&path, output.stdout).unwrap();
+        let options = GenPassphraseOptions {
+            files: vec![path.clone()],
+            ..get_test_options()
+        };
+        let model = train(&options);
+        let ngrams = corpus_ngrams(&options);
+        std::fs::remove_file(&path).unwrap();
+        let expected = train(&get_test_options()).unwrap();
+        let model = model.unwrap();
+        assert_eq!(model.node_count(), expected.node_count());
+        assert_eq!(
+            model.metadata().total_entropy,
+            expected.metadata().total_entropy
+        );
+        assert_eq!(ngrams.unwrap(), corpus_ngrams(&get_test_options()).unwrap());
+    }
+
+    #[test]
+    fn test_train_archived() {
+        let text = std::fs::read(get_testdata_pathbuf()).unwrap();
+        let mut archive = [0; 512];
+        archive[..8].copy_from_slice(b"text.txt");
+        archive[124..135].copy_from_slice(format!("{:011o}", text.len()).as_bytes());
+        archive[156] = b'0';
+        archive[257..263].copy_from_slice(b"ustar\0");
+        let mut archive = archive.to_vec();
+        archive.extend(&text);
+        archive.resize(archive.len().div_ceil(512) * 512 + 1024, 0);
+        let path = std::env::temp_dir().join(format!("markovpass-{}.tar", std::process::id()));
+        std::fs::write(&path, archive).unwrap();
+        let options = GenPassphraseOptions {
+            files: vec![path.clone()],
+            ..get_test_options()
+        };
+        let model = train(&options);
+        std::fs::remove_file(&path).unwrap();
+        let expected = train(&get_test_options()).unwrap();
+        let model = model.unwrap();
+        assert_eq!(model.node_count(), expected.node_count());
+        assert_eq!(
+            model.metadata().total_entropy,
+            expected.metadata().total_entropy
+        );
+    }
+
+    #[test]
+    fn test_train_dedupe_lines() {
+        let options = GenPassphraseOptions {
+            dedupe_lines: true,
+            ..get_test_options()
+        };
+        let single = train(&options).unwrap();
+        let (dir, files) = testdata_copies("dedupe-lines", 3);
+        let options = GenPassphraseOptions { files, ..options };
+        // Every line of the second and third copies is a repeat.
+        let model = train(&options);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let model = model.unwrap();
+        assert_eq!(model.node_count(), single.node_count());
+        assert_eq!(
+            model.metadata().total_entropy,
+            single.metadata().total_entropy
+        );
+        assert_eq!(model.metadata().corpus_files.len(), 3);
+    }
+
+    #[test]
+    fn test_train_streaming() {
+        let options = GenPassphraseOptions {
+            dedupe_sentences: true,
+            ngram_step: 2,
+            reset_at_words: true,
+            ..get_test_options()
+        };
+        // Training on words as they're read counts the same ngrams as the whole cleaned corpus.
+        let streamed = train(&options).unwrap();
+        let ngrams = corpus_ngrams(&options).unwrap();
+        let file_counts =
+            markovchain::TransitionCounts::from_ngrams(ngrams.iter().map(String::as_str));
+        // Training merges each file's counts, which interns ngrams in its own order.
+        let mut counts = markovchain::TransitionCounts::default();
+        counts.merge(&file_counts);
+        let whole = model::Model::new(
+            options.ngram_length,
+            options.min_word_length,
+            streamed.metadata().corpus_files.clone(),
+            counts,
+        )
+        .with_stride(stride(&options))
+        .with_corpus_options(corpus_options(&options));
+        let chain_options = markovchain::ChainOptions::default();
+        assert_eq!(
+            streamed.into_chain(&chain_options).unwrap().fingerprint(),
+            whole.into_chain(&chain_options).unwrap().fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_ngram_length_bounds() {
+        for ngram_length in [0, 1] {
+            let options = GenPassphraseOptions {
+                ngram_length,
+                ..get_test_options()
+            };
+            assert!(train(&options).is_err());
+        }
+        let options = GenPassphraseOptions {
+            ngram_length: MIN_NGRAM_LENGTH,
+            number: 1,
+            ..get_test_options()
+        };
+        assert!(gen_passphrases(&options).is_ok());
+    }
+
+    #[test]
+    fn test_ngram_step() {
+        let options = GenPassphraseOptions {
+            ngram_length: 4,
+            ngram_step: 2,
+            reset_at_words: true,
+            number: 10,
+            ..get_test_options()
+        };
+        let model = train(&options).unwrap();
+        assert_eq!(model.metadata().stride, stride(&options));
+        let chain = chain_from_model(model, &options).unwrap();
+        assert_eq!(chain.stride(), stride(&options));
+        for (passphrase, entropy) in gen_passphrases_from_chain(&chain, &options).unwrap() {
+            assert!(entropy >= options.min_entropy);
+            assert!(passphrase.chars().all(|c| c == ' ' || c.is_alphabetic()));
+            assert!(!passphrase.contains("  "));
+        }
+        for ngram_step in [0, 5] {
+            let options = GenPassphraseOptions {
+                ngram_step,
+                ..options.clone()
+            };
+            assert!(train(&options).is_err());
+            assert!(corpus_ngrams(&options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_estimate_training_memory() {
+        let options = GenPassphraseOptions {
+            ngram_length: 8,
+            ..get_test_options()
+        };
+        let model = train(&options).unwrap();
+        let corpus_bytes = std::fs::metadata(get_testdata_pathbuf()).unwrap().len();
+        let estimate = estimate_training_memory(corpus_bytes, options.ngram_length);
+        assert!(estimate >= model.node_count() as u64 * BYTES_PER_NODE);
+        assert_eq!(estimate_training_memory(1 << 30, 2), 729 * BYTES_PER_NODE);
+        assert_eq!(estimate_training_memory(u64::MAX, 100), u64::MAX);
+    }
+
+    #[test]
+    fn test_train_skip_bad_files() {
+        let missing = PathBuf::from("/nonexistent/markovpass/corpus.txt");
+        let options = GenPassphraseOptions {
+            files: vec![missing.clone(), get_testdata_pathbuf()],
+            ..get_test_options()
+        };
+        let error = train(&options).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with(&missing.display().to_string()));
+
+        let options = GenPassphraseOptions {
+            skip_bad_files: true,
+            ..options
+        };
+        let (model, report) = train_with_report(&options).unwrap();
+        assert_eq!(model.metadata().corpus_files.len(), 1);
+        assert_eq!(report.bad_files.len(), 1);
+        assert_eq!(report.bad_files[0].path, missing);
+
+        let options = GenPassphraseOptions {
+            files: vec![missing],
+            ..options
+        };
+        assert!(train(&options).is_err());
+    }
+
+    #[test]
+    fn test_train_skip_duplicate_files() {
+        let dir = std::env::temp_dir().join(format!("markovpass-dedupe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files: Vec<_> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        std::fs::write(&files[0], "some branching text to train on").unwrap();
+        std::fs::write(&files[1], "other words entirely").unwrap();
+        std::fs::write(&files[2], "some branching text to train on").unwrap();
+        let options = GenPassphraseOptions {
+            files: files.clone(),
+            ..get_test_options()
+        };
+        let trained = train_with_report(&options);
+        let corpus = read_corpus(&options);
+        let unique = read_corpus(&GenPassphraseOptions {
+            files: files[..2].to_vec(),
+            ..get_test_options()
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (model, report) = trained.unwrap();
+        assert_eq!(model.metadata().corpus_files.len(), 2);
+        assert_eq!(
+            report.duplicate_files,
+            vec![DuplicateFile {
+                path: files[2].clone(),
+                original: files[0].clone(),
+            }]
+        );
+        assert_eq!(corpus.unwrap().text(), unique.unwrap().text());
+    }
+
+    #[test]
+    fn test_train_skip_missing_files() {
+        let missing = PathBuf::from("/nonexistent/markovpass/corpus.txt");
+        let options = GenPassphraseOptions {
+            files: vec![missing.clone(), get_testdata_pathbuf()],
+            skip_missing_files: true,
+            ..get_test_options()
+        };
+        let (model, report) = train_with_report(&options).unwrap();
+        assert_eq!(model.metadata().corpus_files.len(), 1);
+        assert_eq!(report.bad_files[0].path, missing);
+
+        // Files which exist but can't be read still fail.
+        let directory = get_testdata_pathbuf().parent().unwrap().to_path_buf();
+        let options = GenPassphraseOptions {
+            files: vec![directory, get_testdata_pathbuf()],
+            ..options
+        };
+        assert!(train(&options).is_err());
+
+        let options = GenPassphraseOptions {
+            files: vec![missing],
+            ..options
+        };
+        assert!(train(&options).is_err());
+    }
+
+    #[test]
+    fn test_file_contributions() {
+        let (dir, files) = testdata_copies("contributions", 2);
+        let options = GenPassphraseOptions {
+            files,
+            ..get_test_options()
+        };
+        let contributions = file_contributions(&options);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let contributions = contributions.unwrap();
+        assert_eq!(contributions.len(), 2);
+        let contribution = &contributions[0];
+        assert_eq!(
+            contribution.ngrams,
+            train(&get_test_options()).unwrap().node_count()
+        );
+        assert_eq!(contribution.unique_ngrams, 0);
+        // Doubling the same text doesn't change any transition probabilities.
+        assert!(contribution.marginal_entropy.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clean_corpus() {
+        let options = get_test_options();
+        let cleaned = clean_corpus(&options).unwrap();
+        assert!(!cleaned.is_empty());
+        assert!(cleaned
+            .split(' ')
+            .all(|word| word.chars().count() >= 5 && !word.contains(char::is_uppercase)));
+        let mut words: Vec<_> = cleaned.split(' ').map(str::to_string).collect();
+        words.sort();
+        words.dedup();
+        assert_eq!(words, corpus_words(&options).unwrap());
+    }
+
+    #[test]
+    fn test_read_corpus() {
+        let corpus = read_corpus(&get_test_options()).unwrap();
+        assert!(corpus.word_count() > corpus.distinct_word_count());
+        assert!(corpus.dropped_word_ratio() > 0.0 && corpus.dropped_word_ratio() < 1.0);
+        assert!(corpus.char_histogram()[&'e'] > corpus.char_histogram()[&'z']);
+    }
+
+    #[test]
+    fn test_corpus_encoding() {
+        let path = std::env::temp_dir().join(format!("markovpass-{}-1252.txt", std::process::id()));
+        std::fs::write(&path, b"\x93Caf\xe9s\x94 and r\xe9sum\xe9s").unwrap();
+        let options = GenPassphraseOptions {
+            files: vec![path.clone()],
+            ..get_test_options()
+        };
+        let detected = corpus_words(&options);
+        let utf8 = corpus_words(&GenPassphraseOptions {
+            encoding: Some(encoding::Encoding::Utf8),
+            ..options.clone()
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(detected.unwrap(), ["cafés", "résumés"]);
+        assert!(utf8.is_err());
+    }
+
+    #[test]
+    fn test_corpus_ngrams() {
+        let options = get_test_options();
+        let ngrams = corpus_ngrams(&options).unwrap();
+        assert!(ngrams.iter().all(|ngram| ngram.chars().count() == 3));
+        let cleaned = clean_corpus(&options).unwrap();
+        // Every character of the cleaned text, plus the boundary before the first word.
+        assert_eq!(ngrams.len(), cleaned.chars().count() + 1);
+        assert!(ngrams[0].starts_with(' '));
+    }
+
+    #[test]
+    fn test_gen_passphrases_from_model() {
+        let options = get_test_options();
+        let mut path = std::env::temp_dir();
+        path.push(format!("markovpass-test-{}.mpm", std::process::id()));
+        train(&options).unwrap().save(&path).unwrap();
+        let result = gen_passphrases(&GenPassphraseOptions {
+            files: vec![],
+            model: Some(path.clone()),
+            ..options
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_gen_passphrases_from_chain() {
+        let options = get_test_options();
+        let chain = build_chain(&options).unwrap();
+        for min_entropy in [40.0, 80.0] {
+            let options = GenPassphraseOptions {
+                min_entropy,
+                ..options.clone()
+            };
+            let passphrases = gen_passphrases_from_chain(&chain, &options).unwrap();
+            assert_eq!(passphrases.len(), 5);
+            assert!(passphrases.iter().all(|&(_, e)| e >= min_entropy));
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_entropy_range() {
+        let options = GenPassphraseOptions {
+            min_entropy: 40.0,
+            max_entropy: Some(50.0),
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        assert!(passphrases.iter().all(|&(_, e)| (40.0..=50.0).contains(&e)));
+
+        let options = GenPassphraseOptions {
+            max_entropy: Some(50.0),
+            ..get_test_options()
+        };
+        assert!(gen_passphrases(&options).is_err());
+    }
+
+    #[test]
+    fn test_generate_from_str() {
+        let corpus = std::fs::read_to_string(get_testdata_pathbuf()).unwrap();
+        let options = GenPassphraseOptions {
+            files: vec![PathBuf::from("missing.txt")],
+            ..get_test_options()
+        };
+        let passphrases = generate_from_str(&corpus, &options).unwrap();
+        assert_eq!(passphrases.len(), options.number);
+        assert!(passphrases.iter().all(|p| p.entropy >= options.min_entropy));
+        assert!(generate_from_str("", &options).is_err());
+    }
+
+    #[test]
+    fn test_read_embedded_corpus() {
+        // Without files, the embedded corpus is read rather than stdin (which would block).
+        let options = GenPassphraseOptions {
+            files: vec![],
+            embedded_corpus: true,
+            ..get_test_options()
+        };
+        match cfg!(feature = "embedded-corpus") {
+            true => {
+                assert!(read_corpus(&options).unwrap().word_count() > 0);
+                assert!(!corpus_words(&options).unwrap().is_empty());
+            }
+            false => assert!(corpus_words(&options)
+                .unwrap_err()
+                .to_string()
+                .contains("without an embedded corpus")),
+        }
+    }
+
+    #[test]
+    fn test_expected_sha256() {
+        let path = get_testdata_pathbuf();
+        let digest = sha256::hex_digest(&std::fs::read(&path).unwrap());
+        let with_digest = |digest: &str| GenPassphraseOptions {
+            expected_sha256: HashMap::from([(path.clone(), digest.to_string())]),
+            skip_bad_files: true,
+            ..get_test_options()
+        };
+        assert!(train(&with_digest(&digest.to_uppercase())).is_ok());
+        assert!(read_corpus(&with_digest(&digest)).is_ok());
+
+        // Mismatched files fail even when bad files are skipped.
+        let options = with_digest("abc123");
+        let error = train(&options).unwrap_err().to_string();
+        assert!(error.contains("Checksum mismatch: expected abc123"));
+        assert!(read_corpus(&options).is_err());
+
+        let model_path =
+            std::env::temp_dir().join(format!("markovpass-sha256-{}.mpm", std::process::id()));
+        train(&get_test_options())
+            .unwrap()
+            .save(&model_path)
+            .unwrap();
+        let model_digest = sha256::hex_digest(&std::fs::read(&model_path).unwrap());
+        let mut options = get_test_options();
+        assert!(load_model(&model_path, &options).is_ok());
+        options
+            .expected_sha256
+            .insert(model_path.clone(), digest.clone());
+        let result = load_model(&model_path, &options);
+        options
+            .expected_sha256
+            .insert(model_path.clone(), model_digest);
+        let matched = load_model(&model_path, &options);
+        std::fs::remove_file(&model_path).unwrap();
+        assert!(result.is_err());
+        assert!(matched.is_ok());
+    }
+
+    #[test]
+    fn test_model_training_options() {
+        let options = GenPassphraseOptions {
+            ngram_length: 4,
+            ngram_step: 2,
+            preserve_case: true,
+            exclude_chars: "Qz".to_string(),
+            ..get_test_options()
+        };
+        let model = train(&options).unwrap();
+        let retrained = model_training_options(get_test_options(), model.metadata());
+        assert_eq!(retrained.ngram_length, 4);
+        assert_eq!(retrained.ngram_step, 2);
+        assert_eq!(corpus_options(&retrained), corpus_options(&options));
+        let mut merged = train(&retrained).unwrap();
+        merged.merge(&model).unwrap();
+    }
+
+    #[test]
+    fn test_suggest_ngram_length() {
+        let (best, trials) = suggest_ngram_length(&get_test_options()).unwrap();
+        assert!(trials.iter().any(|trial| trial.ngram_length == best));
+        assert!(trials
+            .windows(2)
+            .all(|pair| pair[0].ngram_length < pair[1].ngram_length));
+        // Longer ngrams give fewer choices per character but more natural words.
+        let first = trials.first().unwrap();
+        let last = trials.last().unwrap();
+        assert!(first.entropy_rate > last.entropy_rate);
+        assert!(first.naturalness < last.naturalness);
+        assert!(trials
+            .iter()
+            .all(|trial| trial.score() <= trials[best - MIN_NGRAM_LENGTH].score()));
+
+        // The embedded corpus is sampled instead of stdin.
+        let embedded = GenPassphraseOptions {
+            files: vec![],
+            embedded_corpus: true,
+            ..get_test_options()
+        };
+        let suggestion = suggest_ngram_length(&embedded);
+        assert_eq!(suggestion.is_ok(), cfg!(feature = "embedded-corpus"));
+    }
+
+    #[test]
+    fn test_gen_passphrases_with_stats() {
+        let options = GenPassphraseOptions {
+            max_consonants: Some(2),
+            ..get_test_options()
+        };
+        let chain = build_chain(&options).unwrap();
+        let (passphrases, stats) = gen_passphrases_from_chain_with_stats(&chain, &options).unwrap();
+        assert_eq!(passphrases.len(), options.number);
+        assert_eq!(stats.attempts, options.number + stats.rejections.total());
+        assert!(stats.rejections.count(Rejection::ConsonantRun) > 0);
+        assert!(stats.acceptance_rate() > 0.0 && stats.acceptance_rate() < 1.0);
+        assert!(stats.elapsed > std::time::Duration::ZERO);
+        let mut passphrases = iter_passphrases_from_chain(&chain, &options).unwrap();
+        assert_eq!(passphrases.stats(), &GenerationStats::default());
+        passphrases.next().unwrap().unwrap();
+        assert_eq!(passphrases.stats().rejections, *passphrases.rejections());
+    }
+
+    #[test]
+    fn test_gen_passphrases_max_consonants() {
+        let options = GenPassphraseOptions {
+            max_consonants: Some(2),
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        for (passphrase, _) in passphrases {
+            assert!(!passphrase.contains("str"), "{}", passphrase);
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_min_output_word_length() {
+        let options = GenPassphraseOptions {
+            min_word_length: 2,
+            min_output_word_length: Some(5),
+            min_entropy: 40.0,
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        for (passphrase, _) in passphrases {
+            assert!(
+                passphrase.split(' ').all(|word| word.chars().count() >= 5),
+                "{}",
+                passphrase
+            );
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_reject_words() {
+        let filter = corpus_word_filter(&get_test_options(), 0.001).unwrap();
+        let words = corpus_words(&get_test_options()).unwrap();
+        assert!(words.iter().all(|word| filter.contains(word)));
+        let options = GenPassphraseOptions {
+            reject_words: Some(std::sync::Arc::new(filter)),
+            ..get_test_options()
+        };
+        for passphrase in gen_passphrases(&options).unwrap() {
+            for word in passphrase.0.split(' ') {
+                assert!(words.binary_search(&word.to_string()).is_err(), "{}", word);
+            }
+        }
+    }
+
+    #[test]
+    fn test_normalized_entropy() {
+        let options = GenPassphraseOptions {
+            separator: String::new(),
+            preserve_case: true,
+            ..get_test_options()
+        };
+        let chain = build_chain(&options).unwrap();
+        for passphrase in iter_passphrases_from_chain(&chain, &options).unwrap() {
+            let passphrase = passphrase.unwrap();
+            assert!(passphrase.normalized_entropy < passphrase.entropy);
+            assert!(passphrase.min_entropy < passphrase.entropy);
+            assert_eq!(
+                passphrase.entropy_by(EntropyMeasure::Min),
+                passphrase.min_entropy
+            );
+        }
+        let options = GenPassphraseOptions {
+            enforce_normalized_entropy: true,
+            ..options
+        };
+        for passphrase in iter_passphrases_from_chain(&chain, &options).unwrap() {
+            assert!(passphrase.unwrap().normalized_entropy >= 80.0);
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_reject_substrings() {
+        let common =
+            attacker::CommonSubstrings::from_lines(["the", "and", "ing"].into_iter(), 2, 10);
+        let options = GenPassphraseOptions {
+            reject_substrings: Some(std::sync::Arc::new(common)),
+            ..get_test_options()
+        };
+        for (passphrase, _) in gen_passphrases(&options).unwrap() {
+            let joined = passphrase.replace(' ', "");
+            for substring in ["th", "he", "an", "nd", "in", "ng"] {
+                assert!(!joined.contains(substring), "{}", passphrase);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_naturalness() {
+        let options = get_test_options();
+        let ensemble = std::sync::Arc::new(corpus_ensemble(&options, 95.0).unwrap());
+        let options = GenPassphraseOptions {
+            naturalness: Some(ensemble.clone()),
+            ..options
+        };
+        for (passphrase, _) in gen_passphrases(&options).unwrap() {
+            assert!(passphrase.split(' ').all(|word| ensemble.accepts(word)));
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_exclude_chars() {
+        let options = GenPassphraseOptions {
+            exclude_chars: "QXZ".to_string(),
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        for (passphrase, _) in passphrases {
+            assert!(!passphrase.contains(['q', 'x', 'z']), "{}", passphrase);
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_max_retries() {
+        let options = GenPassphraseOptions {
+            max_consonants: Some(0),
+            max_retries: 10,
+            ..get_test_options()
+        };
+        let error = gen_passphrases(&options).unwrap_err().to_string();
+        assert!(error.contains("11 consonant runs"), "{}", error);
+    }
+
+    #[test]
+    fn test_gen_passphrases_compact_camel() {
+        let options = GenPassphraseOptions {
+            style: Style::CompactCamel,
+            policy: Some("max_length = 40".parse().unwrap()),
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        for (passphrase, _) in passphrases {
+            assert!(!passphrase.contains(' '), "{}", passphrase);
+            assert!(passphrase.chars().count() <= 40, "{}", passphrase);
+            assert!(passphrase.starts_with(char::is_lowercase), "{}", passphrase);
+        }
+    }
+
+    #[test]
+    fn test_iter_passphrases_words() {
+        let options = GenPassphraseOptions {
+            separator: "".to_string(),
+            ..get_test_options()
+        };
+        let chain = build_chain(&options).unwrap();
+        for result in iter_passphrases_from_chain(&chain, &options).unwrap() {
+            let passphrase = result.unwrap();
+            assert_eq!(passphrase.words.concat(), passphrase.text);
+            assert!(passphrase.words.iter().all(|word| !word.contains(' ')));
+        }
+    }
+
+    #[test]
+    fn test_audit_replay() {
+        let options = GenPassphraseOptions {
+            number: 3,
+            audit: true,
+            max_consonants: Some(2),
+            ..get_test_options()
+        };
+        let chain = build_chain(&options).unwrap();
+        let fingerprint = chain.fingerprint();
+        for passphrase in iter_passphrases_from_chain(&chain, &options).unwrap() {
+            let passphrase = passphrase.unwrap();
+            let record = passphrase.audit.clone().unwrap();
+            assert_eq!(record.model, fingerprint);
+            assert_eq!(
+                record.commitment,
+                audit::commitment(&fingerprint, &record.seed)
+            );
+            let replayed = replay_passphrase(&chain, &options, record.seed).unwrap();
+            assert_eq!(replayed, passphrase);
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_transform() {
+        #[derive(Debug)]
+        struct Tag;
+
+        impl transform::OutputTransform for Tag {
+            fn transform(&self, passphrase: &str) -> Result<String, Box<dyn std::error::Error>> {
+                Ok(format!("{}!x", passphrase))
+            }
+
+            fn entropy_adjustment(&self) -> f64 {
+                -10.0
+            }
+        }
+
+        let options = GenPassphraseOptions {
+            transform: Some(std::sync::Arc::new(Tag)),
+            policy: Some("banned_substrings = [\"!x!\"]".parse().unwrap()),
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        for (passphrase, entropy) in passphrases {
+            assert!(passphrase.ends_with("!x"), "{}", passphrase);
+            assert!(entropy >= 80.0);
+        }
+    }
+
+    #[test]
+    fn test_gen_passphrases_best_of() {
+        let scorer = std::sync::Arc::new(scoring::TypingEffort);
+        let options = GenPassphraseOptions {
+            number: 20,
+            seed: Some(3),
+            scorer: Some(scorer.clone()),
+            ..get_test_options()
+        };
+        let mean_score = |passphrases: &[(String, f64)]| {
+            let words: Vec<String> = passphrases
+                .iter()
+                .flat_map(|(passphrase, _)| passphrase.split(' ').map(str::to_string))
+                .collect();
+            scoring::WordScorer::score_passphrase(&*scorer, &words)
+        };
+        let single = gen_passphrases(&options).unwrap();
+        let options = GenPassphraseOptions {
+            best_of: 16,
+            ..options
+        };
+        let best = gen_passphrases(&options).unwrap();
+        assert!(mean_score(&best) > mean_score(&single));
+        // Entropies are reported net of the 4 bits picking from 16 costs.
+        assert!(best.iter().all(|&(_, entropy)| entropy >= 80.0));
+    }
+
+    #[test]
+    fn test_gen_passphrases_policy() {
+        let options = GenPassphraseOptions {
+            separator: "-".to_string(),
+            policy: Some(
+                "max_length = 60\nbanned_substrings = [\"the\"]"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..get_test_options()
+        };
+        let passphrases = gen_passphrases(&options).unwrap();
+        for (passphrase, _) in passphrases {
+            assert!(passphrase.chars().count() <= 60, "{}", passphrase);
+            assert!(!passphrase.contains("the"), "{}", passphrase);
+        }
+    }
+
     #[cfg(feature = "benchmarks")]
     #[bench]
     fn bench_gen_passphrases(b: &mut test::Bencher) {
@@ -72,6 +2045,36 @@ mod tests {
         p
     }
 
+    /// `count` copies of the test corpus in a new temporary directory, which the caller removes.
+    /// Each copy has a different number of trailing newlines, so none is skipped as a duplicate.
+    fn testdata_copies(name: &str, count: usize) -> (PathBuf, Vec<PathBuf>) {
+        let dir = std::env::temp_dir().join(format!("markovpass-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut text = std::fs::read(get_testdata_pathbuf()).unwrap();
+        let files = (0..count)
+            .map(|i| {
+                let path = dir.join(format!("{}.txt", i));
+                text.push(b'\n');
+                std::fs::write(&path, &text).unwrap();
+                path
+            })
+            .collect();
+
+        (dir, files)
+    }
+
+    fn corpus_ngrams(
+        options: &GenPassphraseOptions,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut ngrams = vec![];
+        for_each_corpus_ngram(options, |ngram| {
+            ngrams.push(ngram.to_string());
+            Ok(())
+        })?;
+
+        Ok(ngrams)
+    }
+
     fn get_test_options() -> GenPassphraseOptions {
         GenPassphraseOptions {
             files: vec![get_testdata_pathbuf()],
@@ -79,6 +2082,7 @@ mod tests {
             min_entropy: 80.0,
             ngram_length: 3,
             min_word_length: 5,
+            ..Default::default()
         }
     }
 }
@@ -1,108 +1,124 @@
-use clap::{AppSettings, Parser};
+use clap::{AppSettings, CommandFactory, FromArgMatches, Parser, Subcommand};
+use cli::corpus::{corpus_add, corpus_fetch, corpus_list, corpus_remove, CorpusCommand};
+use cli::data_dir::DataDirOptions;
+use cli::generate::{generate, GenerateArgs};
+use cli::model::{model_diff, model_info, ModelCommand};
+use cli::secrets::{keepassxc, KeepassxcArgs};
+use cli::tools::{
+    attest, bench, check, clean, entropy, ngrams, selftest, stats, wordlist, AttestArgs, BenchArgs,
+    CheckArgs, CleanArgs, EntropyArgs, NgramsArgs, StatsArgs, WordlistArgs,
+};
+use cli::train::{train, TrainArgs};
+use cli::{clap_color, load_catalog, localize_command, stderr_renderer, tr, CATALOG, QUIET};
+use markovpass::output::Color;
+
+mod cli;
 
 fn main() {
-    let args = Args::parse();
-    let files = match get_corpus_files(&args.files) {
-        Ok(files) => files,
-        Err(error) => {
-            eprintln!("{}", error);
-            std::process::exit(1);
-        }
-    };
-    let gen_passphrase_options = markovpass::GenPassphraseOptions {
-        files,
-        number: args.number,
-        min_entropy: args.min_entropy,
-        ngram_length: args.ngram_length,
-        min_word_length: args.min_word_length,
-    };
-    let passphrases = match markovpass::gen_passphrases(&gen_passphrase_options) {
-        Ok(passphrases) => passphrases,
-        Err(error) => {
-            eprintln!("{}", error);
-            std::process::exit(1);
-        }
+    match load_catalog() {
+        Ok(catalog) => CATALOG.set(catalog).unwrap(),
+        Err(error) => eprintln!("Warning: Failed to load translations: {}", error),
+    }
+    let matches = localize_command(Cli::command().color(clap_color())).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    QUIET.store(cli.quiet, std::sync::atomic::Ordering::Relaxed);
+    let result = match &cli.command {
+        Some(Command::Generate(args)) => generate(args),
+        Some(Command::Train(args)) => train(args),
+        Some(Command::Model(ModelCommand::Info { file })) => model_info(file),
+        Some(Command::Model(ModelCommand::Diff { first, second })) => model_diff(first, second),
+        Some(Command::Corpus(CorpusCommand::Fetch { set })) => corpus_fetch(set),
+        Some(Command::Corpus(CorpusCommand::List {
+            extensions,
+            recursive,
+        })) => corpus_list(&DataDirOptions {
+            extensions: extensions.clone(),
+            recursive: *recursive,
+        }),
+        Some(Command::Corpus(CorpusCommand::Add { files, force })) => corpus_add(files, *force),
+        Some(Command::Corpus(CorpusCommand::Remove { names })) => corpus_remove(names),
+        Some(Command::Entropy(args)) => entropy(args),
+        Some(Command::Keepassxc(args)) => keepassxc(args),
+        Some(Command::Stats(args)) => stats(args),
+        Some(Command::Clean(args)) => clean(args),
+        Some(Command::Ngrams(args)) => ngrams(args),
+        Some(Command::Check(args)) => check(args),
+        Some(Command::Attest(args)) => attest(args),
+        Some(Command::Bench(args)) => bench(args),
+        Some(Command::Wordlist(args)) => wordlist(args),
+        Some(Command::Selftest) => selftest(),
+        None => generate(&cli.generate),
     };
-
-    for (passphrase, entropy) in passphrases {
-        if args.show_entropy {
-            println!("{} <{}>", passphrase, entropy);
-        } else {
-            println!("{}", passphrase);
+    if let Err(error) = result {
+        // The reader went away (e.g. 'markovpass -n 1000 | head'), so there's nothing to report.
+        if is_broken_pipe(&*error) {
+            return;
         }
+        let renderer = stderr_renderer();
+        // Errors formatted here are translated already, but library errors still need matching.
+        let message = renderer.wrap(&tr(&error.to_string()));
+        eprintln!("{}", renderer.paint(&message, Color::Red));
+        std::process::exit(1);
     }
 }
 
-#[derive(Parser, Debug, Clone)]
-#[clap(version, about, setting = AppSettings::DeriveDisplayOrder)]
-struct Args {
-    /// Files to use as markov chain input corpus. Use '-' to read from stdin
-    #[clap(value_parser)]
-    pub files: Vec<String>,
-
-    /// Number of passphrases to generate
-    #[clap(short = 'n', value_parser, default_value_t = 1)]
-    pub number: usize,
-
-    /// Minimum entropy
-    #[clap(short = 'e', value_parser, default_value_t = 60.0)]
-    pub min_entropy: f64,
+fn is_broken_pipe(error: &(dyn std::error::Error + 'static)) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|error| error.kind() == std::io::ErrorKind::BrokenPipe)
+}
 
-    /// Ngram length
-    #[clap(short = 'l', value_parser, default_value_t = 3)]
-    pub ngram_length: usize,
+#[derive(Parser, Debug, Clone)]
+#[clap(
+    version,
+    about,
+    setting = AppSettings::DeriveDisplayOrder,
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-    /// Minimum word length for corpus
-    #[clap(short = 'w', value_parser, default_value_t = 5)]
-    pub min_word_length: usize,
+    /// Suppress warnings and progress messages. Errors are still printed to stderr
+    #[clap(short, long, value_parser, global = true)]
+    quiet: bool,
 
-    /// Print the entropy for each passphrase
-    #[clap(long, value_parser, default_value_t = false)]
-    pub show_entropy: bool,
+    #[clap(flatten)]
+    generate: GenerateArgs,
 }
 
-fn get_corpus_files(files: &[String]) -> std::io::Result<Vec<std::path::PathBuf>> {
-    match files {
-        [] => get_data_files(),
-        [x] if x == "-" => Ok(vec![]),
-        _ => Ok(files.iter().map(|f| f.into()).collect()),
-    }
-}
-
-fn get_data_files() -> std::io::Result<Vec<std::path::PathBuf>> {
-    let mut data_dirs = directories::ProjectDirs::from_path("markovpass".into())
-        .map(|pds| vec![pds.data_dir().to_path_buf()])
-        .unwrap_or_default();
-    if cfg!(target_os = "linux") {
-        data_dirs.extend(
-            std::env::var("XDG_DATA_DIRS")
-                .unwrap_or_else(|_| "/usr/local/share/:/usr/share/".to_string())
-                .split(':')
-                .map(|s| std::path::PathBuf::from(s).join("markovpass")),
-        );
-    }
-    for dir in &data_dirs {
-        if dir.exists() {
-            let entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
-            let paths: Vec<_> = entries
-                .into_iter()
-                .map(|entry| entry.path())
-                .filter(|path| path.is_file())
-                .collect();
-            if !paths.is_empty() {
-                return Ok(paths);
-            }
-        }
-    }
-    Err(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        format!(
-            "No corpus data found in any of {}.",
-            data_dirs
-                .iter()
-                .map(|p| p.to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(", ")
-        ),
-    ))
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Generate passphrases (the default when no subcommand is given)
+    Generate(GenerateArgs),
+    /// Train a model file which can be used to generate passphrases without the corpus
+    Train(TrainArgs),
+    /// Inspect model files
+    #[clap(subcommand)]
+    Model(ModelCommand),
+    /// Manage the corpus files in the data directories
+    #[clap(subcommand)]
+    Corpus(CorpusCommand),
+    /// Calculate the Shannon and min-entropy of a distribution or uniform wordlist
+    Entropy(EntropyArgs),
+    /// Generate a passphrase and store it in a KeePassXC database with 'keepassxc-cli'
+    Keepassxc(KeepassxcArgs),
+    /// Show statistics about the chain built from a corpus or model
+    Stats(StatsArgs),
+    /// Print the corpus as it's cleaned for training
+    Clean(CleanArgs),
+    /// Print the ngrams the chain is trained on, with word boundaries shown as '_'
+    Ngrams(NgramsArgs),
+    /// Check whether a passphrase complies with a policy
+    Check(CheckArgs),
+    /// Regenerate a passphrase from its audit seed (read from stdin), after checking the seed
+    /// and chain match its commitment
+    Attest(AttestArgs),
+    /// Generate a reproducible list of invented words, e.g. for diceware, or verify one against
+    /// its manifest
+    Wordlist(WordlistArgs),
+    /// Measure training and generation speed, and memory use, on a corpus
+    Bench(BenchArgs),
+    /// Check that training and generation give the expected output for a built-in corpus
+    Selftest,
 }
@@ -1,13 +1,19 @@
+use crate::corpus::{Stride, WORD_BOUNDARY};
+use crate::entropy::{count_entropy, count_min_entropy};
+use rand::Rng;
 use rand_distr::weighted_alias::WeightedAliasIndex;
 use rand_distr::Distribution;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MarkovChainError {
     NoNgrams,
     ZeroEntropy,
     ZeroStartOfWordEntropy,
+    NoNodesAfterPruning,
+    NoStartingNgrams,
 }
 
 impl std::error::Error for MarkovChainError {}
@@ -20,226 +26,1129 @@ impl fmt::Display for MarkovChainError {
             Self::ZeroStartOfWordEntropy => {
                 write!(f, "Cleaned input has no start of word entropy.")
             }
+            Self::NoNodesAfterPruning => write!(
+                f,
+                "No usable ngrams left after pruning low entropy nodes. Try a lower minimum node entropy."
+            ),
+            Self::NoStartingNgrams => write!(f, "No ngrams left to start passphrases at."),
         }
     }
 }
 
-struct MarkovChainIterator<'chain> {
-    markov_chain: &'chain PassphraseMarkovChain<'chain>,
-    current: &'chain str,
+/// Raw transition counts between ngrams.
+///
+/// Ngrams are interned in the order they're first seen, and transitions are kept sorted, so
+/// building a chain from the same counts is deterministic.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransitionCounts {
+    ngrams: Vec<String>,
+    indices: HashMap<String, usize>,
+    transitions: Vec<BTreeMap<usize, u64>>,
 }
 
-impl<'chain> Iterator for MarkovChainIterator<'chain> {
-    type Item = &'chain str;
+impl TransitionCounts {
+    /// Count the transitions in a stream of ngrams.
+    ///
+    /// To guarantee every ngram has at least one valid transition, the last ngram transitions to
+    /// the first.
+    pub fn from_ngrams<'a>(ngrams: impl Iterator<Item = &'a str>) -> Self {
+        let mut counts = Self::default();
+        let mut ngrams = ngrams.peekable();
+        let first_ngram = match ngrams.peek() {
+            Some(&ngram) => ngram,
+            None => return counts,
+        };
+        while let Some(current_ngram) = ngrams.next() {
+            let next_ngram = ngrams.peek().unwrap_or(&first_ngram);
+            counts.add(current_ngram, next_ngram, 1);
+        }
+
+        counts
+    }
+
+    /// Add `count` transitions from `from` to `to`.
+    pub fn add(&mut self, from: &str, to: &str, count: u64) {
+        let from = self.intern(from);
+        let to = self.intern(to);
+        self.add_by_index(from, to, count);
+    }
+
+    /// Add `count` transitions between two already interned ngrams.
+    pub fn add_by_index(&mut self, from: usize, to: usize, count: u64) {
+        *self.transitions[from].entry(to).or_insert(0) += count;
+    }
+
+    /// Add all the transitions from `other` to these counts.
+    pub fn merge(&mut self, other: &TransitionCounts) {
+        for (from, transitions) in other.transitions.iter().enumerate() {
+            for (&to, &count) in transitions {
+                self.add(&other.ngrams[from], &other.ngrams[to], count);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ngrams.is_empty()
+    }
+
+    /// Replace every transition count with `f(count)`.
+    pub fn map_counts(&mut self, mut f: impl FnMut(u64) -> u64) {
+        for transitions in &mut self.transitions {
+            for count in transitions.values_mut() {
+                *count = f(*count);
+            }
+        }
+    }
+
+    /// The transition entropy of the ngram at `index`.
+    pub fn node_entropy(&self, index: usize) -> f64 {
+        let counts: Vec<_> = self.transitions(index).map(|(_, count)| count).collect();
+        count_entropy(&counts)
+    }
+
+    pub fn ngrams(&self) -> &[String] {
+        &self.ngrams
+    }
+
+    /// The index of `ngram`, if it's present.
+    pub fn index(&self, ngram: &str) -> Option<usize> {
+        self.indices.get(ngram).copied()
+    }
+
+    /// The `(target index, count)` transitions out of the ngram at `index`.
+    pub fn transitions(&self, index: usize) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.transitions[index]
+            .iter()
+            .map(|(&target, &count)| (target, count))
+    }
+
+    /// The number of times the ngram at `index` occurs.
+    pub fn occurrences(&self, index: usize) -> u64 {
+        self.transitions[index].values().sum()
+    }
+
+    /// The sum of the transition entropy of every ngram.
+    pub fn total_entropy(&self) -> f64 {
+        (0..self.ngrams.len())
+            .map(|index| self.node_entropy(index))
+            .sum()
+    }
+
+    /// A copy of these counts without the nodes whose transition entropy is below
+    /// `min_node_entropy`.
+    ///
+    /// Pruning is repeated until every remaining node has enough entropy among its remaining
+    /// transitions and can still reach the end of a word, so walks never get stuck.
+    pub fn pruned(&self, min_node_entropy: f64) -> TransitionCounts {
+        let mut alive = vec![true; self.ngrams.len()];
+        loop {
+            let mut changed = false;
+            for index in 0..self.ngrams.len() {
+                if !alive[index] {
+                    continue;
+                }
+                let counts: Vec<_> = self
+                    .transitions(index)
+                    .filter(|&(target, _)| alive[target])
+                    .map(|(_, count)| count)
+                    .collect();
+                if counts.is_empty() || count_entropy(&counts) < min_node_entropy {
+                    alive[index] = false;
+                    changed = true;
+                }
+            }
+            for (index, can_end) in self.can_reach_word_end(&alive).into_iter().enumerate() {
+                if alive[index] && !can_end {
+                    alive[index] = false;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut pruned = TransitionCounts::default();
+        for (index, ngram) in self.ngrams.iter().enumerate() {
+            if alive[index] {
+                pruned.intern(ngram);
+            }
+        }
+        for (index, ngram) in self.ngrams.iter().enumerate() {
+            for (target, count) in self.transitions(index) {
+                if alive[index] && alive[target] {
+                    pruned.add(ngram, &self.ngrams[target], count);
+                }
+            }
+        }
+
+        pruned
+    }
+
+    /// Which nodes can reach a word ending node travelling only through `alive` nodes.
+    fn can_reach_word_end(&self, alive: &[bool]) -> Vec<bool> {
+        let mut predecessors = vec![vec![]; self.ngrams.len()];
+        for index in 0..self.ngrams.len() {
+            for (target, _) in self.transitions(index) {
+                predecessors[target].push(index);
+            }
+        }
+        let mut reachable = vec![false; self.ngrams.len()];
+        let mut stack: Vec<usize> = (0..self.ngrams.len())
+            .filter(|&index| alive[index] && is_word_end(&self.ngrams[index]))
+            .collect();
+        while let Some(index) = stack.pop() {
+            if reachable[index] {
+                continue;
+            }
+            reachable[index] = true;
+            stack.extend(
+                predecessors[index]
+                    .iter()
+                    .filter(|&&index| alive[index] && !reachable[index]),
+            );
+        }
+
+        reachable
+    }
+
+    /// Get the index of `ngram`, adding it without any transitions if it's new.
+    pub fn intern(&mut self, ngram: &str) -> usize {
+        if let Some(&index) = self.indices.get(ngram) {
+            return index;
+        }
+        let index = self.ngrams.len();
+        self.ngrams.push(ngram.to_string());
+        self.indices.insert(ngram.to_string(), index);
+        self.transitions.push(BTreeMap::new());
+
+        index
+    }
+}
+
+/// Counts transitions between the ngrams of cleaned text as its words arrive, giving the same
+/// counts as `Corpus::ngrams_with_stride` would without the text ever being in memory.
+#[derive(Debug, Clone)]
+pub struct TransitionCounter {
+    counts: TransitionCounts,
+    window: usize,
+    stride: Stride,
+    /// The first `window` characters of the text, which the last ngrams wrap around to.
+    head: String,
+    /// The characters from the start of the next ngram on.
+    pending: VecDeque<char>,
+    /// How many of `pending` are from the text rather than `head`.
+    original: usize,
+    /// Characters the stride still has to move past before the next ngram starts.
+    skip: usize,
+    first: Option<usize>,
+    previous: Option<usize>,
+    ngram: String,
+}
+
+impl TransitionCounter {
+    /// A counter for ngrams of `window` characters advancing by `stride`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` or the step is 0.
+    pub fn new(window: usize, stride: Stride) -> Self {
+        assert!(window > 0, "ngram window must be positive");
+        assert!(stride.step > 0, "ngram step must be positive");
+        Self {
+            counts: TransitionCounts::default(),
+            window,
+            stride,
+            head: String::new(),
+            pending: VecDeque::new(),
+            original: 0,
+            skip: 0,
+            first: None,
+            previous: None,
+            ngram: String::new(),
+        }
+    }
+
+    /// Count the next word of the text, which starts with a `WORD_BOUNDARY` like every word of
+    /// cleaned text.
+    pub fn push_word(&mut self, word: &str) {
+        for c in std::iter::once(WORD_BOUNDARY).chain(word.chars()) {
+            if self.head.chars().count() < self.window {
+                self.head.push(c);
+            }
+            self.push_char(c, true);
+        }
+    }
+
+    /// The counts, once the text has wrapped around to its start.
+    pub fn finish(mut self) -> TransitionCounts {
+        for c in std::mem::take(&mut self.head).chars() {
+            self.push_char(c, false);
+        }
+        if let (Some(first), Some(last)) = (self.first, self.previous) {
+            self.counts.add_by_index(last, first, 1);
+        }
+
+        self.counts
+    }
+
+    fn push_char(&mut self, c: char, original: bool) {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        self.pending.push_back(c);
+        self.original += usize::from(original);
+        // An ngram can only start in the text itself, not the wrapped around head.
+        while self.pending.len() >= self.window && self.original > 0 {
+            self.ngram.clear();
+            self.ngram.extend(self.pending.iter().take(self.window));
+            let index = self.counts.intern(&self.ngram);
+            match self.previous {
+                Some(previous) => self.counts.add_by_index(previous, index, 1),
+                None => self.first = Some(index),
+            }
+            self.previous = Some(index);
+            let shift = self.stride.shift(&self.ngram);
+            let popped = shift.min(self.pending.len());
+            self.pending.drain(..popped);
+            self.original = self.original.saturating_sub(popped);
+            self.skip = shift - popped;
+        }
+    }
+}
+
+/// A passphrase generated by the chain, with its entropy by each measure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainPassphrase {
+    /// The words of the passphrase separated by `WORD_BOUNDARY`.
+    pub text: String,
+    /// The Shannon entropy in bits of the choices made generating the passphrase.
+    pub entropy: f64,
+    /// The entropy to an attacker who applies the generation's normalization.
+    pub normalized_entropy: f64,
+    /// The min-entropy in bits of the choices made, which only counts the most likely option of
+    /// each choice, for attackers who try the likeliest passphrases first. Never more than
+    /// `entropy`.
+    pub min_entropy: f64,
+}
+
+struct MarkovChainIterator<'chain, 'rng, R: Rng + ?Sized> {
+    markov_chain: &'chain PassphraseMarkovChain,
+    current: usize,
+    rng: &'rng mut R,
+}
+
+impl<'chain, 'rng, R: Rng + ?Sized> Iterator for MarkovChainIterator<'chain, 'rng, R> {
+    type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
         let last = self.current;
-        self.current = self.markov_chain.get_next_ngram(self.current);
+        self.current = self.markov_chain.nodes[self.current].next(self.rng);
 
         Some(last)
     }
 }
 
 #[derive(Debug)]
-struct MarkovNode<T> {
-    pub value: T,
-    transitions: Vec<T>,
-    dist: WeightedAliasIndex<f64>,
+struct MarkovNode {
+    transitions: Vec<usize>,
+    dist: WeightedAliasIndex<u64>,
     entropy: f64,
+    min_entropy: f64,
+    /// Bits of `entropy` lost when the case of the chosen characters can't be seen.
+    case_loss: f64,
+    /// An upper bound on the bits lost when it can't be seen whether a boundary was chosen.
+    boundary_loss: f64,
 }
 
-impl<T> MarkovNode<T> {
-    pub fn new(value: T, values: Vec<T>, weights: Vec<f64>) -> Self {
-        let entropy = weight_entropy(&weights);
+impl MarkovNode {
+    /// A node whose transitions each add the last `shift` characters of the ngram they go to.
+    pub fn new(
+        values: Vec<usize>,
+        weights: Vec<u64>,
+        counts: &TransitionCounts,
+        shift: usize,
+    ) -> Self {
+        let entropy = count_entropy(&weights);
+        let mut by_case: BTreeMap<String, u64> = BTreeMap::new();
+        let mut boundary = 0;
+        for (&value, &weight) in values.iter().zip(&weights) {
+            let ngram = &counts.ngrams()[value];
+            let skip = ngram.chars().count().saturating_sub(shift);
+            let added: String = ngram.chars().skip(skip).collect();
+            *by_case.entry(added.to_lowercase()).or_insert(0) += weight;
+            if added.contains(WORD_BOUNDARY) {
+                boundary += weight;
+            }
+        }
+        let by_case: Vec<u64> = by_case.into_values().collect();
+        let total: u64 = weights.iter().sum();
         Self {
-            value,
             transitions: values,
+            min_entropy: count_min_entropy(&weights),
             dist: WeightedAliasIndex::new(weights).unwrap(),
             entropy,
+            case_loss: entropy - count_entropy(&by_case),
+            boundary_loss: count_entropy(&[boundary, total - boundary]),
         }
     }
 
-    pub fn next(&self) -> &T {
-        &self.transitions[self.dist.sample(&mut rand::rngs::OsRng)]
+    pub fn next<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        self.transitions[self.dist.sample(rng)]
     }
 
     pub const fn entropy(&self) -> f64 {
         self.entropy
     }
+
+    pub const fn min_entropy(&self) -> f64 {
+        self.min_entropy
+    }
+
+    fn normalized_entropy(&self, normalization: Normalization) -> f64 {
+        let mut entropy = self.entropy;
+        if normalization.fold_case {
+            entropy -= self.case_loss;
+        }
+        if normalization.drop_boundaries {
+            entropy -= self.boundary_loss;
+        }
+
+        entropy.max(0.0)
+    }
+}
+
+/// What an attacker is assumed to normalize away before guessing, for conservative entropy
+/// estimates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    /// Letter case, as when guessing lowercased passphrases.
+    pub fold_case: bool,
+    /// Where words start and end, as when words are joined with no separator.
+    pub drop_boundaries: bool,
+}
+
+/// What to do when every word in the corpus starts with the same ngram, so choosing the start
+/// of a passphrase gives no entropy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SingleStart {
+    /// Fail with `MarkovChainError::ZeroStartOfWordEntropy`.
+    #[default]
+    Fail,
+    /// Also start at the ngrams which follow a word's first ngram, so passphrases may start
+    /// one character into a word.
+    Widen,
+    /// Always start with the one ngram. The start adds no entropy, and passphrases just need
+    /// more transitions to reach their minimum.
+    Allow,
+}
+
+impl fmt::Display for SingleStart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fail => write!(f, "fail"),
+            Self::Widen => write!(f, "widen"),
+            Self::Allow => write!(f, "allow"),
+        }
+    }
+}
+
+impl FromStr for SingleStart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "widen" => Ok(Self::Widen),
+            "allow" => Ok(Self::Allow),
+            _ => Err(format!(
+                "Unknown single start policy '{}'. Expected fail, widen or allow.",
+                s
+            )),
+        }
+    }
+}
+
+/// Options controlling how a chain is built from transition counts.
+#[derive(Debug, Clone, Default)]
+pub struct ChainOptions {
+    /// Prune nodes with less transition entropy (in bits) than this.
+    pub min_node_entropy: f64,
+    /// Choose starting ngrams uniformly instead of by how often they occur.
+    pub uniform_start: bool,
+    /// Allow passphrases to start at any ngram, not just at the start of a word.
+    pub start_anywhere: bool,
+    /// What to do if there's only one starting ngram.
+    pub single_start: SingleStart,
+    /// The stride the transition counts were collected with.
+    pub stride: Stride,
 }
 
 #[derive(Debug)]
-pub struct PassphraseMarkovChain<'ngrams> {
-    nodes: HashMap<&'ngrams str, MarkovNode<&'ngrams str>>,
-    starting_ngrams: Vec<&'ngrams str>,
-    starting_dist: WeightedAliasIndex<f64>,
+pub struct PassphraseMarkovChain {
+    counts: TransitionCounts,
+    nodes: Vec<MarkovNode>,
+    starting_ngrams: Vec<usize>,
+    starting_weights: Vec<u64>,
+    starting_dist: WeightedAliasIndex<u64>,
     starting_entropy: f64,
+    starting_min_entropy: f64,
+    /// Bits of `starting_entropy` lost when the case of the starting ngram can't be seen.
+    starting_case_loss: f64,
+    stride: Stride,
 }
 
-impl<'ngrams> PassphraseMarkovChain<'ngrams> {
+impl PassphraseMarkovChain {
     pub fn new(
-        ngrams: impl Iterator<Item = &'ngrams str>,
-    ) -> Result<PassphraseMarkovChain<'ngrams>, MarkovChainError> {
-        // Count transitions and viable starting ngrams.
-        // To get natural sounding words, starting ngrams should be at word start.
-        let mut transition_counters: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
-        let mut starting_ngram_counts: HashMap<&str, usize> = HashMap::new();
-        let mut ngrams = ngrams.peekable();
-        let first_ngram = <&str>::clone(ngrams.peek().ok_or(MarkovChainError::NoNgrams)?);
-        while let Some(current_ngram) = ngrams.next() {
-            if current_ngram.starts_with(' ') {
-                *starting_ngram_counts.entry(current_ngram).or_insert(0) += 1;
-            }
-            // To guarantee every ngram has at least one valid transition, let the last ngram
-            // transition to the first.
-            let next_ngram = ngrams.peek().unwrap_or(&first_ngram);
-            *transition_counters
-                .entry(current_ngram)
-                .or_default()
-                .entry(next_ngram)
-                .or_insert(0) += 1;
+        counts: TransitionCounts,
+        options: &ChainOptions,
+    ) -> Result<PassphraseMarkovChain, MarkovChainError> {
+        if counts.is_empty() {
+            return Err(MarkovChainError::NoNgrams);
         }
+        let counts = if options.min_node_entropy > 0.0 {
+            let pruned = counts.pruned(options.min_node_entropy);
+            if pruned.is_empty() {
+                return Err(MarkovChainError::NoNodesAfterPruning);
+            }
+            pruned
+        } else {
+            counts
+        };
 
-        // Generate the starting ngram probability distribution.
-        let mut starting_ngrams = Vec::with_capacity(starting_ngram_counts.len());
-        let mut starting_ngram_weights = Vec::with_capacity(starting_ngram_counts.len());
-        for (value, weight) in starting_ngram_counts {
-            starting_ngrams.push(value);
-            starting_ngram_weights.push(weight as f64);
+        // Find viable starting ngrams, weighted by how often they occur unless the start should be
+        // uniform. To get natural sounding words, starting ngrams should be at word start unless
+        // the caller wants to start anywhere.
+        let weight = |index| match options.uniform_start {
+            true => 1,
+            false => counts.occurrences(index),
+        };
+        let mut starting_ngrams: Vec<usize> = (0..counts.ngrams().len())
+            .filter(|&index| options.start_anywhere || is_word_start(&counts.ngrams()[index]))
+            .collect();
+        if starting_ngrams.len() == 1 && options.single_start == SingleStart::Widen {
+            let start = starting_ngrams[0];
+            starting_ngrams.extend(
+                counts
+                    .transitions(start)
+                    .map(|(to, _)| to)
+                    .filter(|&to| to != start && !is_word_start(&counts.ngrams()[to])),
+            );
+        }
+        let starting_ngram_weights: Vec<u64> =
+            starting_ngrams.iter().map(|&index| weight(index)).collect();
+        let starting_entropy = count_entropy(&starting_ngram_weights);
+        let starting_min_entropy = count_min_entropy(&starting_ngram_weights);
+        let mut starting_by_case: BTreeMap<String, u64> = BTreeMap::new();
+        for (&index, &weight) in starting_ngrams.iter().zip(&starting_ngram_weights) {
+            *starting_by_case
+                .entry(counts.ngrams()[index].to_lowercase())
+                .or_insert(0) += weight;
         }
-        let starting_entropy = weight_entropy(&starting_ngram_weights);
-        let starting_dist = WeightedAliasIndex::new(starting_ngram_weights).unwrap();
+        let starting_by_case: Vec<u64> = starting_by_case.into_values().collect();
+        let starting_case_loss = starting_entropy - count_entropy(&starting_by_case);
 
         // Build all the MarkovNodes from the transition counts.
-        let mut nodes: HashMap<&str, MarkovNode<&str>> = HashMap::new();
+        let mut nodes = Vec::with_capacity(counts.ngrams().len());
         let mut total_entropy: f64 = 0.0;
-        for (ngram, transition_counts) in transition_counters {
-            let mut values = Vec::with_capacity(transition_counts.len());
-            let mut weights = Vec::with_capacity(transition_counts.len());
-            for (value, weight) in transition_counts {
-                values.push(value);
-                weights.push(weight as f64);
-            }
-
-            let node = MarkovNode::new(ngram, values, weights);
+        for index in 0..counts.ngrams().len() {
+            let (values, weights): (Vec<_>, Vec<_>) = counts.transitions(index).unzip();
+            let shift = options.stride.shift(&counts.ngrams()[index]);
+            let node = MarkovNode::new(values, weights, &counts, shift);
             total_entropy += node.entropy();
-            nodes.insert(ngram, node);
+            nodes.push(node);
         }
 
         if total_entropy == 0.0 {
             return Err(MarkovChainError::ZeroEntropy);
         }
-        if starting_entropy == 0.0 {
+        if starting_entropy == 0.0 && options.single_start != SingleStart::Allow {
             return Err(MarkovChainError::ZeroStartOfWordEntropy);
         }
+        let starting_dist = WeightedAliasIndex::new(starting_ngram_weights.clone())
+            .map_err(|_| MarkovChainError::NoStartingNgrams)?;
 
         Ok(PassphraseMarkovChain {
+            counts,
             nodes,
             starting_ngrams,
+            starting_weights: starting_ngram_weights,
             starting_dist,
             starting_entropy,
+            starting_min_entropy,
+            starting_case_loss,
+            stride: options.stride,
         })
     }
 
+    /// Generate a passphrase with at least `min_entropy` bits of entropy.
+    ///
+    /// Words in the passphrase are separated by `WORD_BOUNDARY`.
     pub fn passphrase(&self, min_entropy: f64) -> (String, f64) {
+        self.passphrase_with_rng(min_entropy, &mut rand::rngs::OsRng)
+    }
+
+    /// Generate a passphrase like `passphrase`, drawing randomness from `rng`.
+    ///
+    /// Passphrases are only as unpredictable as `rng`, so anything but a cryptographically secure
+    /// and securely seeded generator is only suitable for testing.
+    pub fn passphrase_with_rng<R: Rng + ?Sized>(
+        &self,
+        min_entropy: f64,
+        rng: &mut R,
+    ) -> (String, f64) {
+        let passphrase =
+            self.normalized_passphrase_with_rng(min_entropy, 0.0, Normalization::default(), rng);
+
+        (passphrase.text, passphrase.entropy)
+    }
+
+    /// Generate a passphrase with at least `min_entropy` bits of entropy, and at least
+    /// `min_normalized_entropy` bits to an attacker who applies `normalization`.
+    ///
+    /// The normalized entropy is a lower bound: it assumes the attacker learns every case and
+    /// word boundary choice normalization hides, though some can be inferred from the rest of
+    /// the passphrase.
+    pub fn normalized_passphrase_with_rng<R: Rng + ?Sized>(
+        &self,
+        min_entropy: f64,
+        min_normalized_entropy: f64,
+        normalization: Normalization,
+        rng: &mut R,
+    ) -> ChainPassphrase {
         let mut selected_ngrams = Vec::new();
         let mut entropy = self.starting_entropy;
+        let mut min_entropy_bits = self.starting_min_entropy;
+        let mut normalized_entropy = self.starting_entropy;
+        if normalization.fold_case {
+            normalized_entropy -= self.starting_case_loss;
+        }
 
-        for ngram in self.iter() {
+        for index in self.iter(rng) {
+            let ngram = self.ngram(index);
             selected_ngrams.push(ngram);
-            entropy += self.ngram_entropy(ngram);
-            if entropy >= min_entropy && ngram.ends_with(' ') {
+            entropy += self.nodes[index].entropy();
+            min_entropy_bits += self.nodes[index].min_entropy();
+            normalized_entropy += self.nodes[index].normalized_entropy(normalization);
+            if entropy >= min_entropy
+                && normalized_entropy >= min_normalized_entropy
+                && is_word_end(ngram)
+            {
                 break;
             }
         }
 
-        // Include the first character from each ngram, and the whole final ngram.
-        let tail = selected_ngrams.last().unwrap().chars().skip(1);
-        let chars = selected_ngrams
+        // Include the characters the window moves past at each ngram, and the whole final ngram.
+        let (last, rest) = selected_ngrams.split_last().unwrap();
+        // Reserved up front so growing it leaves no copies of the passphrase behind.
+        let mut passphrase =
+            String::with_capacity(selected_ngrams.iter().map(|ngram| ngram.len()).sum());
+        for ngram in rest {
+            passphrase.extend(ngram.chars().take(self.stride.shift(ngram)));
+        }
+        passphrase.push_str(last);
+        let text = passphrase.trim_matches(WORD_BOUNDARY).to_string();
+        crate::wipe(passphrase);
+        ChainPassphrase {
+            text,
+            entropy,
+            normalized_entropy,
+            min_entropy: min_entropy_bits,
+        }
+    }
+
+    /// A SHA-256 hex digest identifying the chain's ngrams, transition counts and starting
+    /// weights, so chains which generate the same passphrases from the same randomness share it.
+    pub fn fingerprint(&self) -> String {
+        fn add(hasher: &mut crate::sha256::Sha256, value: u64) {
+            hasher.update(&value.to_le_bytes());
+        }
+
+        let mut hasher = crate::sha256::Sha256::new();
+        add(&mut hasher, self.counts.ngrams().len() as u64);
+        for (index, ngram) in self.counts.ngrams().iter().enumerate() {
+            add(&mut hasher, ngram.len() as u64);
+            hasher.update(ngram.as_bytes());
+            let transitions: Vec<_> = self.counts.transitions(index).collect();
+            add(&mut hasher, transitions.len() as u64);
+            for (to, count) in transitions {
+                add(&mut hasher, to as u64);
+                add(&mut hasher, count);
+            }
+        }
+        add(&mut hasher, self.starting_ngrams.len() as u64);
+        for (&index, &weight) in self.starting_ngrams.iter().zip(&self.starting_weights) {
+            add(&mut hasher, index as u64);
+            add(&mut hasher, weight);
+        }
+        // Only strided chains hash their stride, so other fingerprints are unchanged.
+        if self.stride != Stride::default() {
+            add(&mut hasher, self.stride.step as u64);
+            add(&mut hasher, self.stride.reset_at_words as u64);
+        }
+
+        crate::sha256::to_hex(&hasher.finalize())
+    }
+
+    /// The number of nodes (distinct ngrams) in the chain.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The entropy in bits of the choice of starting ngram.
+    pub fn starting_entropy(&self) -> f64 {
+        self.starting_entropy
+    }
+
+    /// The stride the chain's counts were collected with.
+    pub fn stride(&self) -> Stride {
+        self.stride
+    }
+
+    /// An upper bound on the entropy in bits of a passphrase `length` characters long, taking
+    /// the most uncertain transition for every character.
+    pub fn max_entropy(&self, length: usize) -> f64 {
+        let max_node_entropy = self
+            .nodes
             .iter()
-            .map(|n| n.chars().next().unwrap())
-            .chain(tail);
-        let passphrase = chars.collect::<String>().trim().to_string();
+            .map(MarkovNode::entropy)
+            .fold(0.0, f64::max);
+        self.starting_entropy + length as f64 * max_node_entropy
+    }
+
+    /// The typical length in characters of passphrases with at least `min_entropy` bits of
+    /// entropy, from the chain's `entropy_rate`.
+    pub fn expected_length(&self, min_entropy: f64) -> f64 {
+        (min_entropy - self.starting_entropy).max(0.0) / self.entropy_rate()
+    }
+
+    /// The average entropy in bits each character of a long passphrase adds: the transition
+    /// entropy of the nodes weighted by the chain's stationary distribution, over the average
+    /// number of characters each transition adds.
+    pub fn entropy_rate(&self) -> f64 {
+        let mut entropy = 0.0;
+        let mut shift = 0.0;
+        for (index, probability) in self.stationary_distribution().into_iter().enumerate() {
+            entropy += probability * self.nodes[index].entropy();
+            shift += probability * self.stride.shift(self.ngram(index)) as f64;
+        }
 
-        (passphrase, entropy)
+        entropy / shift
     }
 
-    fn iter(&self) -> MarkovChainIterator {
+    /// How often a long walk on the chain visits each node, by power iteration.
+    ///
+    /// Iteration starts from how often each ngram occurs in the corpus, which is already close
+    /// unless pruning changed the transitions. Each step averages with the previous
+    /// distribution, which keeps the same fixed point but also converges for periodic chains.
+    fn stationary_distribution(&self) -> Vec<f64> {
+        const MAX_ITERATIONS: usize = 10000;
+        const TOLERANCE: f64 = 1e-12;
+        let total: u64 = (0..self.nodes.len())
+            .map(|index| self.counts.occurrences(index))
+            .sum();
+        let mut distribution: Vec<f64> = (0..self.nodes.len())
+            .map(|index| self.counts.occurrences(index) as f64 / total as f64)
+            .collect();
+        for _ in 0..MAX_ITERATIONS {
+            let mut next: Vec<f64> = distribution.iter().map(|p| p / 2.0).collect();
+            for (index, &probability) in distribution.iter().enumerate() {
+                let occurrences = self.counts.occurrences(index) as f64;
+                for (target, count) in self.counts.transitions(index) {
+                    next[target] += probability / 2.0 * count as f64 / occurrences;
+                }
+            }
+            let change: f64 = next
+                .iter()
+                .zip(&distribution)
+                .map(|(a, b)| (a - b).abs())
+                .sum();
+            distribution = next;
+            if change < TOLERANCE {
+                break;
+            }
+        }
+
+        distribution
+    }
+
+    /// The probability of a passphrase starting with each letter.
+    pub fn starting_letters(&self) -> BTreeMap<char, f64> {
+        let total: u64 = self.starting_weights.iter().sum();
+        let mut letters = BTreeMap::new();
+        for (&index, &weight) in self.starting_ngrams.iter().zip(&self.starting_weights) {
+            if let Some(letter) = self.ngram(index).chars().find(|&c| c != WORD_BOUNDARY) {
+                *letters.entry(letter).or_insert(0.0) += weight as f64 / total as f64;
+            }
+        }
+
+        letters
+    }
+
+    fn iter<'rng, R: Rng + ?Sized>(&self, rng: &'rng mut R) -> MarkovChainIterator<'_, 'rng, R> {
         MarkovChainIterator {
             markov_chain: self,
-            current: self.get_starting_ngram(),
+            current: self.get_starting_ngram(rng),
+            rng,
         }
     }
 
-    fn get_starting_ngram(&self) -> &str {
-        self.nodes
-            .get(&self.starting_ngrams[self.starting_dist.sample(&mut rand::rngs::OsRng)])
-            .unwrap()
-            .value
+    fn get_starting_ngram<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        self.starting_ngrams[self.starting_dist.sample(rng)]
     }
 
-    fn get_next_ngram(&self, ngram: &str) -> &str {
-        self.nodes.get(ngram).unwrap().next()
+    fn ngram(&self, index: usize) -> &str {
+        &self.counts.ngrams()[index]
     }
+}
 
-    fn ngram_entropy(&self, ngram: &str) -> f64 {
-        self.nodes.get(ngram).unwrap().entropy()
-    }
+fn is_word_start(ngram: &str) -> bool {
+    ngram.starts_with(WORD_BOUNDARY)
 }
 
-fn weight_entropy(weights: &[f64]) -> f64 {
-    let total: f64 = weights.iter().sum();
-    weights.iter().fold(0.0, |acc, weight| {
-        let prob = weight / total;
-        acc - prob * prob.log2()
-    })
+fn is_word_end(ngram: &str) -> bool {
+    ngram.ends_with(WORD_BOUNDARY)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn chain_from(ngrams: &[&str]) -> Result<PassphraseMarkovChain, MarkovChainError> {
+        PassphraseMarkovChain::new(
+            TransitionCounts::from_ngrams(ngrams.iter().cloned()),
+            &ChainOptions::default(),
+        )
+    }
+
     #[test]
     fn test_passphrasemarkovchain_new() {
-        let ngrams = vec![" ti", "tic", "ic ", "c t", " to", "toc", "oc ", "c t"];
-        let result = PassphraseMarkovChain::new(ngrams.iter().cloned());
+        let ngrams = [" ti", "tic", "ic ", "c t", " to", "toc", "oc ", "c t"];
+        let result = chain_from(&ngrams);
         assert!(result.is_ok());
         let chain = result.unwrap();
-        assert_eq!(chain.starting_ngrams.len(), 2);
-        assert!(chain.starting_ngrams.contains(&" ti"));
-        assert!(chain.starting_ngrams.contains(&" to"));
+        let starting_ngrams: Vec<_> = chain
+            .starting_ngrams
+            .iter()
+            .map(|&i| chain.ngram(i))
+            .collect();
+        assert_eq!(starting_ngrams.len(), 2);
+        assert!(starting_ngrams.contains(&" ti"));
+        assert!(starting_ngrams.contains(&" to"));
         assert_eq!(chain.starting_entropy, 1.0);
-        assert!(ngrams.contains(&chain.get_starting_ngram()));
+        assert!(ngrams.contains(&chain.ngram(chain.get_starting_ngram(&mut rand::rngs::OsRng))));
         let (p, e) = chain.passphrase(60.0);
         assert_eq!(e, 60.0);
         assert_eq!(p.len(), 239);
     }
 
+    #[test]
+    fn test_starting_letters() {
+        let chain = chain_from(&[
+            " ab", "ab ", "b a", " ab", "ab ", "b c", " cd", "cd ", "d a",
+        ])
+        .unwrap();
+        let letters: Vec<_> = chain.starting_letters().into_iter().collect();
+        assert_eq!(letters, vec![('a', 2.0 / 3.0), ('c', 1.0 / 3.0)]);
+    }
+
+    #[test]
+    fn test_max_entropy() {
+        let chain = chain_from(&[
+            " ab", "ab ", "b a", " ab", "ab ", "b c", " cd", "cd ", "d a",
+        ])
+        .unwrap();
+        // Only "ab " has a choice, of two equally likely transitions.
+        assert_eq!(chain.max_entropy(10), chain.starting_entropy() + 10.0);
+        for _ in 0..10 {
+            let (passphrase, entropy) = chain.passphrase(20.0);
+            assert!(entropy <= chain.max_entropy(passphrase.chars().count()));
+        }
+    }
+
+    #[test]
+    fn test_expected_length() {
+        // Only "ab " has a choice, of two, and it's 2 of the 9 ngrams, so a bit is chosen every
+        // 4.5 characters.
+        let chain = chain_from(&[
+            " ab", "ab ", "b a", " ab", "ab ", "b c", " cd", "cd ", "d a",
+        ])
+        .unwrap();
+        let length = chain.expected_length(chain.starting_entropy() + 10.0);
+        assert!((length - 45.0).abs() < 1e-9, "{}", length);
+        assert_eq!(chain.expected_length(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_entropy_rate() {
+        let chain = chain_from(&[
+            " ab", "ab ", "b a", " ab", "ab ", "b c", " cd", "cd ", "d a",
+        ])
+        .unwrap();
+        assert!((chain.entropy_rate() - 1.0 / 4.5).abs() < 1e-9);
+
+        // Only " ab" has a choice, and it's every other character of a long walk, however rarely
+        // it and "abd" occur compared to "abc".
+        let mut counts = TransitionCounts::default();
+        counts.add(" ab", "abc", 1);
+        counts.add(" ab", "abd", 1);
+        counts.add("abc", " ab", 100);
+        counts.add("abd", " ab", 1);
+        let options = ChainOptions {
+            start_anywhere: true,
+            ..Default::default()
+        };
+        let chain = PassphraseMarkovChain::new(counts, &options).unwrap();
+        assert!(
+            (chain.entropy_rate() - 0.5).abs() < 1e-9,
+            "{}",
+            chain.entropy_rate()
+        );
+    }
+
+    #[test]
+    fn test_stride() {
+        // Ngrams two characters apart: each transition adds two characters.
+        let mut counts = TransitionCounts::default();
+        for from in [" ab", " cb"] {
+            counts.add(from, "bc ", 1);
+            counts.add(from, "bd ", 1);
+        }
+        for from in ["bc ", "bd "] {
+            counts.add(from, " ab", 1);
+            counts.add(from, " cb", 1);
+        }
+        let options = ChainOptions {
+            stride: Stride {
+                step: 2,
+                reset_at_words: false,
+            },
+            ..Default::default()
+        };
+        let chain = PassphraseMarkovChain::new(counts.clone(), &options).unwrap();
+        for _ in 0..20 {
+            let (passphrase, entropy) = chain.passphrase(2.0);
+            assert!(["abc", "abd", "cbc", "cbd"].contains(&passphrase.as_str()));
+            assert_eq!(entropy, 3.0);
+        }
+        // Every choice is even, so no option is any easier to guess.
+        let mut rng = rand::rngs::OsRng;
+        let passphrase =
+            chain.normalized_passphrase_with_rng(2.0, 0.0, Normalization::default(), &mut rng);
+        assert_eq!(passphrase.min_entropy, passphrase.entropy);
+        assert!((chain.entropy_rate() - 0.5).abs() < 1e-9);
+        let default = PassphraseMarkovChain::new(counts, &ChainOptions::default()).unwrap();
+        assert_ne!(chain.fingerprint(), default.fingerprint());
+    }
+
+    #[test]
+    fn test_uniform_start() {
+        let ngrams = [
+            " ti", "tic", "ic ", "c t", " ti", "tic", "ic ", "c t", " to", "toc", "oc ", "c t",
+        ];
+        let counts = TransitionCounts::from_ngrams(ngrams.into_iter());
+        let chain = PassphraseMarkovChain::new(counts.clone(), &ChainOptions::default()).unwrap();
+        assert!(chain.starting_entropy < 1.0);
+        let options = ChainOptions {
+            uniform_start: true,
+            ..Default::default()
+        };
+        let chain = PassphraseMarkovChain::new(counts, &options).unwrap();
+        assert_eq!(chain.starting_entropy, 1.0);
+    }
+
+    #[test]
+    fn test_min_entropy() {
+        // " ti" starts two words in three, and every other choice is forced.
+        let ngrams = [
+            " ti", "tic", "ic ", "c t", " ti", "tic", "ic ", "c t", " to", "toc", "oc ", "c t",
+        ];
+        let counts = TransitionCounts::from_ngrams(ngrams.into_iter());
+        let chain = PassphraseMarkovChain::new(counts, &ChainOptions::default()).unwrap();
+        assert_eq!(chain.starting_min_entropy, (3.0f64 / 2.0).log2());
+        let mut rng = rand::rngs::OsRng;
+        for _ in 0..10 {
+            let passphrase =
+                chain.normalized_passphrase_with_rng(0.0, 0.0, Normalization::default(), &mut rng);
+            assert!(passphrase.min_entropy < passphrase.entropy);
+            assert!(passphrase.min_entropy >= chain.starting_min_entropy);
+        }
+    }
+
+    #[test]
+    fn test_start_anywhere() {
+        let ngrams = [
+            " ab", "abc", "bc ", "c a", " ab", "abc", "cbd", "bd ", "d a",
+        ];
+        let counts = TransitionCounts::from_ngrams(ngrams.into_iter());
+        let options = ChainOptions {
+            start_anywhere: true,
+            ..Default::default()
+        };
+        let chain = PassphraseMarkovChain::new(counts, &options).unwrap();
+        assert_eq!(chain.starting_ngrams.len(), 7);
+        assert!(chain.starting_entropy > 0.0);
+    }
+
+    #[test]
+    fn test_transition_counts() {
+        let counts = TransitionCounts::from_ngrams([" ab", "abc", "bc ", " ab"].into_iter());
+        assert_eq!(counts.ngrams(), &[" ab", "abc", "bc "]);
+        assert_eq!(
+            counts.transitions(0).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 1)]
+        );
+        assert_eq!(counts.transitions(2).collect::<Vec<_>>(), vec![(0, 1)]);
+        assert_eq!(counts.occurrences(0), 2);
+
+        let mut merged = counts.clone();
+        merged.merge(&TransitionCounts::from_ngrams([" ab", "abd"].into_iter()));
+        assert_eq!(merged.ngrams(), &[" ab", "abc", "bc ", "abd"]);
+        assert_eq!(
+            merged.transitions(0).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 1), (3, 1)]
+        );
+        assert_eq!(merged.transitions(3).collect::<Vec<_>>(), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_transition_counter() {
+        use crate::corpus::{CleaningOptions, Corpus};
+        let options = CleaningOptions::default();
+        let texts = [
+            "",
+            "a",
+            "ab cd",
+            "the quick brown fox jumps over the lazy dog",
+        ];
+        let strides = [(1, false), (2, false), (5, false), (5, true)];
+        for (text, window, (step, reset_at_words)) in texts
+            .into_iter()
+            .flat_map(|text| [1, 3, 7].map(|window| (text, window)))
+            .flat_map(|(text, window)| strides.map(|stride| (text, window, stride)))
+        {
+            let stride = Stride {
+                step,
+                reset_at_words,
+            };
+            let corpus = Corpus::from_text(text, window, &options);
+            let expected = TransitionCounts::from_ngrams(corpus.ngrams_with_stride(stride));
+            let mut counter = TransitionCounter::new(window, stride);
+            for word in corpus.words() {
+                counter.push_word(word);
+            }
+            assert_eq!(
+                counter.finish(),
+                expected,
+                "{:?} {} {:?}",
+                text,
+                window,
+                stride
+            );
+        }
+    }
+
+    #[test]
+    fn test_pruned() {
+        // "abc" and "bcd" always transition the same way, but "c t" branches.
+        let ngrams = [
+            " ab", "abc", "bc ", "c t", " ti", "tic", "ic ", "c t", " to", "toc", "oc ", "c a",
+        ];
+        let counts = TransitionCounts::from_ngrams(ngrams.into_iter());
+        assert_eq!(counts.pruned(0.0), counts);
+        assert!(counts.pruned(0.5).is_empty());
+
+        let ngrams = [" ab", " ac", " ab", " ad", " ac", " ad"];
+        let counts = TransitionCounts::from_ngrams(ngrams.into_iter());
+        // " ad" only ever transitions to " ac" and " ab", so no node can reach a word end.
+        assert!(counts.pruned(0.5).is_empty());
+    }
+
+    #[test]
+    fn test_prune_keeps_branching_nodes() {
+        let ngrams = ["a ", "b ", "a ", "a ", "b ", "b ", "c "];
+        let counts = TransitionCounts::from_ngrams(ngrams.into_iter());
+        let pruned = counts.pruned(0.5);
+        // "c " only transitions to "a ", but "a " and "b " still branch without it.
+        assert_eq!(pruned.ngrams(), &["a ", "b "]);
+        assert_eq!(
+            pruned.transitions(0).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 2)]
+        );
+        assert_eq!(
+            pruned.transitions(1).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_passphrase_no_starting_ngrams_after_pruning() {
+        // " a" only transitions to "a ", so pruning leaves no word start.
+        let ngrams = [" a", "a ", "b ", "a ", "a ", "b ", "b ", "c "];
+        let options = ChainOptions {
+            min_node_entropy: 0.5,
+            single_start: SingleStart::Allow,
+            ..Default::default()
+        };
+        let result =
+            PassphraseMarkovChain::new(TransitionCounts::from_ngrams(ngrams.into_iter()), &options);
+        assert_eq!(result.unwrap_err(), MarkovChainError::NoStartingNgrams);
+    }
+
     #[test]
     fn test_passphrase_no_ngrams() {
-        let result = PassphraseMarkovChain::new(std::iter::empty());
+        let result = chain_from(&[]);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), MarkovChainError::NoNgrams);
     }
 
     #[test]
     fn test_passphrase_no_entropy() {
-        let ngrams = vec![" ab", "abc", "bcd", "cd ", "d a"];
-        let result = PassphraseMarkovChain::new(ngrams.into_iter());
+        let ngrams = [" ab", "abc", "bcd", "cd ", "d a"];
+        let result = chain_from(&ngrams);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), MarkovChainError::ZeroEntropy);
     }
 
     #[test]
     fn test_passphrases_no_starting_entropy() {
-        let ngrams = vec![
+        let ngrams = [
             " ab", "abc", "bc ", "c a", " ab", "abc", "cbd", "bd ", "d a",
         ];
-        let result = PassphraseMarkovChain::new(ngrams.into_iter());
+        let result = chain_from(&ngrams);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
             MarkovChainError::ZeroStartOfWordEntropy
         );
     }
+
+    #[test]
+    fn test_single_start() {
+        let ngrams = [
+            " ab", "abc", "bc ", "c a", " ab", "abd", "bd ", "d a", " ab", "abd", "bd ", "d a",
+        ];
+        let counts = TransitionCounts::from_ngrams(ngrams.iter().copied());
+        let chain = |single_start| {
+            let options = ChainOptions {
+                single_start,
+                ..Default::default()
+            };
+            PassphraseMarkovChain::new(counts.clone(), &options)
+        };
+        assert_eq!(
+            chain(SingleStart::Fail).unwrap_err(),
+            MarkovChainError::ZeroStartOfWordEntropy
+        );
+        let widened = chain(SingleStart::Widen).unwrap();
+        assert!(widened.starting_entropy() > 0.0);
+        let allowed = chain(SingleStart::Allow).unwrap();
+        assert_eq!(allowed.starting_entropy(), 0.0);
+        let (passphrase, entropy) = allowed.passphrase(3.0);
+        assert!(passphrase.starts_with("ab"));
+        assert!(entropy >= 3.0);
+        assert_eq!("widen".parse(), Ok(SingleStart::Widen));
+        assert!("sometimes".parse::<SingleStart>().is_err());
+    }
 }
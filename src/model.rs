@@ -0,0 +1,1024 @@
+//! Saved model files.
+//!
+//! A model file stores the raw transition counts of a trained chain so it can be shared and
+//! reused without the source corpus. Strings are stored once in an ngram table and transitions
+//! are delta and varint encoded, which keeps files compact. Quantized models go further, storing
+//! each count as a single logarithmically scaled byte. Saved files are compressed with zstd on
+//! top of that, when it's installed.
+
+use crate::compression::{self, Compression};
+use crate::corpus::{ApostrophePolicy, DigitPolicy, Stride};
+use crate::encoding::Encoding;
+use crate::markovchain::{ChainOptions, MarkovChainError, PassphraseMarkovChain, TransitionCounts};
+use crate::script::Script;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"MKVP";
+
+/// The model file format version written by this version of markovpass.
+///
+/// Version 2 added quantization, version 3 the stride and version 4 the corpus options. Older
+/// files can still be read.
+pub const FORMAT_VERSION: u64 = 4;
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(io::Error),
+    NotAModel,
+    UnsupportedVersion(u64),
+    Corrupt(&'static str),
+    IncompatibleNgramLength(usize, usize),
+    IncompatibleStride,
+    IncompatibleCorpusOptions,
+    Quantized,
+}
+
+impl std::error::Error for ModelError {}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::NotAModel => write!(f, "Not a markovpass model file."),
+            Self::UnsupportedVersion(version) if *version > FORMAT_VERSION => write!(
+                f,
+                "Model format version {} is newer than this markovpass can read (versions 1 to \
+                 {}). Upgrade markovpass, or retrain the model with this version.",
+                version, FORMAT_VERSION
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported model format version {} (expected 1 to {}).",
+                version, FORMAT_VERSION
+            ),
+            Self::Corrupt(reason) => write!(f, "Corrupt model file: {}.", reason),
+            Self::IncompatibleNgramLength(expected, found) => write!(
+                f,
+                "Can't merge a model with ngram length {} into one with ngram length {}.",
+                found, expected
+            ),
+            Self::IncompatibleStride => {
+                write!(f, "Can't merge models trained with different ngram steps.")
+            }
+            Self::IncompatibleCorpusOptions => {
+                write!(
+                    f,
+                    "Can't merge models trained on corpora cleaned differently."
+                )
+            }
+            Self::Quantized => write!(f, "Quantized models can't be merged."),
+        }
+    }
+}
+
+impl From<io::Error> for ModelError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Corrupt("unexpected end of file"),
+            _ => Self::Io(error),
+        }
+    }
+}
+
+/// Provenance information recorded in a model when it's trained.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelMetadata {
+    /// The format version of the file the model was read from. Models are always saved in the
+    /// current `FORMAT_VERSION`.
+    pub format_version: u64,
+    /// The markovpass version that trained the model.
+    pub crate_version: String,
+    /// Creation time in seconds since the Unix epoch.
+    pub created: u64,
+    pub ngram_length: usize,
+    pub min_word_length: usize,
+    pub corpus_files: Vec<CorpusFile>,
+    /// The sum of the transition entropy of every node in the model.
+    pub total_entropy: f64,
+    /// How the counts were quantized, if they were.
+    pub quantization: Option<Quantization>,
+    /// How the ngrams were stepped through the corpus.
+    pub stride: Stride,
+    /// How the corpus was read and cleaned, unless the model predates recording it (format
+    /// version 4) or was merged with one which does.
+    pub corpus_options: Option<CorpusOptions>,
+}
+
+/// The options a model's corpus was read and cleaned with, besides `ngram_length` and
+/// `min_word_length`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CorpusOptions {
+    pub apostrophes: ApostrophePolicy,
+    pub digits: DigitPolicy,
+    pub spell_numbers: bool,
+    pub script: Option<Script>,
+    pub preserve_case: bool,
+    pub dedupe_lines: bool,
+    pub dedupe_sentences: bool,
+    /// The characters words containing them were dropped for, lowercase and sorted.
+    pub exclude_chars: String,
+    /// The encoding the corpus was read with, or `None` if it was detected for each file.
+    pub encoding: Option<Encoding>,
+}
+
+/// How a model's transition counts were quantized by `Model::quantize`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantization {
+    /// Counts are stored as one of `2^bits - 1` levels.
+    pub bits: u32,
+    /// The largest count when quantizing, which sets the scale of the levels.
+    pub max_count: u64,
+    /// The largest change quantizing made to the transition entropy of any node, in bits.
+    ///
+    /// Each character of a passphrase is one transition, so a passphrase's reported entropy is
+    /// off by at most this much per character (not counting the choice of starting ngram).
+    pub max_entropy_error: f64,
+}
+
+impl Quantization {
+    fn levels(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    /// Small counts are stored exactly, larger ones on a logarithmic scale from 1 to `max_count`.
+    fn is_exact(&self) -> bool {
+        self.max_count <= self.levels()
+    }
+
+    fn encode(&self, count: u64) -> u8 {
+        if self.is_exact() {
+            return count as u8;
+        }
+        let levels = self.levels();
+        let position = (count as f64).ln() / (self.max_count as f64).ln();
+        let code = 1 + (position * (levels - 1) as f64).round() as u64;
+        let code = code.clamp(1, levels) as u8;
+        // Rounding a decoded count can nudge it into a neighbouring level when re-encoding.
+        [code, code.saturating_sub(1), code.saturating_add(1)]
+            .into_iter()
+            .find(|&candidate| candidate > 0 && self.decode(candidate) == count)
+            .unwrap_or(code)
+    }
+
+    fn decode(&self, code: u8) -> u64 {
+        if self.is_exact() {
+            return code as u64;
+        }
+        let exponent = (code as f64 - 1.0) / (self.levels() - 1) as f64;
+        ((self.max_count as f64).powf(exponent).round() as u64).max(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusFile {
+    pub name: String,
+    /// Hex encoded SHA-256 digest of the file contents.
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    metadata: ModelMetadata,
+    counts: TransitionCounts,
+}
+
+impl Model {
+    pub(crate) fn new(
+        ngram_length: usize,
+        min_word_length: usize,
+        corpus_files: Vec<CorpusFile>,
+        counts: TransitionCounts,
+    ) -> Self {
+        let metadata = ModelMetadata {
+            format_version: FORMAT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            created: now(),
+            ngram_length,
+            min_word_length,
+            corpus_files,
+            total_entropy: counts.total_entropy(),
+            quantization: None,
+            stride: Stride::default(),
+            corpus_options: None,
+        };
+
+        Self { metadata, counts }
+    }
+
+    /// Record that the counts were collected with `stride`.
+    pub(crate) fn with_stride(mut self, stride: Stride) -> Self {
+        self.metadata.stride = stride;
+        self
+    }
+
+    /// Record that the corpus was read and cleaned with `corpus_options`.
+    pub(crate) fn with_corpus_options(mut self, corpus_options: CorpusOptions) -> Self {
+        self.metadata.corpus_options = Some(corpus_options);
+        self
+    }
+
+    /// Add the transition counts from `other` to this model.
+    ///
+    /// The corpus files of both models are recorded, and the version and creation time are
+    /// updated to reflect the merge. Models with different corpus options can't be merged, and
+    /// merging with a model without recorded corpus options leaves the result without them.
+    pub fn merge(&mut self, other: &Model) -> Result<(), ModelError> {
+        if self.metadata.quantization.is_some() || other.metadata.quantization.is_some() {
+            return Err(ModelError::Quantized);
+        }
+        if other.ngram_length() != self.ngram_length() {
+            return Err(ModelError::IncompatibleNgramLength(
+                self.ngram_length(),
+                other.ngram_length(),
+            ));
+        }
+        if other.metadata.stride != self.metadata.stride {
+            return Err(ModelError::IncompatibleStride);
+        }
+        match (
+            &self.metadata.corpus_options,
+            &other.metadata.corpus_options,
+        ) {
+            (Some(ours), Some(theirs)) if ours != theirs => {
+                return Err(ModelError::IncompatibleCorpusOptions)
+            }
+            (Some(_), None) => self.metadata.corpus_options = None,
+            _ => (),
+        }
+        self.counts.merge(&other.counts);
+        self.metadata
+            .corpus_files
+            .extend_from_slice(&other.metadata.corpus_files);
+        self.metadata.crate_version = env!("CARGO_PKG_VERSION").to_string();
+        self.metadata.created = now();
+        self.metadata.total_entropy = self.counts.total_entropy();
+
+        Ok(())
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
+    }
+
+    pub fn ngram_length(&self) -> usize {
+        self.metadata.ngram_length
+    }
+
+    /// The number of distinct ngrams in the model.
+    pub fn node_count(&self) -> usize {
+        self.counts.ngrams().len()
+    }
+
+    /// Round the transition counts to `bits` bits each so the model saves in less space, recording
+    /// how much this changes node entropies in the metadata.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `bits` is from 2 to 8.
+    pub fn quantize(&mut self, bits: u32) {
+        assert!(
+            (2..=8).contains(&bits),
+            "quantization bits must be from 2 to 8"
+        );
+        let max_count = (0..self.node_count())
+            .flat_map(|index| self.counts.transitions(index).map(|(_, count)| count))
+            .max()
+            .unwrap_or(1);
+        let entropies: Vec<_> = (0..self.node_count())
+            .map(|index| self.counts.node_entropy(index))
+            .collect();
+        let mut quantization = Quantization {
+            bits,
+            max_count,
+            max_entropy_error: 0.0,
+        };
+        self.counts
+            .map_counts(|count| quantization.decode(quantization.encode(count)));
+
+        quantization.max_entropy_error = entropies
+            .iter()
+            .enumerate()
+            .map(|(index, entropy)| (self.counts.node_entropy(index) - entropy).abs())
+            .fold(0.0, f64::max);
+        // Errors from quantizing an already quantized model add up.
+        if let Some(previous) = self.metadata.quantization {
+            quantization.max_entropy_error += previous.max_entropy_error;
+        }
+        self.metadata.quantization = Some(quantization);
+        self.metadata.total_entropy = self.counts.total_entropy();
+    }
+
+    /// Compare this model with `other`.
+    pub fn diff(&self, other: &Model) -> ModelDiff {
+        let shared_ngrams = self
+            .counts
+            .ngrams()
+            .iter()
+            .filter(|ngram| other.counts.index(ngram).is_some())
+            .count();
+        let vocabulary = self.node_count() + other.node_count() - shared_ngrams;
+
+        ModelDiff {
+            node_counts: (self.node_count(), other.node_count()),
+            total_entropies: (self.metadata.total_entropy, other.metadata.total_entropy),
+            shared_ngrams,
+            vocabulary,
+            kl_divergences: (
+                kl_divergence(&self.counts, &other.counts, vocabulary),
+                kl_divergence(&other.counts, &self.counts, vocabulary),
+            ),
+        }
+    }
+
+    /// Build a chain from the model, with the stride it was trained with whatever `options`
+    /// says.
+    pub(crate) fn into_chain(
+        self,
+        options: &ChainOptions,
+    ) -> Result<PassphraseMarkovChain, MarkovChainError> {
+        let options = ChainOptions {
+            stride: self.metadata.stride,
+            ..options.clone()
+        };
+        PassphraseMarkovChain::new(self.counts, &options)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ModelError> {
+        Self::read_compressed(BufReader::new(File::open(path)?))
+    }
+
+    /// Save the model compressed with zstd, or uncompressed if `zstd` isn't installed.
+    pub fn save(&self, path: &Path) -> Result<(), ModelError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let compressed =
+            compression::compress_writer(Compression::Zstd, &mut writer, |compressor| {
+                let mut compressor = BufWriter::new(compressor);
+                self.write(&mut compressor)?;
+                compressor.flush()
+            });
+        match compressed {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => self.write(&mut writer)?,
+            result => result?,
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Read a model which may be compressed in any format `compression` recognizes, streaming
+    /// it through the decompressor.
+    pub fn read_compressed(mut reader: impl Read + Send) -> Result<Self, ModelError> {
+        let mut head = vec![];
+        (&mut reader)
+            .take(MAGIC.len() as u64)
+            .read_to_end(&mut head)?;
+        let compression = Compression::detect(&head);
+        let mut reader = io::Cursor::new(head).chain(reader);
+        let Some(compression) = compression else {
+            return Self::read(reader);
+        };
+        compression::decompress_reader(compression, &mut reader, |model| {
+            let result = Self::read(&mut *model);
+            // Drain the rest so a corrupt model is reported as that, not as a broken pipe.
+            io::copy(model, &mut io::sink())?;
+            Ok(result)
+        })?
+    }
+
+    /// The size of the model written uncompressed, in bytes.
+    pub fn serialized_size(&self) -> u64 {
+        let mut counter = ByteCounter(0);
+        self.write(&mut counter).unwrap();
+
+        counter.0
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_varint(&mut writer, FORMAT_VERSION)?;
+        write_metadata(&mut writer, &self.metadata)?;
+
+        let ngrams = self.counts.ngrams();
+        write_varint(&mut writer, ngrams.len() as u64)?;
+        for ngram in ngrams {
+            write_string(&mut writer, ngram)?;
+        }
+        for index in 0..ngrams.len() {
+            let transitions: Vec<_> = self.counts.transitions(index).collect();
+            write_varint(&mut writer, transitions.len() as u64)?;
+            let mut previous = 0;
+            for (target, count) in transitions {
+                write_varint(&mut writer, (target - previous) as u64)?;
+                match &self.metadata.quantization {
+                    Some(quantization) => writer.write_all(&[quantization.encode(count)])?,
+                    None => write_varint(&mut writer, count)?,
+                }
+                previous = target;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(mut reader: impl Read) -> Result<Self, ModelError> {
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| ModelError::NotAModel)?;
+        if &magic != MAGIC {
+            return Err(ModelError::NotAModel);
+        }
+        let version = read_varint(&mut reader)?;
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(ModelError::UnsupportedVersion(version));
+        }
+        let metadata = read_metadata(&mut reader, version)?;
+
+        let ngram_count = read_varint(&mut reader)? as usize;
+        let mut counts = TransitionCounts::default();
+        for index in 0..ngram_count {
+            let ngram = read_string(&mut reader)?;
+            if counts.intern(&ngram) != index {
+                return Err(ModelError::Corrupt("duplicate ngram"));
+            }
+        }
+        for index in 0..ngram_count {
+            let transition_count = read_varint(&mut reader)?;
+            if transition_count == 0 {
+                return Err(ModelError::Corrupt("ngram without transitions"));
+            }
+            let mut target: usize = 0;
+            for _ in 0..transition_count {
+                target = usize::try_from(read_varint(&mut reader)?)
+                    .ok()
+                    .and_then(|delta| target.checked_add(delta))
+                    .ok_or(ModelError::Corrupt("invalid transition"))?;
+                let count = match &metadata.quantization {
+                    Some(quantization) => {
+                        let mut code = [0];
+                        reader.read_exact(&mut code)?;
+                        if u64::from(code[0]) > quantization.levels() {
+                            return Err(ModelError::Corrupt("invalid quantized count"));
+                        }
+                        quantization.decode(code[0])
+                    }
+                    None => read_varint(&mut reader)?,
+                };
+                if target >= ngram_count || count == 0 {
+                    return Err(ModelError::Corrupt("invalid transition"));
+                }
+                counts.add_by_index(index, target, count);
+            }
+        }
+
+        Ok(Self { metadata, counts })
+    }
+}
+
+/// A writer that only counts what's written to it.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn write_metadata(writer: &mut impl Write, metadata: &ModelMetadata) -> io::Result<()> {
+    write_string(writer, &metadata.crate_version)?;
+    write_varint(writer, metadata.created)?;
+    write_varint(writer, metadata.ngram_length as u64)?;
+    write_varint(writer, metadata.min_word_length as u64)?;
+    write_varint(writer, metadata.corpus_files.len() as u64)?;
+    for file in &metadata.corpus_files {
+        write_string(writer, &file.name)?;
+        write_string(writer, &file.sha256)?;
+    }
+    writer.write_all(&metadata.total_entropy.to_le_bytes())?;
+    match &metadata.quantization {
+        Some(quantization) => {
+            write_varint(writer, quantization.bits as u64)?;
+            write_varint(writer, quantization.max_count)?;
+            writer.write_all(&quantization.max_entropy_error.to_le_bytes())?;
+        }
+        None => write_varint(writer, 0)?,
+    }
+    write_varint(writer, metadata.stride.step as u64)?;
+    write_varint(writer, metadata.stride.reset_at_words as u64)?;
+    match &metadata.corpus_options {
+        Some(options) => {
+            write_varint(writer, 1)?;
+            write_string(writer, &options.apostrophes.to_string())?;
+            write_string(writer, &options.digits.to_string())?;
+            write_varint(writer, options.spell_numbers as u64)?;
+            write_string(
+                writer,
+                &options.script.map_or(String::new(), |s| s.to_string()),
+            )?;
+            write_varint(writer, options.preserve_case as u64)?;
+            write_varint(writer, options.dedupe_lines as u64)?;
+            write_varint(writer, options.dedupe_sentences as u64)?;
+            write_string(writer, &options.exclude_chars)?;
+            write_string(
+                writer,
+                &options.encoding.map_or(String::new(), |e| e.to_string()),
+            )
+        }
+        None => write_varint(writer, 0),
+    }
+}
+
+fn read_metadata(reader: &mut impl Read, version: u64) -> Result<ModelMetadata, ModelError> {
+    let crate_version = read_string(reader)?;
+    let created = read_varint(reader)?;
+    let ngram_length = read_varint(reader)? as usize;
+    let min_word_length = read_varint(reader)? as usize;
+    let file_count = read_varint(reader)?;
+    let mut corpus_files = vec![];
+    for _ in 0..file_count {
+        let name = read_string(reader)?;
+        let sha256 = read_string(reader)?;
+        corpus_files.push(CorpusFile { name, sha256 });
+    }
+    let mut entropy_bytes = [0; 8];
+    reader.read_exact(&mut entropy_bytes)?;
+    let quantization = match version {
+        1 => None,
+        _ => match read_varint(reader)? {
+            0 => None,
+            bits @ 2..=8 => {
+                let max_count = read_varint(reader)?;
+                let mut error_bytes = [0; 8];
+                reader.read_exact(&mut error_bytes)?;
+                Some(Quantization {
+                    bits: bits as u32,
+                    max_count,
+                    max_entropy_error: f64::from_le_bytes(error_bytes),
+                })
+            }
+            _ => return Err(ModelError::Corrupt("invalid quantization")),
+        },
+    };
+    let stride = match version {
+        1 | 2 => Stride::default(),
+        _ => {
+            let step = read_varint(reader)? as usize;
+            let reset_at_words = match read_varint(reader)? {
+                0 => false,
+                1 => true,
+                _ => return Err(ModelError::Corrupt("invalid stride")),
+            };
+            if step == 0 {
+                return Err(ModelError::Corrupt("invalid stride"));
+            }
+            Stride {
+                step,
+                reset_at_words,
+            }
+        }
+    };
+
+    let corpus_options = match version {
+        1..=3 => None,
+        _ => match read_bool(reader, "invalid corpus options")? {
+            false => None,
+            true => Some(read_corpus_options(reader)?),
+        },
+    };
+
+    Ok(ModelMetadata {
+        format_version: version,
+        crate_version,
+        created,
+        ngram_length,
+        min_word_length,
+        corpus_files,
+        total_entropy: f64::from_le_bytes(entropy_bytes),
+        quantization,
+        stride,
+        corpus_options,
+    })
+}
+
+fn read_corpus_options(reader: &mut impl Read) -> Result<CorpusOptions, ModelError> {
+    const INVALID: &str = "invalid corpus options";
+    fn parse<T: std::str::FromStr>(value: &str) -> Result<T, ModelError> {
+        value.parse().map_err(|_| ModelError::Corrupt(INVALID))
+    }
+    fn parse_optional<T: std::str::FromStr>(value: &str) -> Result<Option<T>, ModelError> {
+        match value {
+            "" => Ok(None),
+            value => parse(value).map(Some),
+        }
+    }
+
+    Ok(CorpusOptions {
+        apostrophes: parse(&read_string(reader)?)?,
+        digits: parse(&read_string(reader)?)?,
+        spell_numbers: read_bool(reader, INVALID)?,
+        script: parse_optional(&read_string(reader)?)?,
+        preserve_case: read_bool(reader, INVALID)?,
+        dedupe_lines: read_bool(reader, INVALID)?,
+        dedupe_sentences: read_bool(reader, INVALID)?,
+        exclude_chars: read_string(reader)?,
+        encoding: parse_optional(&read_string(reader)?)?,
+    })
+}
+
+/// Read a flag written as a varint, which is `reason` corrupt if it's neither 0 nor 1.
+fn read_bool(reader: &mut impl Read, reason: &'static str) -> Result<bool, ModelError> {
+    match read_varint(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ModelError::Corrupt(reason)),
+    }
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    write_varint(writer, value.len() as u64)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, ModelError> {
+    let length = read_varint(reader)?;
+    let mut bytes = vec![];
+    reader.take(length).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != length {
+        return Err(ModelError::Corrupt("unexpected end of file"));
+    }
+
+    String::from_utf8(bytes).map_err(|_| ModelError::Corrupt("invalid string"))
+}
+
+/// How two models differ, as computed by `Model::diff`.
+///
+/// Pairs hold the value for the first model and then the second.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelDiff {
+    pub node_counts: (usize, usize),
+    pub total_entropies: (f64, f64),
+    /// The number of ngrams in both models.
+    pub shared_ngrams: usize,
+    /// The number of ngrams in either model.
+    pub vocabulary: usize,
+    /// The Kullback-Leibler divergence in bits of each model's ngram frequencies from the other's.
+    ///
+    /// Both distributions are Laplace smoothed over the combined vocabulary, so the divergence is
+    /// finite even when the models have ngrams the other lacks.
+    pub kl_divergences: (f64, f64),
+}
+
+impl ModelDiff {
+    /// The Jaccard similarity of the ngram vocabularies, from 0 (disjoint) to 1 (identical).
+    pub fn vocabulary_overlap(&self) -> f64 {
+        if self.vocabulary == 0 {
+            1.0
+        } else {
+            self.shared_ngrams as f64 / self.vocabulary as f64
+        }
+    }
+}
+
+/// The Laplace smoothed KL divergence D(p || q) in bits of the ngram frequencies of `p` from those
+/// of `q`, where there are `vocabulary` distinct ngrams between them.
+fn kl_divergence(p: &TransitionCounts, q: &TransitionCounts, vocabulary: usize) -> f64 {
+    let total = |counts: &TransitionCounts| -> f64 {
+        (0..counts.ngrams().len())
+            .map(|index| counts.occurrences(index) as f64)
+            .sum::<f64>()
+            + vocabulary as f64
+    };
+    let (p_total, q_total) = (total(p), total(q));
+    let q_prob = |ngram: &str| {
+        let occurrences = q.index(ngram).map_or(0, |index| q.occurrences(index));
+        (occurrences as f64 + 1.0) / q_total
+    };
+
+    // Sum over the ngrams of `p`, then over those only in `q`, where `p` has just the smoothing.
+    let mut divergence = 0.0;
+    for (index, ngram) in p.ngrams().iter().enumerate() {
+        let p_prob = (p.occurrences(index) as f64 + 1.0) / p_total;
+        divergence += p_prob * (p_prob / q_prob(ngram)).log2();
+    }
+    let p_prob = 1.0 / p_total;
+    for (index, ngram) in q.ngrams().iter().enumerate() {
+        if p.index(ngram).is_none() {
+            let q_prob = (q.occurrences(index) as f64 + 1.0) / q_total;
+            divergence += p_prob * (p_prob / q_prob).log2();
+        }
+    }
+
+    divergence
+}
+
+fn write_varint(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<u64, ModelError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ModelError::Corrupt("varint too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_model() -> Model {
+        let ngrams = [" ti", "tic", "ic ", "c t", " to", "toc", "oc ", "c t"];
+        let corpus_files = vec![CorpusFile {
+            name: "tictoc.txt".to_string(),
+            sha256: crate::sha256::hex_digest(b"tic toc"),
+        }];
+        Model::new(
+            3,
+            3,
+            corpus_files,
+            TransitionCounts::from_ngrams(ngrams.into_iter()),
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let model = test_model();
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        let loaded = Model::read(bytes.as_slice()).unwrap();
+        assert_eq!(loaded, model);
+        assert_eq!(loaded.ngram_length(), 3);
+        assert_eq!(loaded.node_count(), 7);
+        assert_eq!(loaded.metadata().total_entropy, 1.0);
+        assert_eq!(loaded.metadata().corpus_files[0].name, "tictoc.txt");
+
+        let stride = Stride {
+            step: 2,
+            reset_at_words: true,
+        };
+        let model = test_model().with_stride(stride);
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        assert_eq!(
+            Model::read(bytes.as_slice()).unwrap().metadata().stride,
+            stride
+        );
+
+        let corpus_options = CorpusOptions {
+            apostrophes: ApostrophePolicy::Strip,
+            digits: DigitPolicy::Trailing,
+            script: Some(Script::Greek),
+            dedupe_lines: true,
+            exclude_chars: "lo".to_string(),
+            encoding: Some(Encoding::Windows1252),
+            ..Default::default()
+        };
+        let model = test_model().with_corpus_options(corpus_options.clone());
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        let loaded = Model::read(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.metadata().corpus_options, Some(corpus_options));
+    }
+
+    #[test]
+    fn test_save_compressed() {
+        let model = test_model();
+        let mut path = std::env::temp_dir();
+        path.push(format!("markovpass-model-test-{}.mpm", std::process::id()));
+        model.save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let loaded = Model::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), model);
+        if std::process::Command::new("zstd")
+            .arg("--version")
+            .output()
+            .is_ok()
+        {
+            assert_eq!(Compression::detect(&bytes), Some(Compression::Zstd));
+            assert!(Model::read_compressed(&bytes[..bytes.len() - 4]).is_err());
+        }
+
+        // Uncompressed models still load.
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        assert_eq!(model.serialized_size(), bytes.len() as u64);
+        assert_eq!(Model::read_compressed(bytes.as_slice()).unwrap(), model);
+        assert!(matches!(
+            Model::read_compressed("not a model".as_bytes()),
+            Err(ModelError::NotAModel)
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut model = test_model();
+        let ngrams = [" ti", "tia", "ia ", "a t"];
+        let other = Model::new(
+            3,
+            3,
+            vec![],
+            TransitionCounts::from_ngrams(ngrams.into_iter()),
+        );
+        model.merge(&other).unwrap();
+        assert_eq!(model.node_count(), 10);
+        assert_eq!(model.metadata().corpus_files.len(), 1);
+        assert!(model.metadata().total_entropy > 1.0);
+
+        let other = Model::new(4, 3, vec![], TransitionCounts::default());
+        assert!(matches!(
+            model.merge(&other),
+            Err(ModelError::IncompatibleNgramLength(3, 4))
+        ));
+
+        let stride = Stride {
+            step: 2,
+            reset_at_words: true,
+        };
+        let other = Model::new(3, 3, vec![], TransitionCounts::default()).with_stride(stride);
+        assert!(matches!(
+            model.merge(&other),
+            Err(ModelError::IncompatibleStride)
+        ));
+
+        let cleaned = |preserve_case| {
+            test_model().with_corpus_options(CorpusOptions {
+                preserve_case,
+                ..Default::default()
+            })
+        };
+        let mut model = cleaned(false);
+        assert!(matches!(
+            model.merge(&cleaned(true)),
+            Err(ModelError::IncompatibleCorpusOptions)
+        ));
+        model.merge(&cleaned(false)).unwrap();
+        assert!(model.metadata().corpus_options.is_some());
+        model.merge(&test_model()).unwrap();
+        assert_eq!(model.metadata().corpus_options, None);
+    }
+
+    #[test]
+    fn test_quantize() {
+        // Counts of 1, 2, ... 1000 between the same few ngrams.
+        let mut counts = TransitionCounts::default();
+        let ngrams = [" ab", "abc", "bcd", "cd "];
+        for (i, from) in ngrams.iter().enumerate() {
+            for (j, to) in ngrams.iter().enumerate() {
+                counts.add(from, to, [1, 2, 37, 1000][(i + j) % 4]);
+            }
+        }
+        let mut model = Model::new(3, 3, vec![], counts);
+        let total_entropy = model.metadata().total_entropy;
+        model.quantize(4);
+        let quantization = model.metadata().quantization.unwrap();
+        assert_eq!(quantization.max_count, 1000);
+        assert!(quantization.max_entropy_error > 0.0);
+        assert!(
+            (model.metadata().total_entropy - total_entropy).abs()
+                <= 4.0 * quantization.max_entropy_error
+        );
+
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        assert_eq!(Model::read(bytes.as_slice()).unwrap(), model);
+        assert!(matches!(
+            model.clone().merge(&model),
+            Err(ModelError::Quantized)
+        ));
+
+        // Small counts are kept exactly.
+        let mut model = test_model();
+        model.quantize(8);
+        assert_eq!(
+            model.metadata().quantization.unwrap().max_entropy_error,
+            0.0
+        );
+        assert_eq!(model.counts, test_model().counts);
+    }
+
+    #[test]
+    fn test_read_version_1() {
+        let model = test_model();
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        // Version 1 files are the same but for the version and the quantization, stride and
+        // corpus options fields, which come right after the total entropy.
+        let position = bytes
+            .windows(8)
+            .position(|window| window == model.metadata().total_entropy.to_le_bytes())
+            .unwrap();
+        bytes.drain(position + 8..position + 12);
+        bytes[MAGIC.len()] = 1;
+        let loaded = Model::read(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.metadata().format_version, 1);
+        assert_eq!(loaded.counts, model.counts);
+        assert_eq!(
+            loaded.metadata().corpus_files,
+            model.metadata().corpus_files
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let model = test_model();
+        let diff = model.diff(&model);
+        assert_eq!(diff.node_counts, (7, 7));
+        assert_eq!(diff.shared_ngrams, 7);
+        assert_eq!(diff.vocabulary_overlap(), 1.0);
+        assert_eq!(diff.kl_divergences, (0.0, 0.0));
+
+        let ngrams = [" ti", "tia", "ia ", "a t"];
+        let other = Model::new(
+            3,
+            3,
+            vec![],
+            TransitionCounts::from_ngrams(ngrams.into_iter()),
+        );
+        let diff = model.diff(&other);
+        assert_eq!(diff.node_counts, (7, 4));
+        assert_eq!(diff.shared_ngrams, 1);
+        assert_eq!(diff.vocabulary, 10);
+        assert!(diff.kl_divergences.0 > 0.0 && diff.kl_divergences.1 > 0.0);
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u64::MAX] {
+            let mut bytes = vec![];
+            write_varint(&mut bytes, value).unwrap();
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_read_errors() {
+        assert!(matches!(
+            Model::read("not a model".as_bytes()),
+            Err(ModelError::NotAModel)
+        ));
+
+        let mut bytes = MAGIC.to_vec();
+        write_varint(&mut bytes, FORMAT_VERSION + 1).unwrap();
+        let error = Model::read(bytes.as_slice()).unwrap_err();
+        assert!(matches!(error, ModelError::UnsupportedVersion(_)));
+        assert!(error.to_string().contains("newer than this markovpass"));
+
+        let mut bytes = MAGIC.to_vec();
+        write_varint(&mut bytes, 0).unwrap();
+        assert!(matches!(
+            Model::read(bytes.as_slice()),
+            Err(ModelError::UnsupportedVersion(0))
+        ));
+
+        let mut bytes = vec![];
+        test_model().write(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            Model::read(bytes.as_slice()),
+            Err(ModelError::Corrupt(_))
+        ));
+
+        // Transition deltas adding up past the largest index.
+        let mut bytes = MAGIC.to_vec();
+        write_varint(&mut bytes, FORMAT_VERSION).unwrap();
+        write_metadata(&mut bytes, test_model().metadata()).unwrap();
+        write_varint(&mut bytes, 1).unwrap();
+        write_string(&mut bytes, "tic").unwrap();
+        for value in [2, 0, 1, u64::MAX, 1] {
+            write_varint(&mut bytes, value).unwrap();
+        }
+        assert!(matches!(
+            Model::read(bytes.as_slice()),
+            Err(ModelError::Corrupt("invalid transition"))
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+//! Environment aware rendering of terminal output: whether to use colors, and how wide to wrap.
+//!
+//! Colors follow the `NO_COLOR` (<https://no-color.org>) and `CLICOLOR`/`CLICOLOR_FORCE`
+//! conventions, and the width comes from `COLUMNS` or the terminal itself.
+
+use std::fmt;
+
+/// The width to wrap to when it can't be found any other way.
+pub const DEFAULT_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Yellow,
+}
+
+impl Color {
+    fn code(self) -> u8 {
+        match self {
+            Self::Red => 31,
+            Self::Yellow => 33,
+        }
+    }
+}
+
+/// How to render output to one stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Renderer {
+    pub color: bool,
+    pub width: usize,
+}
+
+impl Renderer {
+    /// A renderer for a stream which is a terminal or not, reading settings from `var`
+    /// (normally `std::env::var`) before asking the terminal for its width.
+    pub fn new(is_terminal: bool, var: impl Fn(&str) -> Option<String>) -> Self {
+        let width = var("COLUMNS")
+            .and_then(|columns| columns.trim().parse().ok())
+            .filter(|&width| width > 0)
+            .or_else(|| is_terminal.then(terminal_width).flatten())
+            .unwrap_or(DEFAULT_WIDTH);
+
+        Self {
+            color: use_color(is_terminal, &var),
+            width,
+        }
+    }
+
+    /// A renderer for standard error, configured from the environment.
+    pub fn stderr() -> Self {
+        let is_terminal = std::io::IsTerminal::is_terminal(&std::io::stderr());
+        Self::new(is_terminal, |name| std::env::var(name).ok())
+    }
+
+    /// `text` in `color`, if colors are enabled.
+    pub fn paint<'a>(&self, text: &'a str, color: Color) -> Painted<'a> {
+        Painted {
+            text,
+            color: self.color.then_some(color),
+        }
+    }
+
+    /// `text` wrapped to the renderer's width.
+    pub fn wrap(&self, text: &str) -> String {
+        wrap(text, self.width)
+    }
+}
+
+/// Text which may be displayed in color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Painted<'a> {
+    text: &'a str,
+    color: Option<Color>,
+}
+
+impl fmt::Display for Painted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.color {
+            Some(color) => write!(f, "\x1b[{}m{}\x1b[0m", color.code(), self.text),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// Whether to use colors on a stream. `NO_COLOR` disables colors and `CLICOLOR_FORCE` enables
+/// them even when not writing to a terminal. Otherwise terminals get colors unless `CLICOLOR`
+/// is 0 or `TERM` is "dumb".
+pub fn use_color(is_terminal: bool, var: impl Fn(&str) -> Option<String>) -> bool {
+    let set = |name: &str| var(name).is_some_and(|value| !value.is_empty());
+    if set("NO_COLOR") {
+        return false;
+    }
+    if set("CLICOLOR_FORCE") && var("CLICOLOR_FORCE").as_deref() != Some("0") {
+        return true;
+    }
+
+    is_terminal && var("CLICOLOR").as_deref() != Some("0") && var("TERM").as_deref() != Some("dumb")
+}
+
+/// Wrap `text` at spaces so no line is longer than `width` characters, except for words which
+/// are too long by themselves. Existing line breaks are kept.
+pub fn wrap(text: &str, width: usize) -> String {
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut length = 0;
+        for word in paragraph.split(' ').filter(|word| !word.is_empty()) {
+            let word_length = word.chars().count();
+            if length > 0 && length + 1 + word_length > width {
+                lines.push(std::mem::take(&mut line));
+                length = 0;
+            }
+            if length > 0 {
+                line.push(' ');
+                length += 1;
+            }
+            line.push_str(word);
+            length += word_length;
+        }
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+/// The width of the terminal on standard error, if it is one.
+#[cfg(unix)]
+pub fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+
+    (size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+#[cfg(not(unix))]
+pub fn terminal_width() -> Option<usize> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn test_use_color() {
+        assert!(use_color(true, env(&[])));
+        assert!(!use_color(false, env(&[])));
+        assert!(!use_color(true, env(&[("NO_COLOR", "1")])));
+        assert!(use_color(true, env(&[("NO_COLOR", "")])));
+        assert!(use_color(false, env(&[("CLICOLOR_FORCE", "1")])));
+        assert!(!use_color(false, env(&[("CLICOLOR_FORCE", "0")])));
+        assert!(!use_color(
+            true,
+            env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")])
+        ));
+        assert!(!use_color(true, env(&[("CLICOLOR", "0")])));
+        assert!(!use_color(true, env(&[("TERM", "dumb")])));
+    }
+
+    #[test]
+    fn test_renderer() {
+        let renderer = Renderer::new(false, env(&[("COLUMNS", "40")]));
+        assert_eq!(
+            renderer,
+            Renderer {
+                color: false,
+                width: 40
+            }
+        );
+        assert_eq!(renderer.paint("hi", Color::Red).to_string(), "hi");
+        let renderer = Renderer::new(false, env(&[("CLICOLOR_FORCE", "1"), ("COLUMNS", "x")]));
+        assert_eq!(renderer.width, DEFAULT_WIDTH);
+        assert_eq!(
+            renderer.paint("hi", Color::Red).to_string(),
+            "\x1b[31mhi\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("one two three four", 9), "one two\nthree\nfour");
+        assert_eq!(wrap("one two\nthree", 80), "one two\nthree");
+        assert_eq!(wrap("a verylongword b", 4), "a\nverylongword\nb");
+        assert_eq!(wrap("", 10), "");
+    }
+}
@@ -0,0 +1,60 @@
+//! Phonetic read-outs for dictating passphrases.
+//!
+//! Each character is spelled with the NATO phonetic alphabet, with capitals, digits and
+//! punctuation named so nothing is lost over a phone line.
+
+const NATO: [&str; 26] = [
+    "alfa", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliett",
+    "kilo", "lima", "mike", "november", "oscar", "papa", "quebec", "romeo", "sierra", "tango",
+    "uniform", "victor", "whiskey", "x-ray", "yankee", "zulu",
+];
+
+const DIGITS: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+/// Spell out `passphrase` one character at a time, e.g. "alfa, capital bravo, space, one".
+pub fn nato_readout(passphrase: &str) -> String {
+    let words: Vec<String> = passphrase.chars().map(nato_word).collect();
+    words.join(", ")
+}
+
+fn nato_word(c: char) -> String {
+    if c.is_ascii_lowercase() {
+        return NATO[(c as u8 - b'a') as usize].to_string();
+    }
+    if c.is_ascii_uppercase() {
+        return format!("capital {}", NATO[(c as u8 - b'A') as usize]);
+    }
+    if let Some(digit) = c.to_digit(10) {
+        return DIGITS[digit as usize].to_string();
+    }
+    let name = match c {
+        ' ' => "space",
+        '\'' => "apostrophe",
+        '-' => "dash",
+        '_' => "underscore",
+        '.' => "period",
+        ',' => "comma",
+        '/' => "slash",
+        _ if c.is_uppercase() => return format!("capital \"{}\"", c),
+        _ => return format!("\"{}\"", c),
+    };
+
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nato_readout() {
+        assert_eq!(
+            nato_readout("Don't x-9"),
+            "capital delta, oscar, november, apostrophe, tango, space, x-ray, dash, nine"
+        );
+        assert_eq!(nato_readout("éÉ"), "\"é\", capital \"É\"");
+        assert_eq!(nato_readout(""), "");
+    }
+}
@@ -0,0 +1,349 @@
+//! Site specific passphrase rules.
+//!
+//! Policies are read from a small subset of TOML: one `key = value` per line, where values are
+//! integers, strings or arrays of strings, and `#` starts a comment. For example:
+//!
+//! ```toml
+//! min_length = 20
+//! max_length = 64
+//! required_classes = ["lowercase", "space"]
+//! banned_substrings = ["password"]
+//! ```
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A kind of character a policy can require.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Space,
+    Symbol,
+}
+
+impl CharClass {
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            Self::Lowercase => c.is_lowercase(),
+            Self::Uppercase => c.is_uppercase(),
+            Self::Digit => c.is_numeric(),
+            Self::Space => c.is_whitespace(),
+            Self::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Lowercase => write!(f, "lowercase"),
+            Self::Uppercase => write!(f, "uppercase"),
+            Self::Digit => write!(f, "digit"),
+            Self::Space => write!(f, "space"),
+            Self::Symbol => write!(f, "symbol"),
+        }
+    }
+}
+
+impl FromStr for CharClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lowercase" => Ok(Self::Lowercase),
+            "uppercase" => Ok(Self::Uppercase),
+            "digit" => Ok(Self::Digit),
+            "space" => Ok(Self::Space),
+            "symbol" => Ok(Self::Symbol),
+            _ => Err(format!("unknown character class '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// The fewest characters a passphrase may have.
+    pub min_length: Option<usize>,
+    /// The most characters a passphrase may have.
+    pub max_length: Option<usize>,
+    /// Classes of character which must each appear at least once.
+    pub required_classes: Vec<CharClass>,
+    /// Strings which must not appear anywhere in a passphrase (ignoring case).
+    pub banned_substrings: Vec<String>,
+}
+
+/// A way in which a passphrase breaks a policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    TooShort(usize, usize),
+    TooLong(usize, usize),
+    MissingClass(CharClass),
+    BannedSubstring(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooShort(length, min) => {
+                write!(
+                    f,
+                    "{} characters is shorter than the minimum {}",
+                    length, min
+                )
+            }
+            Self::TooLong(length, max) => {
+                write!(
+                    f,
+                    "{} characters is longer than the maximum {}",
+                    length, max
+                )
+            }
+            Self::MissingClass(class) => write!(f, "no {} characters", class),
+            Self::BannedSubstring(banned) => write!(f, "contains banned '{}'", banned),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PolicyError {
+    Io(std::io::Error),
+    Parse(usize, String),
+}
+
+impl std::error::Error for PolicyError {}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to read policy: {}", error),
+            Self::Parse(line, message) => {
+                write!(f, "Invalid policy on line {}: {}.", line, message)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for PolicyError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self, PolicyError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Every way in which `passphrase` breaks this policy.
+    pub fn violations(&self, passphrase: &str) -> Vec<Violation> {
+        let mut violations = vec![];
+        let length = passphrase.chars().count();
+        if let Some(min) = self.min_length.filter(|&min| length < min) {
+            violations.push(Violation::TooShort(length, min));
+        }
+        if let Some(max) = self.max_length.filter(|&max| length > max) {
+            violations.push(Violation::TooLong(length, max));
+        }
+        for &class in &self.required_classes {
+            if !passphrase.chars().any(|c| class.contains(c)) {
+                violations.push(Violation::MissingClass(class));
+            }
+        }
+        let lowercase = passphrase.to_lowercase();
+        for banned in &self.banned_substrings {
+            if lowercase.contains(&banned.to_lowercase()) {
+                violations.push(Violation::BannedSubstring(banned.clone()));
+            }
+        }
+
+        violations
+    }
+
+    pub fn allows(&self, passphrase: &str) -> bool {
+        self.violations(passphrase).is_empty()
+    }
+}
+
+impl FromStr for Policy {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = Policy::default();
+        for (i, line) in s.lines().enumerate() {
+            let error = |message: String| PolicyError::Parse(i + 1, message);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected 'key = value'".to_string()))?;
+            let value = Value::parse(value.trim()).map_err(error)?;
+            match key.trim() {
+                "min_length" => policy.min_length = Some(value.integer().map_err(error)?),
+                "max_length" => policy.max_length = Some(value.integer().map_err(error)?),
+                "required_classes" => {
+                    policy.required_classes = value
+                        .strings()
+                        .map_err(error)?
+                        .iter()
+                        .map(|class| class.parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(error)?
+                }
+                "banned_substrings" => policy.banned_substrings = value.strings().map_err(error)?,
+                key => return Err(error(format!("unknown key '{}'", key))),
+            }
+        }
+
+        Ok(policy)
+    }
+}
+
+/// `line` without any trailing comment, leaving '#'s inside strings alone.
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+enum Value {
+    Integer(usize),
+    Strings(Vec<String>),
+    String(String),
+}
+
+impl Value {
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Some(items) = s.strip_prefix('[') {
+            let items = items
+                .strip_suffix(']')
+                .ok_or_else(|| "unterminated array".to_string())?;
+            let strings = items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(parse_string)
+                .collect::<Result<_, _>>()?;
+            Ok(Self::Strings(strings))
+        } else if s.starts_with('"') {
+            Ok(Self::String(parse_string(s)?))
+        } else {
+            s.parse()
+                .map(Self::Integer)
+                .map_err(|_| format!("invalid value '{}'", s))
+        }
+    }
+
+    fn integer(self) -> Result<usize, String> {
+        match self {
+            Self::Integer(n) => Ok(n),
+            _ => Err("expected an integer".to_string()),
+        }
+    }
+
+    fn strings(self) -> Result<Vec<String>, String> {
+        match self {
+            Self::Strings(strings) => Ok(strings),
+            Self::String(string) => Ok(vec![string]),
+            Self::Integer(_) => Err("expected an array of strings".to_string()),
+        }
+    }
+}
+
+/// Parse a basic double quoted string. Escapes other than `\"` and `\\` aren't supported.
+pub(crate) fn parse_string(s: &str) -> Result<String, String> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, not '{}'", s))?;
+    let mut string = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('"' | '\\')) => string.push(c),
+                _ => return Err(format!("unsupported escape in '{}'", s)),
+            },
+            c => string.push(c),
+        }
+    }
+
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let policy: Policy = "\
+            # Example site rules\n\
+            min_length = 12\n\
+            max_length = 40 # Some sites truncate\n\
+            required_classes = [\"lowercase\", \"space\"]\n\
+            banned_substrings = [\"pass#word\", \"qwerty\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            policy,
+            Policy {
+                min_length: Some(12),
+                max_length: Some(40),
+                required_classes: vec![CharClass::Lowercase, CharClass::Space],
+                banned_substrings: vec!["pass#word".to_string(), "qwerty".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "min_length 12".parse::<Policy>(),
+            Err(PolicyError::Parse(1, _))
+        ));
+        assert!(matches!(
+            "\nmin_length = \"12\"".parse::<Policy>(),
+            Err(PolicyError::Parse(2, _))
+        ));
+        assert!(matches!(
+            "required_classes = [\"emoji\"]".parse::<Policy>(),
+            Err(PolicyError::Parse(1, _))
+        ));
+        assert!(matches!(
+            "colour = 3".parse::<Policy>(),
+            Err(PolicyError::Parse(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_violations() {
+        let policy = Policy {
+            min_length: Some(10),
+            max_length: Some(20),
+            required_classes: vec![CharClass::Space, CharClass::Digit],
+            banned_substrings: vec!["Cat".to_string()],
+        };
+        assert_eq!(
+            policy.violations("concatenation"),
+            vec![
+                Violation::MissingClass(CharClass::Space),
+                Violation::MissingClass(CharClass::Digit),
+                Violation::BannedSubstring("Cat".to_string()),
+            ]
+        );
+        assert_eq!(policy.violations("a 1"), vec![Violation::TooShort(3, 10)]);
+        assert!(policy.allows("twenty 20 dogs"));
+    }
+}
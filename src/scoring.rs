@@ -0,0 +1,214 @@
+//! Scoring words for best-of-N selection of passphrases.
+//!
+//! With `GenPassphraseOptions::best_of` set, several passphrases are generated and the one with
+//! the best mean word score is kept. Scores should be between 0 and 1, higher being better, so
+//! scorers can be combined with `Weighted`.
+
+use crate::generator::VOWELS;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub trait WordScorer: fmt::Debug + Send + Sync {
+    /// How good `word` is, from 0 to 1.
+    fn score(&self, word: &str) -> f64;
+
+    /// How good a passphrase made of `words` is: by default the mean of the word scores.
+    fn score_passphrase(&self, words: &[String]) -> f64 {
+        if words.is_empty() {
+            return 0.0;
+        }
+        words.iter().map(|word| self.score(word)).sum::<f64>() / words.len() as f64
+    }
+}
+
+/// Prefers words without long runs of consonants or vowels.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pronounceability;
+
+impl WordScorer for Pronounceability {
+    fn score(&self, word: &str) -> f64 {
+        let letters: Vec<bool> = word
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .map(|c| c.to_lowercase().all(|lower| VOWELS.contains(lower)))
+            .collect();
+        if letters.is_empty() {
+            return 0.0;
+        }
+        // Count letters past the second in each run of consonants or of vowels.
+        let mut awkward = 0;
+        let mut run = 0;
+        for (i, &is_vowel) in letters.iter().enumerate() {
+            run = if i > 0 && letters[i - 1] == is_vowel {
+                run + 1
+            } else {
+                1
+            };
+            if run > 2 {
+                awkward += 1;
+            }
+        }
+
+        1.0 - awkward as f64 / letters.len() as f64
+    }
+}
+
+/// Prefers words typed near the home row of a QWERTY keyboard, alternating hands.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TypingEffort;
+
+impl TypingEffort {
+    /// The effort of reaching `c` from the home row, and which hand types it.
+    fn key(c: char) -> (f64, Option<bool>) {
+        const ROWS: [(&str, f64); 3] = [("asdfghjkl", 0.0), ("qwertyuiop", 1.0), ("zxcvbnm", 1.5)];
+        const LEFT: &str = "qwertasdfgzxcvb";
+        for (row, effort) in ROWS {
+            if row.contains(c) {
+                return (effort, Some(LEFT.contains(c)));
+            }
+        }
+
+        (2.0, None)
+    }
+}
+
+impl WordScorer for TypingEffort {
+    fn score(&self, word: &str) -> f64 {
+        let keys: Vec<(f64, Option<bool>)> = word
+            .chars()
+            .flat_map(char::to_lowercase)
+            .map(Self::key)
+            .collect();
+        if keys.is_empty() {
+            return 0.0;
+        }
+        let mut effort: f64 = keys.iter().map(|&(effort, _)| effort).sum();
+        // Typing consecutive keys with the same hand is slower than alternating.
+        effort += keys
+            .windows(2)
+            .filter(|pair| pair[0].1.is_some() && pair[0].1 == pair[1].1)
+            .count() as f64
+            * 0.5;
+
+        1.0 - (effort / keys.len() as f64 / 2.5).min(1.0)
+    }
+}
+
+/// Prefers shorter words, scoring words of `max` characters or more 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Brevity {
+    pub max: usize,
+}
+
+impl Default for Brevity {
+    fn default() -> Self {
+        Self { max: 16 }
+    }
+}
+
+impl WordScorer for Brevity {
+    fn score(&self, word: &str) -> f64 {
+        1.0 - (word.chars().count() as f64 / self.max as f64).min(1.0)
+    }
+}
+
+/// A weighted mean of other scorers.
+#[derive(Debug, Clone, Default)]
+pub struct Weighted(pub Vec<(f64, Arc<dyn WordScorer>)>);
+
+impl WordScorer for Weighted {
+    fn score(&self, word: &str) -> f64 {
+        let total: f64 = self.0.iter().map(|&(weight, _)| weight).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.0
+            .iter()
+            .map(|(weight, scorer)| weight * scorer.score(word))
+            .sum::<f64>()
+            / total
+    }
+}
+
+/// The built-in scorers, by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScorerKind {
+    Pronounceable,
+    Typing,
+    Short,
+}
+
+impl ScorerKind {
+    pub fn scorer(self) -> Arc<dyn WordScorer> {
+        match self {
+            Self::Pronounceable => Arc::new(Pronounceability),
+            Self::Typing => Arc::new(TypingEffort),
+            Self::Short => Arc::new(Brevity::default()),
+        }
+    }
+}
+
+impl fmt::Display for ScorerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pronounceable => write!(f, "pronounceable"),
+            Self::Typing => write!(f, "typing"),
+            Self::Short => write!(f, "short"),
+        }
+    }
+}
+
+impl FromStr for ScorerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pronounceable" => Ok(Self::Pronounceable),
+            "typing" => Ok(Self::Typing),
+            "short" => Ok(Self::Short),
+            _ => Err(format!(
+                "Unknown scorer '{}'. Expected pronounceable, typing or short.",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pronounceability() {
+        assert_eq!(Pronounceability.score("banana"), 1.0);
+        // The "r" of "str" and the "t" and "h" of "ngth" are awkward.
+        assert_eq!(Pronounceability.score("strength"), 0.625);
+        assert!(Pronounceability.score("queueing") < 1.0);
+        assert_eq!(Pronounceability.score(""), 0.0);
+    }
+
+    #[test]
+    fn test_typing_effort() {
+        assert!(TypingEffort.score("flask") > TypingEffort.score("zebra"));
+        assert!(TypingEffort.score("sighs") > TypingEffort.score("sadder"));
+        assert!(TypingEffort.score("ßß") < TypingEffort.score("zebra"));
+        assert_eq!(TypingEffort.score(""), 0.0);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let scorer = Weighted(vec![
+            (3.0, Arc::new(Brevity { max: 10 }) as Arc<dyn WordScorer>),
+            (1.0, Arc::new(Pronounceability)),
+        ]);
+        // Brevity 0.5 and pronounceability 1.0.
+        assert_eq!(scorer.score("hello"), 0.625);
+        let words = ["hello".to_string(), "strength".to_string()];
+        assert_eq!(
+            Pronounceability.score_passphrase(&words),
+            (1.0 + 0.625) / 2.0
+        );
+        assert_eq!(Weighted::default().score("hello"), 0.0);
+    }
+}
@@ -0,0 +1,108 @@
+//! Classifying characters by writing system.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+impl Script {
+    /// The script of a letter, if it's one we know about.
+    pub fn of(c: char) -> Option<Script> {
+        if !c.is_alphabetic() {
+            return None;
+        }
+        match c as u32 {
+            0x0041..=0x024F | 0x1E00..=0x1EFF | 0x2C60..=0x2C7F | 0xA720..=0xA7FF => {
+                Some(Self::Latin)
+            }
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Some(Self::Greek),
+            0x0400..=0x052F | 0x1C80..=0x1C8F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => {
+                Some(Self::Cyrillic)
+            }
+            _ => None,
+        }
+    }
+
+    /// The basic lowercase letters of the script's alphabet (for Latin, just 'a' to 'z').
+    pub fn alphabet(self) -> &'static str {
+        match self {
+            Self::Latin => "abcdefghijklmnopqrstuvwxyz",
+            Self::Greek => "αβγδεζηθικλμνξοπρστυφχψω",
+            Self::Cyrillic => "абвгдежзийклмнопрстуфхцчшщъыьэюя",
+        }
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Latin => write!(f, "latin"),
+            Self::Greek => write!(f, "greek"),
+            Self::Cyrillic => write!(f, "cyrillic"),
+        }
+    }
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "latin" => Ok(Self::Latin),
+            "greek" => Ok(Self::Greek),
+            "cyrillic" => Ok(Self::Cyrillic),
+            _ => Err(format!("Unknown script '{}'.", s)),
+        }
+    }
+}
+
+/// Groups of characters that are easily mistaken for each other within a single script.
+const CONFUSABLE_GROUPS: [&str; 4] = ["0oO", "1lI|", "5sS", "2zZ"];
+
+/// Whether `text` contains characters which could be confused when reading it back.
+///
+/// That's the case if it mixes letters from different scripts (where lookalikes such as Latin 'a'
+/// and Cyrillic 'а' abound) or contains two different characters from a confusable group.
+pub fn has_homoglyphs(text: &str) -> bool {
+    let mut scripts = text.chars().filter_map(Script::of);
+    if let Some(first) = scripts.next() {
+        if scripts.any(|script| script != first) {
+            return true;
+        }
+    }
+
+    CONFUSABLE_GROUPS.iter().any(|group| {
+        let mut present = group.chars().filter(|&c| text.contains(c));
+        present.next().is_some() && present.next().is_some()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_of() {
+        assert_eq!(Script::of('a'), Some(Script::Latin));
+        assert_eq!(Script::of('é'), Some(Script::Latin));
+        assert_eq!(Script::of('α'), Some(Script::Greek));
+        assert_eq!(Script::of('а'), Some(Script::Cyrillic));
+        assert_eq!(Script::of('\''), None);
+        assert_eq!(Script::of('日'), None);
+    }
+
+    #[test]
+    fn test_has_homoglyphs() {
+        assert!(!has_homoglyphs("plain latin words"));
+        assert!(!has_homoglyphs("καλημέρα κόσμε"));
+        assert!(has_homoglyphs("pаssword"));
+        assert!(has_homoglyphs("hello w0rld"));
+        assert!(has_homoglyphs("l1ke"));
+        assert!(!has_homoglyphs("like 2 words"));
+    }
+}
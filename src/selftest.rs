@@ -0,0 +1,104 @@
+//! A deterministic end-to-end check of training and generation, for packagers.
+//!
+//! A seeded generator is run against a small embedded corpus and the output is compared with
+//! known good passphrases and entropies, so any change to cleaning, training, sampling or entropy
+//! calculation shows up as a mismatch.
+
+use crate::GenPassphraseOptions;
+use std::fmt;
+
+/// The opening of "Pride and Prejudice", which is in the public domain.
+const CORPUS: &str = "\
+It is a truth universally acknowledged, that a single man in possession of a good fortune, must be
+in want of a wife. However little known the feelings or views of such a man may be on his first
+entering a neighbourhood, this truth is so well fixed in the minds of the surrounding families,
+that he is considered the rightful property of some one or other of their daughters. \"My dear Mr.
+Bennet,\" said his lady to him one day, \"have you heard that Netherfield Park is let at last?\"
+Mr. Bennet replied that he had not. \"But it is,\" returned she; \"for Mrs. Long has just been here,
+and she told me all about it.\" Mr. Bennet made no answer. \"Do you not want to know who has taken
+it?\" cried his wife impatiently. \"You want to tell me, and I have no objection to hearing it.\"
+This was invitation enough.
+";
+
+const SEED: u64 = 1701;
+
+const EXPECTED: [(&str, f64); 3] = [
+    (
+        "want was taken cried his wife impatiently acknow who have you hearing",
+        35.21394869808772,
+    ),
+    (
+        "their day had not bennet last entering familied this",
+        31.821165716327037,
+    ),
+    (
+        "not wife however you hered that considere and she such may had",
+        30.98215448360224,
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelftestError {
+    pub expected: (String, f64),
+    pub actual: Option<(String, f64)>,
+}
+
+impl std::error::Error for SelftestError {}
+
+impl fmt::Display for SelftestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (expected, expected_entropy) = &self.expected;
+        match &self.actual {
+            Some((actual, actual_entropy)) => write!(
+                f,
+                "Self-test failed: expected '{}' <{}>, got '{}' <{}>.",
+                expected, expected_entropy, actual, actual_entropy
+            ),
+            None => write!(
+                f,
+                "Self-test failed: expected '{}' <{}>, got nothing.",
+                expected, expected_entropy
+            ),
+        }
+    }
+}
+
+/// Run the self-test, returning the passphrases checked.
+pub fn run() -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let options = GenPassphraseOptions {
+        number: EXPECTED.len(),
+        min_entropy: 30.0,
+        min_word_length: 3,
+        seed: Some(SEED),
+        ..Default::default()
+    };
+    let model = crate::train_from_reader("<selftest>".to_string(), CORPUS.as_bytes(), &options)?;
+    let chain = model.into_chain(&Default::default())?;
+    let passphrases = crate::gen_passphrases_from_chain(&chain, &options)?;
+
+    for (i, &(expected, expected_entropy)) in EXPECTED.iter().enumerate() {
+        let actual = passphrases.get(i);
+        let matches = actual.is_some_and(|(passphrase, entropy)| {
+            passphrase == expected && (entropy - expected_entropy).abs() < 1e-9
+        });
+        if !matches {
+            return Err(Box::new(SelftestError {
+                expected: (expected.to_string(), expected_entropy),
+                actual: actual.cloned(),
+            }));
+        }
+    }
+
+    Ok(passphrases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let passphrases = run().unwrap();
+        assert_eq!(passphrases.len(), EXPECTED.len());
+    }
+}
@@ -0,0 +1,40 @@
+//! Custom post-processing of generated passphrases.
+
+use std::error::Error;
+use std::fmt;
+
+/// A transform applied to each passphrase after its words are put together, and before policies
+/// are checked, so policies see the passphrase as it's finally output.
+pub trait OutputTransform: fmt::Debug + Send + Sync {
+    fn transform(&self, passphrase: &str) -> Result<String, Box<dyn Error>>;
+
+    /// How many bits of entropy the transform adds to a passphrase (or removes, if negative).
+    ///
+    /// Deterministic transforms like appending a fixed tag add nothing, which is the default.
+    /// Transforms adding randomness of their own add its entropy, and transforms which can map
+    /// different passphrases to the same output (like truncating them) lose some. Generation
+    /// aims for the minimum entropy including this adjustment, and reports entropies with it.
+    fn entropy_adjustment(&self) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Tag;
+
+    impl OutputTransform for Tag {
+        fn transform(&self, passphrase: &str) -> Result<String, Box<dyn Error>> {
+            Ok(format!("{}-site", passphrase))
+        }
+    }
+
+    #[test]
+    fn test_default_entropy_adjustment() {
+        assert_eq!(Tag.transform("abc").unwrap(), "abc-site");
+        assert_eq!(Tag.entropy_adjustment(), 0.0);
+    }
+}
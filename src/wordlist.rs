@@ -0,0 +1,512 @@
+//! Reproducible wordlists of invented words, with manifests for verifying them.
+//!
+//! A wordlist is generated from a chain and a 256-bit seed, so anyone with the same corpus,
+//! options and seed gets exactly the same list. The manifest records a commitment to the seed
+//! and chain, the list's digest and the options used, and `Manifest::verify` checks a rebuilt
+//! list against it:
+//!
+//! ```toml
+//! version = 1
+//! model = "0fdd62e0..."
+//! seed_commitment = "b44bc3ae..."
+//! wordlist_sha256 = "5c1d2e8f..."
+//! size = 7776
+//! min_length = 3
+//! max_length = 9
+//! ngram_length = "3"
+//! ```
+
+use crate::markovchain::PassphraseMarkovChain;
+use crate::{audit, policy, sha256};
+use rand::SeedableRng;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+pub const MANIFEST_VERSION: usize = 1;
+
+/// How many candidate words to try per word in the list before giving up.
+const ATTEMPTS_PER_WORD: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordlistOptions {
+    pub size: usize,
+    /// The fewest characters a word may have.
+    pub min_length: usize,
+    /// The most characters a word may have.
+    pub max_length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordlistError {
+    /// Only this many distinct words could be found.
+    TooFewWords(usize),
+    /// A problem with the manifest, on the given line if it's specific to one.
+    InvalidManifest(Option<usize>, String),
+    ModelMismatch,
+    SeedMismatch,
+    DigestMismatch,
+    /// The wordlist falls short of a quality threshold, described by the message.
+    LowQuality(String),
+}
+
+impl std::error::Error for WordlistError {}
+
+impl fmt::Display for WordlistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooFewWords(count) => write!(
+                f,
+                "Only found {} distinct words. Try a bigger corpus or a wider length range.",
+                count
+            ),
+            Self::InvalidManifest(Some(line), message) => {
+                write!(f, "Invalid manifest on line {}: {}.", line, message)
+            }
+            Self::InvalidManifest(None, message) => write!(f, "Invalid manifest: {}.", message),
+            Self::ModelMismatch => write!(
+                f,
+                "The chain doesn't match the manifest. Check the corpus and the options it lists."
+            ),
+            Self::SeedMismatch => write!(f, "The seed doesn't match the manifest's commitment."),
+            Self::DigestMismatch => write!(f, "The wordlist doesn't match the manifest's digest."),
+            Self::LowQuality(message) => write!(f, "Wordlist quality check failed: {}.", message),
+        }
+    }
+}
+
+/// Generate `options.size` distinct words of the allowed lengths from `seed`, sorted.
+pub fn generate(
+    chain: &PassphraseMarkovChain,
+    options: &WordlistOptions,
+    seed: [u8; 32],
+) -> Result<Vec<String>, WordlistError> {
+    let mut rng = rand_chacha::ChaCha20Rng::from_seed(seed);
+    let mut words = BTreeSet::new();
+    for _ in 0..options.size.saturating_mul(ATTEMPTS_PER_WORD) {
+        if words.len() == options.size {
+            break;
+        }
+        // Passphrases with no minimum entropy stop at the end of their first word.
+        let (word, _) = chain.passphrase_with_rng(0.0, &mut rng);
+        let length = word.chars().count();
+        if (options.min_length..=options.max_length).contains(&length) {
+            words.insert(word);
+        }
+    }
+    if words.len() < options.size {
+        return Err(WordlistError::TooFewWords(words.len()));
+    }
+
+    Ok(words.into_iter().collect())
+}
+
+/// The SHA-256 hex digest of a wordlist as printed, one word per line (as `sha256sum` gives).
+pub fn digest(words: &[String]) -> String {
+    let mut hasher = sha256::Sha256::new();
+    for word in words {
+        hasher.update(word.as_bytes());
+        hasher.update(b"\n");
+    }
+    sha256::to_hex(&hasher.finalize())
+}
+
+/// How easy a wordlist's words are to tell apart and to type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// The smallest edit distance between two words, or `None` with fewer than two words. Words
+    /// one typo apart are easily mistaken for each other.
+    pub min_edit_distance: Option<usize>,
+    /// How many leading characters identify each word, so typing that many is enough for
+    /// autocompletion, or `None` if some word is a prefix of another.
+    pub unique_prefix_length: Option<usize>,
+    /// How many words there are of each length in characters.
+    pub lengths: BTreeMap<usize, usize>,
+}
+
+impl Analysis {
+    /// Check the analysis against `thresholds`, describing the first one missed.
+    pub fn check(&self, thresholds: &QualityThresholds) -> Result<(), WordlistError> {
+        if let Some(minimum) = thresholds.min_edit_distance {
+            if let Some(distance) = self.min_edit_distance.filter(|&d| d < minimum) {
+                return Err(WordlistError::LowQuality(format!(
+                    "some words are only {} edits apart, under the minimum of {}",
+                    distance, minimum
+                )));
+            }
+        }
+        if let Some(maximum) = thresholds.max_unique_prefix_length {
+            match self.unique_prefix_length {
+                None => {
+                    return Err(WordlistError::LowQuality(
+                        "some words are prefixes of others".to_string(),
+                    ))
+                }
+                Some(length) if length > maximum => {
+                    return Err(WordlistError::LowQuality(format!(
+                        "words need their first {} characters to be told apart, over the \
+                         maximum of {}",
+                        length, maximum
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.min_edit_distance {
+            Some(distance) => writeln!(f, "Minimum edit distance: {}", distance)?,
+            None => writeln!(f, "Minimum edit distance: n/a")?,
+        }
+        match self.unique_prefix_length {
+            Some(length) => writeln!(f, "Unique prefix length: {}", length)?,
+            None => writeln!(
+                f,
+                "Unique prefix length: none (some words are prefixes of others)"
+            )?,
+        }
+        writeln!(f, "Word lengths:")?;
+        for (length, count) in &self.lengths {
+            writeln!(f, "  {:>3}: {}", length, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The quality a wordlist must meet to be exported. `None` leaves a measure unchecked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QualityThresholds {
+    pub min_edit_distance: Option<usize>,
+    pub max_unique_prefix_length: Option<usize>,
+}
+
+/// Analyze the distinct `words`.
+///
+/// Finding the minimum edit distance compares every pair of words, but pairs which can't beat the
+/// closest pair found so far are skipped, and distinct words are never closer than one edit.
+pub fn analyze(words: &[String]) -> Analysis {
+    let words: Vec<Vec<char>> = words.iter().map(|word| word.chars().collect()).collect();
+    let mut lengths = BTreeMap::new();
+    for word in &words {
+        *lengths.entry(word.len()).or_insert(0) += 1;
+    }
+
+    let mut min_edit_distance: Option<usize> = None;
+    'pairs: for (i, a) in words.iter().enumerate() {
+        for b in &words[i + 1..] {
+            let bound = min_edit_distance.unwrap_or(usize::MAX);
+            if let Some(distance) = edit_distance_under(a, b, bound) {
+                min_edit_distance = Some(distance);
+                if distance <= 1 {
+                    break 'pairs;
+                }
+            }
+        }
+    }
+
+    let mut sorted: Vec<&Vec<char>> = words.iter().collect();
+    sorted.sort();
+    // The longest common prefix of any two words is between neighbours in sorted order.
+    let unique_prefix_length = sorted.windows(2).try_fold(1, |length: usize, pair| {
+        let common = common_prefix_length(pair[0], pair[1]);
+        match common < pair[0].len().min(pair[1].len()) {
+            true => Some(length.max(common + 1)),
+            false => None,
+        }
+    });
+
+    Analysis {
+        min_edit_distance,
+        unique_prefix_length,
+        lengths,
+    }
+}
+
+fn common_prefix_length(a: &[char], b: &[char]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// The Levenshtein distance between `a` and `b` if it's under `bound`.
+fn edit_distance_under(a: &[char], b: &[char], bound: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) >= bound {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // Distances never drop from one row to the next.
+        if current.iter().min().is_some_and(|&min| min >= bound) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    Some(previous[b.len()]).filter(|&distance| distance < bound)
+}
+
+/// What's needed to check a wordlist was generated as claimed, except the seed itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// The fingerprint of the chain the words were generated from.
+    pub model: String,
+    /// The commitment to the seed and chain, as for `audit::commitment`.
+    pub seed_commitment: String,
+    pub wordlist_sha256: String,
+    pub options: WordlistOptions,
+    /// The corpus and chain options used, for whoever reproduces the list. They're covered by
+    /// `model`, so they're recorded as given rather than checked.
+    pub chain_options: Vec<(String, String)>,
+}
+
+impl Manifest {
+    pub fn new(
+        chain: &PassphraseMarkovChain,
+        options: &WordlistOptions,
+        seed: &[u8; 32],
+        words: &[String],
+        chain_options: Vec<(String, String)>,
+    ) -> Self {
+        let model = chain.fingerprint();
+        Self {
+            seed_commitment: audit::commitment(&model, seed),
+            model,
+            wordlist_sha256: digest(words),
+            options: *options,
+            chain_options,
+        }
+    }
+
+    /// Rebuild the wordlist described by the manifest from `chain` and `seed`, checking both
+    /// and the words against it.
+    pub fn verify(
+        &self,
+        chain: &PassphraseMarkovChain,
+        seed: [u8; 32],
+    ) -> Result<Vec<String>, WordlistError> {
+        let model = chain.fingerprint();
+        if model != self.model {
+            return Err(WordlistError::ModelMismatch);
+        }
+        if audit::commitment(&model, &seed) != self.seed_commitment {
+            return Err(WordlistError::SeedMismatch);
+        }
+        let words = generate(chain, &self.options, seed)?;
+        if digest(&words) != self.wordlist_sha256 {
+            return Err(WordlistError::DigestMismatch);
+        }
+
+        Ok(words)
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quote = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(f, "version = {}", MANIFEST_VERSION)?;
+        writeln!(f, "model = \"{}\"", self.model)?;
+        writeln!(f, "seed_commitment = \"{}\"", self.seed_commitment)?;
+        writeln!(f, "wordlist_sha256 = \"{}\"", self.wordlist_sha256)?;
+        writeln!(f, "size = {}", self.options.size)?;
+        writeln!(f, "min_length = {}", self.options.min_length)?;
+        writeln!(f, "max_length = {}", self.options.max_length)?;
+        for (key, value) in &self.chain_options {
+            writeln!(f, "{} = \"{}\"", key, quote(value))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = WordlistError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut version = None;
+        let mut strings = vec![];
+        let mut integers = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let error = |message: String| WordlistError::InvalidManifest(Some(i + 1), message);
+            let line = policy::strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected 'key = value'".to_string()))?;
+            let (key, value) = (key.trim().to_string(), value.trim());
+            if value.starts_with('"') {
+                strings.push((key, policy::parse_string(value).map_err(error)?));
+            } else {
+                let value: usize = value
+                    .parse()
+                    .map_err(|_| error(format!("invalid value '{}'", value)))?;
+                match key.as_str() {
+                    "version" => version = Some(value),
+                    _ => integers.push((key, value)),
+                }
+            }
+        }
+        let error = |message: String| WordlistError::InvalidManifest(None, message);
+        if version != Some(MANIFEST_VERSION) {
+            return Err(error(format!("expected version {}", MANIFEST_VERSION)));
+        }
+        let mut take_string = |key: &str| {
+            let index = strings.iter().position(|(k, _)| k == key);
+            index
+                .map(|index| strings.remove(index).1)
+                .ok_or_else(|| error(format!("missing '{}'", key)))
+        };
+        let model = take_string("model")?;
+        let seed_commitment = take_string("seed_commitment")?;
+        let wordlist_sha256 = take_string("wordlist_sha256")?;
+        let integer = |key: &str| {
+            integers
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|&(_, value)| value)
+                .ok_or_else(|| error(format!("missing '{}'", key)))
+        };
+
+        Ok(Self {
+            model,
+            seed_commitment,
+            wordlist_sha256,
+            options: WordlistOptions {
+                size: integer("size")?,
+                min_length: integer("min_length")?,
+                max_length: integer("max_length")?,
+            },
+            chain_options: strings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markovchain::{ChainOptions, TransitionCounts};
+
+    fn chain() -> PassphraseMarkovChain {
+        let corpus = " ab ac ad bc bd cd abc abd acd bcd ";
+        let chars: Vec<char> = corpus.chars().collect();
+        let ngrams: Vec<String> = (0..chars.len())
+            .map(|i| (0..3).map(|j| chars[(i + j) % chars.len()]).collect())
+            .collect();
+        let counts = TransitionCounts::from_ngrams(ngrams.iter().map(String::as_str));
+        PassphraseMarkovChain::new(counts, &ChainOptions::default()).unwrap()
+    }
+
+    const OPTIONS: WordlistOptions = WordlistOptions {
+        size: 5,
+        min_length: 2,
+        max_length: 4,
+    };
+
+    #[test]
+    fn test_generate() {
+        let chain = chain();
+        let words = generate(&chain, &OPTIONS, [1; 32]).unwrap();
+        assert_eq!(words.len(), 5);
+        assert!(words.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(generate(&chain, &OPTIONS, [1; 32]).unwrap(), words);
+        let options = WordlistOptions {
+            size: 1000,
+            ..OPTIONS
+        };
+        assert!(matches!(
+            generate(&chain, &options, [1; 32]),
+            Err(WordlistError::TooFewWords(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let chain = chain();
+        let words = generate(&chain, &OPTIONS, [1; 32]).unwrap();
+        let chain_options = vec![("ngram_length".to_string(), "3".to_string())];
+        let manifest = Manifest::new(&chain, &OPTIONS, &[1; 32], &words, chain_options);
+        let parsed: Manifest = manifest.to_string().parse().unwrap();
+        assert_eq!(parsed, manifest);
+        assert_eq!(parsed.verify(&chain, [1; 32]).unwrap(), words);
+        assert_eq!(
+            parsed.verify(&chain, [2; 32]),
+            Err(WordlistError::SeedMismatch)
+        );
+        let tampered = Manifest {
+            wordlist_sha256: digest(&words[1..]),
+            ..manifest
+        };
+        assert_eq!(
+            tampered.verify(&chain, [1; 32]),
+            Err(WordlistError::DigestMismatch)
+        );
+        assert!("version = 2".parse::<Manifest>().is_err());
+    }
+
+    #[test]
+    fn test_edit_distance_under() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        let distance = |a, b, bound| edit_distance_under(&chars(a), &chars(b), bound);
+        assert_eq!(distance("kitten", "sitting", usize::MAX), Some(3));
+        assert_eq!(distance("kitten", "sitting", 3), None);
+        assert_eq!(distance("flaw", "lawn", 5), Some(2));
+        assert_eq!(distance("", "abc", 4), Some(3));
+        assert_eq!(distance("café", "cafe", 2), Some(1));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let analysis = analyze(&words(&["acorn", "badger", "bangle", "zebra"]));
+        assert_eq!(analysis.min_edit_distance, Some(3));
+        assert_eq!(analysis.unique_prefix_length, Some(3));
+        assert_eq!(
+            analysis.lengths.into_iter().collect::<Vec<_>>(),
+            vec![(5, 2), (6, 2)]
+        );
+        let analysis = analyze(&words(&["bag", "bagel", "bog"]));
+        assert_eq!(analysis.min_edit_distance, Some(1));
+        assert_eq!(analysis.unique_prefix_length, None);
+        let analysis = analyze(&words(&["word"]));
+        assert_eq!(analysis.min_edit_distance, None);
+        assert_eq!(analysis.unique_prefix_length, Some(1));
+    }
+
+    #[test]
+    fn test_analysis_check() {
+        let analysis = analyze(&["acorn", "badger", "bangle"].map(String::from));
+        let thresholds = QualityThresholds {
+            min_edit_distance: Some(3),
+            max_unique_prefix_length: Some(3),
+        };
+        assert_eq!(analysis.check(&thresholds), Ok(()));
+        assert!(matches!(
+            analysis.check(&QualityThresholds {
+                min_edit_distance: Some(4),
+                ..thresholds
+            }),
+            Err(WordlistError::LowQuality(_))
+        ));
+        assert!(matches!(
+            analysis.check(&QualityThresholds {
+                max_unique_prefix_length: Some(2),
+                ..thresholds
+            }),
+            Err(WordlistError::LowQuality(_))
+        ));
+        let prefixed = analyze(&["bag", "bagel"].map(String::from));
+        assert!(prefixed.check(&QualityThresholds::default()).is_ok());
+        assert!(prefixed.check(&thresholds).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Checks that generating passphrases with `--no-disk` leaves nothing on disk.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn corpus() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/Jane Austen - Pride and Prejudice.txt")
+}
+
+/// The markovpass binary with its home, data, cache and temporary directories all in `root`.
+fn markovpass(root: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_markovpass"));
+    for var in [
+        "HOME",
+        "XDG_DATA_HOME",
+        "XDG_CACHE_HOME",
+        "XDG_CONFIG_HOME",
+        "TMPDIR",
+    ] {
+        command.env(var, root);
+    }
+    command.env("XDG_DATA_DIRS", root.join("system"));
+
+    command
+}
+
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        match path.is_dir() {
+            true => files.extend(files_under(&path)),
+            false => files.push(path),
+        }
+    }
+
+    files
+}
+
+#[test]
+fn test_no_disk() {
+    let root = std::env::temp_dir().join(format!("markovpass-no-disk-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+
+    let output = markovpass(&root)
+        .args(["--no-disk", "-n", "3", "--show-entropy"])
+        .arg(corpus())
+        .stdout(Stdio::piped())
+        .output()
+        .unwrap();
+    let generated = files_under(&root);
+
+    let seeds = root.join("seeds");
+    let refused = markovpass(&root)
+        .args(["--no-disk", "--audit", "/dev/null", "--audit-seeds"])
+        .arg(&seeds)
+        .arg(corpus())
+        .output()
+        .unwrap();
+    let seeds_written = seeds.exists();
+
+    let redirected = root.join("passphrases.txt");
+    let redirected_status = markovpass(&root)
+        .arg("--no-disk")
+        .arg(corpus())
+        .stdout(std::fs::File::create(&redirected).unwrap())
+        .status()
+        .unwrap();
+    let redirected_contents = std::fs::read_to_string(&redirected).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+    assert_eq!(generated, Vec::<PathBuf>::new());
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--audit-seeds"));
+    assert!(!seeds_written);
+    assert!(!redirected_status.success());
+    assert_eq!(redirected_contents, "");
+}